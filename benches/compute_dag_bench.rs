@@ -1,7 +1,7 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 
 use dagrs::{Dag, DefaultTask, EnvVar, Input, Output, Task};
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 fn calc(input: Input, env: Arc<EnvVar>) -> Output {
     let base = env.get::<usize>("base").unwrap();
@@ -48,6 +48,28 @@ fn compute_dag_bench(bencher: &mut Criterion) {
     bencher.bench_function("compute dag", |b| b.iter(|| compute_dag(tasks.clone())));
 }
 
+/// Independent tasks blocking their thread, as a CPU-heavy or IO-bound synchronous action would.
+fn blocking_dag_bench(bencher: &mut Criterion) {
+    let tasks = (0..32usize)
+        .map(|i_task| {
+            DefaultTask::with_closure(&i_task.to_string(), |_, _| {
+                std::thread::sleep(Duration::from_millis(5));
+                Output::empty()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    bencher.bench_function("blocking dag", |b| {
+        b.iter(|| assert!(Dag::with_tasks(tasks.clone()).start().unwrap()))
+    });
+}
+
+criterion_group!(
+  name = blocking_benches;
+  config = Criterion::default().sample_size(20);
+  targets = blocking_dag_bench
+);
+
 criterion_group!(
   name = benches;
   config = {
@@ -67,4 +89,4 @@ criterion_group!(
   targets = compute_dag_bench
 );
 
-criterion_main!(benches);
+criterion_main!(benches, blocking_benches);
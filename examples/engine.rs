@@ -40,7 +40,7 @@ fn main() {
     t1_d.set_predecessors(&[&t1_b, &t1_c]);
     let dag1 = Dag::with_tasks(vec![t1_a, t1_b, t1_c, t1_d]);
     // Add dag1 to engine.
-    engine.append_dag("graph1", dag1);
+    engine.append_dag("graph1", dag1).unwrap();
 
     // Create some task for dag2.
     let t2_a = DefaultTask::with_closure("Compute A2", |_, _| Output::new(2usize));
@@ -70,11 +70,11 @@ fn main() {
     t2_d.set_predecessors(&[&t2_c]);
     let dag2 = Dag::with_tasks(vec![t2_a, t2_b, t2_c, t2_d]);
     // Add dag2 to engine.
-    engine.append_dag("graph2", dag2);
+    engine.append_dag("graph2", dag2).unwrap();
     // Read tasks from configuration files and resolve to dag3.
     let dag3 = Dag::with_yaml("tests/config/correct.yaml", HashMap::new()).unwrap();
     // Add dag3 to engine.
-    engine.append_dag("graph3", dag3);
+    engine.append_dag("graph3", dag3).unwrap();
     // Execute dag in order, the order should be dag1, dag2, dag3.
     assert_eq!(engine.run_sequential(), vec![true, true, true]);
     // Get the execution results of dag1 and dag2.
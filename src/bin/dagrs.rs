@@ -1,7 +1,10 @@
-use std::{collections::HashMap, fs::File, str::FromStr};
+use std::{collections::HashMap, fs::File, path::Path, str::FromStr, sync::Arc, time::Duration};
 
-use clap::Parser;
-use dagrs::Dag;
+use clap::{Parser, Subcommand};
+use dagrs::{
+    utils::parse_duration, Dag, Engine, FileCacheStore, FileCheckpointStore, MissedRuns, Schedule,
+    SchedulingPolicy,
+};
 
 #[derive(Parser, Debug)]
 #[command(name = "dagrs", version = "0.2.0")]
@@ -15,6 +18,50 @@ struct Args {
     /// Log level, the default is 'info'.
     #[arg(long)]
     log_level: Option<String>,
+    /// Maximum number of tasks executing at the same time, unlimited by default.
+    #[arg(long)]
+    max_parallelism: Option<usize>,
+    /// Resource pool consumed by tasks, given as 'name=capacity'. Can be repeated.
+    #[arg(long, value_parser = parse_resource)]
+    resource: Vec<(String, u32)>,
+    /// Directory where the checkpoint of each finished task is saved.
+    #[arg(long)]
+    checkpoint: Option<String>,
+    /// Resume the execution saved in the checkpoint directory, skipping the succeeded tasks.
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+    /// Execute the task with this name even when it is up to date or cached. Can be repeated.
+    #[arg(long)]
+    force: Vec<String>,
+    /// Directory where the outputs of the tasks with 'cache: true' are cached.
+    #[arg(long)]
+    cache: Option<String>,
+    /// Order of the ready tasks: 'fifo' (the default), 'priority' or 'critical_path'.
+    #[arg(long)]
+    scheduling: Option<SchedulingPolicy>,
+    /// Execute the dag once by default.
+    #[command(subcommand)]
+    mode: Option<Mode>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Mode {
+    /// Execute the dag repeatedly on a schedule, until Ctrl-C.
+    Schedule {
+        /// Cron expression in UTC: 'minute hour day-of-month month day-of-week'.
+        #[arg(long, required_unless_present = "every", conflicts_with = "every")]
+        cron: Option<String>,
+        /// Interval between two executions, such as '30s', '15m', '6h' or '1d'.
+        #[arg(long, value_parser = parse_interval)]
+        every: Option<Duration>,
+        /// Execute all the runs missed while the previous ones were executing, not only the latest.
+        #[arg(long)]
+        catch_up: bool,
+        /// Maximum number of queued runs, including the one executing. The runs are executed one
+        /// after the other.
+        #[arg(long, default_value_t = 1)]
+        max_queued_runs: usize,
+    },
 }
 
 fn main() {
@@ -22,9 +69,101 @@ fn main() {
 
     init_logger(&args);
 
-    let yaml_path = args.yaml;
-    let mut dag = Dag::with_yaml(yaml_path.as_str(), HashMap::new()).unwrap();
-    assert!(dag.start().unwrap());
+    // The dag borrows the path, and lives until the end of the program.
+    let yaml_path: &'static str = Box::leak(args.yaml.into_boxed_str());
+    let mut dag = Dag::with_yaml(yaml_path, HashMap::new()).unwrap();
+    if let Some(max_parallelism) = args.max_parallelism {
+        dag.set_max_parallelism(max_parallelism);
+    }
+    for (name, capacity) in &args.resource {
+        dag.add_resource_pool(name, *capacity);
+    }
+    if let Some(dir) = &args.checkpoint {
+        let store = Arc::new(FileCheckpointStore::new(dir));
+        if args.resume {
+            dag.resume_from(store);
+        } else {
+            dag.set_checkpoint_store(store);
+        }
+    }
+    if let Some(dir) = &args.cache {
+        dag.set_cache_store(Arc::new(FileCacheStore::new(dir)));
+    }
+    for name in &args.force {
+        dag.force_task(name);
+    }
+    if let Some(policy) = args.scheduling {
+        dag.set_scheduling_policy(policy);
+    }
+
+    if let Some(Mode::Schedule {
+        cron,
+        every,
+        catch_up,
+        max_queued_runs,
+    }) = args.mode
+    {
+        let mut schedule = match (cron, every) {
+            (Some(cron), _) => Schedule::cron(&cron).unwrap(),
+            (None, Some(every)) => Schedule::every(every),
+            (None, None) => unreachable!("clap requires --cron or --every"),
+        };
+        if catch_up {
+            schedule.set_missed_runs(MissedRuns::CatchUp);
+        }
+        schedule.set_max_queued_runs(max_queued_runs);
+        run_schedule(yaml_path, dag, schedule);
+        return;
+    }
+
+    // Cancel the dag on Ctrl-C, running commands are killed.
+    let cancellation = dag.cancellation_handle();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            log::warn!("Received Ctrl-C, cancelling the dag.");
+            cancellation.cancel();
+        }
+    });
+    assert!(runtime.block_on(dag.async_start()).unwrap());
+}
+
+/// Execute the dag on its schedule until Ctrl-C, which also cancels the run in progress.
+fn run_schedule(yaml_path: &str, dag: Dag<'static>, schedule: Schedule) {
+    let name = Path::new(yaml_path)
+        .file_stem()
+        .map_or(yaml_path.into(), |stem| stem.to_string_lossy());
+    let cancellation = dag.cancellation_handle();
+    let mut engine = Engine::default();
+    engine.append_dag(&name, dag).unwrap();
+    engine.schedule(&name, schedule).unwrap();
+    let runs = engine.run_scheduler(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            log::warn!("Received Ctrl-C, stopping the scheduler.");
+            cancellation.cancel();
+        }
+    });
+    let executed = runs.iter().filter(|run| run.report.is_some()).count();
+    log::info!(
+        "Executed {} runs of '{}', dropped {}.",
+        executed,
+        name,
+        runs.len() - executed
+    );
+}
+
+/// Parse an interval made of a number and a unit, 'ms', 's', 'm', 'h' or 'd'.
+fn parse_interval(interval: &str) -> Result<Duration, String> {
+    parse_duration(interval)
+        .ok_or("expected a number and a unit, 'ms', 's', 'm', 'h' or 'd'".to_string())
+}
+
+fn parse_resource(resource: &str) -> Result<(String, u32), String> {
+    let (name, capacity) = resource
+        .split_once('=')
+        .ok_or("expected 'name=capacity'".to_string())?;
+    let capacity = capacity.parse().map_err(|e| format!("{}", e))?;
+    Ok((name.to_string(), capacity))
 }
 
 fn init_logger(args: &Args) {
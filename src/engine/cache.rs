@@ -0,0 +1,321 @@
+//! Caching of the outputs of tasks
+//!
+//! # [`CacheStore`]
+//!
+//! By default every task is executed each time its dag runs. A task with a [`CachePolicy`],
+//! see [`Task::cache_policy`](crate::Task::cache_policy), is only executed when its fingerprint
+//! is not found in the [`CacheStore`] of the dag, see [`Dag::set_cache_store`](super::Dag::set_cache_store).
+//! Otherwise, its cached output is given to its successors and it is reported as
+//! [`TaskStatus::Cached`](super::TaskStatus::Cached).
+//!
+//! The fingerprint of a task is computed from:
+//! - its name,
+//! - the cache key provided by its action, see [`Complex::cache_key`](crate::Complex::cache_key).
+//!   The key of a [`CommandAction`](crate::CommandAction) is its command and the contents of its
+//!   declared input files,
+//! - the outputs of its predecessors,
+//! - the environment variables listed by its policy.
+//!
+//! The outputs, inputs and variables must be of types that can be stored, the same as for the
+//! checkpoints, see [`Checkpointable`](super::Checkpointable). A task whose fingerprint cannot be
+//! computed is always executed, and only the outputs that can be stored are cached.
+//!
+//! The cache encodes the values with the registry of types of the checkpoints, which the
+//! serialized contents of the `serde` feature share. It uses their [`Checkpointable`](super::Checkpointable)
+//! bytes rather than a serde format, so that it does not depend on the feature and a value always
+//! hashes to the same fingerprint, even when it was not created by `Content::serializable`.
+//!
+//! [`MemoryCacheStore`] keeps the outputs while the program runs, [`FileCacheStore`] stores one file
+//! per fingerprint in a local directory.
+//!
+//! # Example
+//!
+//! ```rust
+//! use dagrs::{CachePolicy, Dag, DefaultTask, MemoryCacheStore, Output, TaskStatus};
+//! use std::sync::Arc;
+//!
+//! let store = Arc::new(MemoryCacheStore::new());
+//! let mut task = DefaultTask::with_closure("expensive", |_input, _env| Output::new(42usize));
+//! task.set_cache_policy(CachePolicy::new());
+//! let mut dag = Dag::with_tasks(vec![task]).reusable();
+//! dag.set_cache_store(store);
+//!
+//! let report = dag.start_with_report().unwrap();
+//! assert_eq!(report.tasks[0].status, TaskStatus::Succeeded);
+//! dag.reset();
+//! let report = dag.start_with_report().unwrap();
+//! assert_eq!(report.tasks[0].status, TaskStatus::Cached);
+//! assert_eq!(*dag.get_result::<usize>().unwrap(), 42);
+//! ```
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use log::{error, warn};
+use thiserror::Error;
+
+use super::{checkpoint::Codecs, StoredOutput};
+use crate::{EnvVar, Input, Output};
+
+/// Errors raised by a [`CacheStore`].
+#[derive(Debug, Error)]
+pub enum CacheError {
+    /// Reading or writing the cache failed.
+    #[error("Cache io error: {0}")]
+    Io(#[from] std::io::Error),
+    /// The cached output cannot be read back.
+    #[error("Corrupted cache entry [{0}].")]
+    Corrupted(String),
+}
+
+/// Persists the outputs of the tasks, keyed by fingerprint.
+pub trait CacheStore: Send + Sync {
+    /// Load the output cached for a fingerprint, `None` if there is none.
+    fn get(&self, fingerprint: &str) -> Result<Option<StoredOutput>, CacheError>;
+    /// Cache the output of a fingerprint, replacing the previous one.
+    fn put(&self, fingerprint: &str, output: &StoredOutput) -> Result<(), CacheError>;
+    /// Remove all the cached outputs.
+    fn clear(&self) -> Result<(), CacheError>;
+}
+
+/// Declares that the output of a task can be cached, and what its fingerprint depends on in
+/// addition to its name, its action and its inputs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CachePolicy {
+    env: Vec<String>,
+}
+
+impl CachePolicy {
+    /// A policy whose fingerprint does not depend on the environment.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make the fingerprint depend on the environment variable `name`.
+    pub fn with_env(mut self, name: &str) -> Self {
+        self.env.push(name.to_string());
+        self
+    }
+
+    /// The environment variables the fingerprint depends on.
+    pub fn env(&self) -> &[String] {
+        &self.env
+    }
+}
+
+/// Keeps the cached outputs in memory.
+#[derive(Debug, Default)]
+pub struct MemoryCacheStore {
+    entries: Mutex<HashMap<String, StoredOutput>>,
+}
+
+impl MemoryCacheStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of cached outputs.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl CacheStore for MemoryCacheStore {
+    fn get(&self, fingerprint: &str) -> Result<Option<StoredOutput>, CacheError> {
+        Ok(self.entries.lock().unwrap().get(fingerprint).cloned())
+    }
+
+    fn put(&self, fingerprint: &str, output: &StoredOutput) -> Result<(), CacheError> {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(fingerprint.to_string(), output.clone());
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<(), CacheError> {
+        self.entries.lock().unwrap().clear();
+        Ok(())
+    }
+}
+
+/// Stores the cached outputs in a local directory, one file per fingerprint.
+#[derive(Debug, Clone)]
+pub struct FileCacheStore {
+    dir: PathBuf,
+}
+
+const FILE_HEADER: &str = "dagrs-cache";
+const FILE_EXTENSION: &str = "cache";
+
+impl FileCacheStore {
+    /// Store the cached outputs in `dir`, which is created when the first output is cached.
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The fingerprints are hexadecimal, they are valid file names.
+    fn path(&self, fingerprint: &str) -> PathBuf {
+        self.dir.join(fingerprint).with_extension(FILE_EXTENSION)
+    }
+}
+
+impl CacheStore for FileCacheStore {
+    fn get(&self, fingerprint: &str) -> Result<Option<StoredOutput>, CacheError> {
+        let content = match fs::read(self.path(fingerprint)) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let corrupted = || CacheError::Corrupted(fingerprint.to_string());
+        let mut parts = content.splitn(3, |b| *b == b'\n');
+        let mut line = || {
+            parts
+                .next()
+                .and_then(|line| std::str::from_utf8(line).ok())
+                .ok_or_else(corrupted)
+        };
+        if line()? != FILE_HEADER {
+            return Err(corrupted());
+        }
+        let type_tag = line()?.to_string();
+        let data = parts.next().ok_or_else(corrupted)?.to_vec();
+        Ok(Some(match type_tag.as_str() {
+            "-" => StoredOutput::Empty,
+            _ => StoredOutput::Value { type_tag, data },
+        }))
+    }
+
+    fn put(&self, fingerprint: &str, output: &StoredOutput) -> Result<(), CacheError> {
+        let (type_tag, data): (&str, &[u8]) = match output {
+            StoredOutput::Empty => ("-", &[]),
+            StoredOutput::Value { type_tag, data } => (type_tag, data),
+        };
+        let mut content = format!("{}\n{}\n", FILE_HEADER, type_tag).into_bytes();
+        content.extend_from_slice(data);
+
+        fs::create_dir_all(&self.dir)?;
+        // Write then rename, so that an interrupted write does not leave a truncated entry.
+        let path = self.path(fingerprint);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<(), CacheError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == FILE_EXTENSION) {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A 64-bit FNV-1a hash. Unlike the hasher of the standard library, it is stable across
+/// compilers and platforms, so the fingerprints stay valid in a cache on disk.
+pub(crate) struct StableHasher(u64);
+
+impl StableHasher {
+    pub(crate) fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    /// Hash a length-prefixed field, so that consecutive fields cannot be confused.
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    pub(crate) fn finish(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+/// The cache store of a dag, with the types of the outputs it can store, the same as the
+/// checkpoints of the dag.
+#[derive(Clone)]
+pub(crate) struct Cache {
+    pub(crate) store: Arc<dyn CacheStore>,
+    pub(crate) codecs: Codecs,
+}
+
+impl Cache {
+    /// The fingerprint of an execution of a task, `None` if one of its inputs or environment
+    /// variables cannot be stored.
+    pub(crate) fn fingerprint(
+        &self,
+        name: &str,
+        cache_key: Option<&str>,
+        policy: &CachePolicy,
+        input: &Input,
+        env: &EnvVar,
+    ) -> Option<String> {
+        let mut hasher = StableHasher::new();
+        hasher.write(name.as_bytes());
+        hasher.write(cache_key.unwrap_or_default().as_bytes());
+        for (_, name, content) in input.sources() {
+            hasher.write(name.as_bytes());
+            match content {
+                Some(content) => {
+                    let (type_tag, data) = self.codecs.encode_content(content)?;
+                    hasher.write(type_tag.as_bytes());
+                    hasher.write(&data);
+                }
+                None => hasher.write(b"-"),
+            }
+        }
+        for name in policy.env() {
+            hasher.write(name.as_bytes());
+            match env.get_content(name) {
+                Some(content) => {
+                    let (type_tag, data) = self.codecs.encode_content(content)?;
+                    hasher.write(type_tag.as_bytes());
+                    hasher.write(&data);
+                }
+                None => hasher.write(b"-"),
+            }
+        }
+        Some(hasher.finish())
+    }
+
+    /// The output cached for a fingerprint, if it can be decoded.
+    pub(crate) fn get(&self, name: &str, fingerprint: &str) -> Option<Output> {
+        match self.store.get(fingerprint) {
+            Ok(output) => output.and_then(|output| self.codecs.decode(&output)),
+            Err(err) => {
+                warn!("Cannot load the cached output of task[{}]: {}", name, err);
+                None
+            }
+        }
+    }
+
+    /// Cache the output of a task, if it can be stored.
+    pub(crate) fn put(&self, name: &str, fingerprint: &str, output: &Output) {
+        if let Some(output) = self.codecs.encode(output) {
+            if let Err(err) = self.store.put(fingerprint, &output) {
+                error!("Failed to cache the output of task[{}]: {}", name, err);
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Cooperative cancellation of a running Dag
+//!
+//! # [`CancellationHandle`]
+//!
+//! Each [`Dag`](super::Dag) owns a [`CancellationHandle`], which can be cloned and moved to
+//! another thread or task, for example a Ctrl-C handler. Cancelling it aborts the execution
+//! of the dag:
+//! - tasks that have not started yet are terminated,
+//! - running tasks are aborted, and the child processes of `CommandAction` are killed,
+//! - asynchronous actions can observe the cancellation through [`EnvVar::cancellation`](crate::EnvVar::cancellation),
+//!   in order to stop cleanly before being aborted.
+//!
+//! # Example
+//!
+//! ```rust
+//! use dagrs::{Dag, DefaultTask, Output};
+//!
+//! let task = DefaultTask::with_closure("Task", |_input, _env| Output::empty());
+//! let mut dag = Dag::with_tasks(vec![task]);
+//! let handle = dag.cancellation_handle();
+//! handle.cancel();
+//! assert!(!dag.start().unwrap());
+//! ```
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use tokio::sync::Notify;
+
+/// A cloneable handle used to cancel the execution of a Dag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationHandle {
+    inner: Arc<CancellationState>,
+}
+
+#[derive(Debug, Default)]
+struct CancellationState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationHandle {
+    /// Construct a new handle that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request the cancellation. All the clones of this handle observe it.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+
+    /// Whether the cancellation has been requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the cancellation is requested.
+    pub async fn cancelled(&self) {
+        // The future is registered as soon as it is created, so a cancellation
+        // happening between the check and the await is not missed.
+        let notified = self.inner.notify.notified();
+        if self.is_cancelled() {
+            return;
+        }
+        notified.await;
+    }
+}
@@ -0,0 +1,543 @@
+//! Checkpoints of a Dag execution
+//!
+//! # [`CheckpointStore`]
+//!
+//! A checkpoint store persists the final status of each task, and its output when it can be
+//! stored, as soon as the task finishes. When a long execution fails, the dag can be resumed
+//! with [`Dag::resume_from`](super::Dag::resume_from): the tasks that succeeded previously,
+//! and whose predecessors are all restored too, are not executed again, their stored outputs
+//! are given to their successors instead.
+//!
+//! Tasks are identified by their names in the store, so the names of the tasks of a dag using
+//! checkpoints should be unique. [`FileCheckpointStore`] stores one file per task in a local
+//! directory.
+//!
+//! # Storable outputs
+//!
+//! An output can be stored when its type implements [`Checkpointable`]. The strings, numbers,
+//! booleans, `Vec<String>` and the `(stdout, stderr)` lines of a [`CommandAction`](crate::CommandAction)
+//! are supported, and other types can be registered with
+//! [`Dag::register_checkpoint_type`](super::Dag::register_checkpoint_type). A task whose output
+//! cannot be stored is executed again when the dag is resumed.
+//!
+//! With the `serde` feature, the serialized contents are decoded with the same registry of types,
+//! see [`ContentRegistry`](crate::ContentRegistry), and the contents restored from a checkpoint
+//! can be serialized.
+//!
+//! # Example
+//!
+//! ```rust
+//! use dagrs::{Dag, DefaultTask, FileCheckpointStore, Output, TaskStatus};
+//! use std::sync::Arc;
+//!
+//! let dir = std::env::temp_dir().join("dagrs_checkpoint_doc");
+//! let store = Arc::new(FileCheckpointStore::new(&dir));
+//!
+//! let a = DefaultTask::with_closure("a", |_input, _env| Output::new(21usize));
+//! let mut b = DefaultTask::with_closure("b", |_input, _env| Output::error("boom".to_string()));
+//! b.set_predecessors(&[&a]);
+//! let mut dag = Dag::with_tasks(vec![a, b]);
+//! dag.set_checkpoint_store(store.clone());
+//! assert!(!dag.start().unwrap());
+//!
+//! let a = DefaultTask::with_closure("a", |_input, _env| -> Output { unreachable!() });
+//! let mut b = DefaultTask::with_closure("b", |input, _env| {
+//!     let a = input.get_iter().next().unwrap().get::<usize>().unwrap();
+//!     Output::new(a * 2)
+//! });
+//! b.set_predecessors(&[&a]);
+//! let mut dag = Dag::with_tasks(vec![a, b]);
+//! dag.resume_from(store);
+//! let report = dag.start_with_report().unwrap();
+//! assert!(report.is_success());
+//! assert_eq!(report.task_by_name("a").unwrap().status, TaskStatus::Restored);
+//! assert_eq!(*dag.get_result::<usize>().unwrap(), 42);
+//! # std::fs::remove_dir_all(dir).unwrap();
+//! ```
+
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use log::error;
+use thiserror::Error;
+
+use super::{DagObserver, TaskReport, TaskStatus};
+use crate::{task::Content, Output};
+#[cfg(feature = "serde")]
+use crate::{
+    task::{deserialize, DeserializeFn},
+    Serializable,
+};
+
+/// Errors raised by a [`CheckpointStore`].
+#[derive(Debug, Error)]
+pub enum CheckpointError {
+    /// Reading or writing the checkpoint failed.
+    #[error("Checkpoint io error: {0}")]
+    Io(#[from] std::io::Error),
+    /// The stored checkpoint cannot be read back.
+    #[error("Corrupted checkpoint of task[{0}].")]
+    Corrupted(String),
+}
+
+/// The output of a task, as stored in a checkpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoredOutput {
+    /// The task produced no content.
+    Empty,
+    /// The content of the output, encoded by the [`Checkpointable`] type identified by `type_tag`.
+    Value { type_tag: String, data: Vec<u8> },
+}
+
+/// What is stored about a task once it finished.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskCheckpoint {
+    pub status: TaskStatus,
+    /// The output of a succeeded task, `None` if it cannot be stored.
+    pub output: Option<StoredOutput>,
+}
+
+/// Persists the checkpoints of the tasks of a dag, keyed by task name.
+pub trait CheckpointStore: Send + Sync {
+    /// Store the checkpoint of a task, replacing the previous one.
+    fn save(&self, task: &str, checkpoint: &TaskCheckpoint) -> Result<(), CheckpointError>;
+    /// Load the checkpoint of a task, `None` if there is none.
+    fn load(&self, task: &str) -> Result<Option<TaskCheckpoint>, CheckpointError>;
+    /// Remove all the checkpoints.
+    fn clear(&self) -> Result<(), CheckpointError>;
+}
+
+/// A type whose values can be stored in a checkpoint. With the `serde` feature, a type which is
+/// also [`Serializable`](crate::Serializable) shares its tag with the serialized contents.
+pub trait Checkpointable: Sized + Send + Sync + 'static {
+    /// The name identifying the type in the checkpoints, it must be unique among the stored types.
+    const TYPE_TAG: &'static str;
+    /// Encode the value.
+    fn to_bytes(&self) -> Vec<u8>;
+    /// Decode a value encoded by `to_bytes`.
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+/// Stores the checkpoints in a local directory, one file per task.
+#[derive(Debug, Clone)]
+pub struct FileCheckpointStore {
+    dir: PathBuf,
+}
+
+const FILE_HEADER: &str = "dagrs-checkpoint";
+const FILE_EXTENSION: &str = "ckpt";
+
+impl FileCheckpointStore {
+    /// Store the checkpoints in `dir`, which is created when the first checkpoint is saved.
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The file of a task, any character of its name that may not be valid in a file name is escaped.
+    fn path(&self, task: &str) -> PathBuf {
+        let mut file = String::with_capacity(task.len());
+        for byte in task.bytes() {
+            if byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-' {
+                file.push(byte as char);
+            } else {
+                file.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        self.dir.join(file).with_extension(FILE_EXTENSION)
+    }
+}
+
+impl CheckpointStore for FileCheckpointStore {
+    fn save(&self, task: &str, checkpoint: &TaskCheckpoint) -> Result<(), CheckpointError> {
+        let (type_tag, data): (&str, &[u8]) = match &checkpoint.output {
+            None => ("", &[]),
+            Some(StoredOutput::Empty) => ("-", &[]),
+            Some(StoredOutput::Value { type_tag, data }) => (type_tag, data),
+        };
+        let mut content = format!(
+            "{}\n{}\n{}\n",
+            FILE_HEADER,
+            status_name(checkpoint.status),
+            type_tag
+        )
+        .into_bytes();
+        content.extend_from_slice(data);
+
+        fs::create_dir_all(&self.dir)?;
+        // Write then rename, so that an interrupted write does not leave a truncated checkpoint.
+        let path = self.path(task);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    fn load(&self, task: &str) -> Result<Option<TaskCheckpoint>, CheckpointError> {
+        let content = match fs::read(self.path(task)) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let corrupted = || CheckpointError::Corrupted(task.to_string());
+        let mut parts = content.splitn(4, |b| *b == b'\n');
+        let mut line = || {
+            parts
+                .next()
+                .and_then(|line| std::str::from_utf8(line).ok())
+                .ok_or_else(corrupted)
+        };
+        if line()? != FILE_HEADER {
+            return Err(corrupted());
+        }
+        let status = parse_status(line()?).ok_or_else(corrupted)?;
+        let type_tag = line()?.to_string();
+        let data = parts.next().ok_or_else(corrupted)?.to_vec();
+        let output = match type_tag.as_str() {
+            "" => None,
+            "-" => Some(StoredOutput::Empty),
+            _ => Some(StoredOutput::Value { type_tag, data }),
+        };
+        Ok(Some(TaskCheckpoint { status, output }))
+    }
+
+    fn clear(&self) -> Result<(), CheckpointError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == FILE_EXTENSION) {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn status_name(status: TaskStatus) -> &'static str {
+    match status {
+        TaskStatus::Pending => "pending",
+        TaskStatus::Succeeded => "succeeded",
+        TaskStatus::Restored => "restored",
+        TaskStatus::Cached => "cached",
+        TaskStatus::UpToDate => "up_to_date",
+        TaskStatus::Failed => "failed",
+        TaskStatus::Skipped => "skipped",
+        TaskStatus::Bypassed => "bypassed",
+        TaskStatus::Terminated => "terminated",
+        TaskStatus::TimedOut => "timed_out",
+        TaskStatus::Panicked => "panicked",
+    }
+}
+
+fn parse_status(name: &str) -> Option<TaskStatus> {
+    Some(match name {
+        "pending" => TaskStatus::Pending,
+        "succeeded" => TaskStatus::Succeeded,
+        "restored" => TaskStatus::Restored,
+        "cached" => TaskStatus::Cached,
+        "up_to_date" => TaskStatus::UpToDate,
+        "failed" => TaskStatus::Failed,
+        "skipped" => TaskStatus::Skipped,
+        "bypassed" => TaskStatus::Bypassed,
+        "terminated" => TaskStatus::Terminated,
+        "timed_out" => TaskStatus::TimedOut,
+        "panicked" => TaskStatus::Panicked,
+        _ => return None,
+    })
+}
+
+/// Converts the contents of the outputs of one type from and to their stored form.
+#[derive(Clone, Copy)]
+struct Codec {
+    type_tag: &'static str,
+    encode: fn(&Content) -> Option<Vec<u8>>,
+    decode: fn(&[u8]) -> Option<Content>,
+    /// Decodes the contents serialized with serde, only the [`Serializable`] types have one.
+    #[cfg(feature = "serde")]
+    deserialize: Option<DeserializeFn>,
+}
+
+fn encode<T: Checkpointable>(content: &Content) -> Option<Vec<u8>> {
+    content.get::<T>().map(T::to_bytes)
+}
+
+fn decode<T: Checkpointable>(bytes: &[u8]) -> Option<Content> {
+    T::from_bytes(bytes).map(Content::new)
+}
+
+#[cfg(feature = "serde")]
+fn decode_serializable<T: Serializable>(bytes: &[u8]) -> Option<Content> {
+    T::from_bytes(bytes).map(Content::serializable)
+}
+
+/// The tag of a content wrapping a content of type `type_tag`.
+pub(crate) fn wrapping_tag(type_tag: &str) -> String {
+    format!("Content<{}>", type_tag)
+}
+
+/// The tag of the content wrapped by a content of type `type_tag`, if it wraps one.
+pub(crate) fn wrapped_tag(type_tag: &str) -> Option<&str> {
+    type_tag
+        .strip_prefix("Content<")
+        .and_then(|tag| tag.strip_suffix('>'))
+}
+
+/// The types whose values can be stored in the checkpoints and the cache of a dag. It is also
+/// the registry of the serialized contents, see [`ContentRegistry`](crate::ContentRegistry).
+#[derive(Clone)]
+pub(crate) struct Codecs(Vec<Codec>);
+
+impl Codecs {
+    pub(crate) fn empty() -> Self {
+        Self(Vec::new())
+    }
+
+    pub(crate) fn register<T: Checkpointable>(&mut self) {
+        self.insert(Codec {
+            type_tag: T::TYPE_TAG,
+            encode: encode::<T>,
+            decode: decode::<T>,
+            #[cfg(feature = "serde")]
+            deserialize: None,
+        });
+    }
+
+    /// Register a type which can also be decoded from its serialized form, the decoded contents
+    /// are serializable again.
+    #[cfg(feature = "serde")]
+    pub(crate) fn register_serializable<T: Serializable>(&mut self) {
+        self.insert(Codec {
+            type_tag: T::TYPE_TAG,
+            encode: encode::<T>,
+            decode: decode_serializable::<T>,
+            deserialize: Some(deserialize::<T>),
+        });
+    }
+
+    fn insert(&mut self, codec: Codec) {
+        self.0.retain(|other| other.type_tag != codec.type_tag);
+        self.0.push(codec);
+    }
+
+    fn find(&self, type_tag: &str) -> Option<&Codec> {
+        self.0.iter().find(|codec| codec.type_tag == type_tag)
+    }
+
+    /// The serde decoder of the contents tagged `type_tag`, which must not wrap another content.
+    #[cfg(feature = "serde")]
+    pub(crate) fn deserializer(&self, type_tag: &str) -> Option<DeserializeFn> {
+        self.find(type_tag).and_then(|codec| codec.deserialize)
+    }
+
+    /// The stored form of an output, `None` if the type of its content is not registered.
+    pub(crate) fn encode(&self, output: &Output) -> Option<StoredOutput> {
+        match output {
+            Output::Out(None) => Some(StoredOutput::Empty),
+            Output::Out(Some(content)) => self
+                .encode_content(content)
+                .map(|(type_tag, data)| StoredOutput::Value { type_tag, data }),
+            _ => None,
+        }
+    }
+
+    /// Restore a stored output, `None` if its type is not registered or it cannot be decoded.
+    pub(crate) fn decode(&self, output: &StoredOutput) -> Option<Output> {
+        match output {
+            StoredOutput::Empty => Some(Output::empty()),
+            StoredOutput::Value { type_tag, data } => self
+                .decode_content(type_tag, data)
+                .map(|content| Output::Out(Some(content))),
+        }
+    }
+
+    /// A content may wrap another content, like the output of a [`CommandAction`](crate::CommandAction),
+    /// the tag of the inner type is then wrapped in `Content<...>`.
+    pub(crate) fn encode_content(&self, content: &Content) -> Option<(String, Vec<u8>)> {
+        if let Some(inner) = content.get::<Content>() {
+            return self
+                .encode_content(inner)
+                .map(|(type_tag, data)| (wrapping_tag(&type_tag), data));
+        }
+        self.0.iter().find_map(|codec| {
+            (codec.encode)(content).map(|data| (codec.type_tag.to_string(), data))
+        })
+    }
+
+    fn decode_content(&self, type_tag: &str, data: &[u8]) -> Option<Content> {
+        if let Some(inner) = wrapped_tag(type_tag) {
+            return self.decode_content(inner, data).map(Content::new);
+        }
+        self.find(type_tag).and_then(|codec| (codec.decode)(data))
+    }
+}
+
+/// The primitive types, `Vec<String>` and the `(stdout, stderr)` lines of a command. They are
+/// also [`Serializable`] with the `serde` feature.
+impl Default for Codecs {
+    fn default() -> Self {
+        let mut codecs = Self::empty();
+        macro_rules! register {
+            ($($ty:ty),*) => {
+                $(
+                    #[cfg(not(feature = "serde"))]
+                    codecs.register::<$ty>();
+                    #[cfg(feature = "serde")]
+                    codecs.register_serializable::<$ty>();
+                )*
+            };
+        }
+        register!(
+            String,
+            bool,
+            i8,
+            i16,
+            i32,
+            i64,
+            isize,
+            u8,
+            u16,
+            u32,
+            u64,
+            usize,
+            f32,
+            f64,
+            Vec<String>,
+            (Vec<String>, Vec<String>)
+        );
+        codecs
+    }
+}
+
+macro_rules! impl_checkpointable_from_str {
+    ($($ty:ty),*) => {
+        $(
+            impl Checkpointable for $ty {
+                const TYPE_TAG: &'static str = stringify!($ty);
+
+                fn to_bytes(&self) -> Vec<u8> {
+                    self.to_string().into_bytes()
+                }
+
+                fn from_bytes(bytes: &[u8]) -> Option<Self> {
+                    std::str::from_utf8(bytes).ok()?.parse().ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_checkpointable_from_str!(
+    String, bool, i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64
+);
+
+/// Each string is prefixed by its length.
+impl Checkpointable for Vec<String> {
+    const TYPE_TAG: &'static str = "Vec<String>";
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for line in self {
+            bytes.extend_from_slice(format!("{}:", line.len()).as_bytes());
+            bytes.extend_from_slice(line.as_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let mut lines = Vec::new();
+        while !bytes.is_empty() {
+            let colon = bytes.iter().position(|b| *b == b':')?;
+            let len: usize = std::str::from_utf8(&bytes[..colon]).ok()?.parse().ok()?;
+            let line = bytes.get(colon + 1..colon + 1 + len)?;
+            lines.push(String::from_utf8(line.to_vec()).ok()?);
+            bytes = &bytes[colon + 1 + len..];
+        }
+        Some(lines)
+    }
+}
+
+/// The `(stdout, stderr)` lines of a command, the stdout part is prefixed by its length.
+impl Checkpointable for (Vec<String>, Vec<String>) {
+    const TYPE_TAG: &'static str = "(Vec<String>, Vec<String>)";
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let stdout = self.0.to_bytes();
+        let mut bytes = format!("{}:", stdout.len()).into_bytes();
+        bytes.extend(stdout);
+        bytes.extend(self.1.to_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let colon = bytes.iter().position(|b| *b == b':')?;
+        let len: usize = std::str::from_utf8(&bytes[..colon]).ok()?.parse().ok()?;
+        let stdout = bytes.get(colon + 1..colon + 1 + len)?;
+        Some((
+            Vec::from_bytes(stdout)?,
+            Vec::from_bytes(&bytes[colon + 1 + len..])?,
+        ))
+    }
+}
+
+/// Saves the checkpoint of each task once it finished.
+pub(crate) struct CheckpointObserver {
+    pub(crate) store: Arc<dyn CheckpointStore>,
+    pub(crate) codecs: Codecs,
+    /// Remove the checkpoints of the previous execution when the dag starts.
+    pub(crate) clear_on_start: bool,
+}
+
+impl CheckpointObserver {
+    fn save(&self, task: &str, checkpoint: TaskCheckpoint) {
+        if let Err(err) = self.store.save(task, &checkpoint) {
+            error!("Failed to save the checkpoint of task[{}]: {}", task, err);
+        }
+    }
+}
+
+impl DagObserver for CheckpointObserver {
+    fn on_dag_start(&self, _tasks: &[(usize, &str)]) {
+        if self.clear_on_start {
+            if let Err(err) = self.store.clear() {
+                error!("Failed to clear the checkpoints: {}", err);
+            }
+        }
+    }
+
+    fn on_task_success(&self, report: &TaskReport, output: &Output) {
+        // The checkpoint of a restored task is already stored.
+        if report.status != TaskStatus::Restored {
+            let output = self.codecs.encode(output);
+            self.save(
+                &report.name,
+                TaskCheckpoint {
+                    status: report.status,
+                    output,
+                },
+            );
+        }
+    }
+
+    fn on_task_failure(&self, report: &TaskReport) {
+        self.save(
+            &report.name,
+            TaskCheckpoint {
+                status: report.status,
+                output: None,
+            },
+        );
+    }
+
+    fn on_task_skipped(&self, report: &TaskReport) {
+        self.on_task_failure(report);
+    }
+}
@@ -1,20 +1,35 @@
-use super::{graph::Graph, DagError};
+use super::{
+    cache::Cache,
+    checkpoint::{CheckpointObserver, Codecs},
+    executor::InlineExecutor,
+    graph::Graph,
+    observer::{LoggingObserver, SenderObserver},
+    schedule::{acquire_permits, Rank, ReadyQueue},
+    CachePolicy, CacheStore, CancellationHandle, CheckpointStore, Checkpointable, DagError,
+    DagObserver, DagOutcome, DagReport, Executor, SchedulingPolicy, TaskReport, TaskStatus,
+    TokioExecutor,
+};
 use crate::{
-    task::{ExecState, Input, Output, Task},
+    task::{Content, ExecState, Expansion, Input, Output, SubDagOutput, Task},
     utils::EnvVar,
-    Action, Parser,
+    Action, Parser, RetryPolicy,
 };
-use log::{debug, error};
+use log::warn;
 use std::{
-    collections::HashMap,
-    panic::{self, AssertUnwindSafe},
+    any::Any,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    future::Future,
+    pin::Pin,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
+        Arc, Mutex,
     },
+    time::{Duration, SystemTime},
 };
-use tokio::sync::mpsc::UnboundedSender;
-use tokio::task::JoinHandle;
+use tokio::sync::{mpsc::UnboundedSender, OwnedSemaphorePermit, Semaphore};
+use tokio::task::{JoinError, JoinHandle};
+use tokio::time::Instant;
 
 /// [`Dag`] is dagrs's main body.
 ///
@@ -25,15 +40,15 @@ use tokio::task::JoinHandle;
 ///
 /// The execution process of Dag is roughly as follows:
 /// - The user gives a list of tasks `tasks`. These tasks can be parsed from configuration files, or provided
-/// by user programming implementations.
+///   by user programming implementations.
 /// - Internally generate `Graph` based on task dependencies, and generate execution sequences based on `rely_graph`.
 /// - The task is scheduled to start executing asynchronously.
 /// - The task will wait to get the result `execute_states` generated by the execution of the predecessor task.
 /// - If the result of the predecessor task can be obtained, check the continuation status `can_continue`, if it
-/// is true, continue to execute the defined logic, if it is false, trigger `handle_error`, and cancel the
-/// execution of the subsequent task.
+///   is true, continue to execute the defined logic, if it is false, trigger `handle_error`, and cancel the
+///   execution of the subsequent task.
 /// - After all tasks are executed, set the continuation status to false, which means that the tasks of the dag
-/// cannot be scheduled for execution again.
+///   cannot be scheduled for execution again, unless the dag is reset, see [`Dag::reset`] and [`Dag::reusable`].
 ///
 ///  # Example
 /// ```rust
@@ -48,7 +63,7 @@ use tokio::task::JoinHandle;
 ///
 /// ```
 // #[derive(Debug)]
-pub struct Dag <'a> {
+pub struct Dag<'a> {
     /// Store all tasks' infos.
     ///
     /// Arc but no mutex, because only one thread will change [`TaskWrapper`]at a time.
@@ -64,7 +79,7 @@ pub struct Dag <'a> {
     /// When an error occurs during the execution of any task, this flag will be set to false, and
     /// subsequent tasks will be canceled.
     /// when all tasks in the dag are executed, the flag will also be set to false, indicating that
-    /// the task cannot be run repeatedly.
+    /// the task cannot be run repeatedly until the dag is reset.
     can_continue: Arc<AtomicBool>,
     /// A flag that indicates whether the task should continue to execute as much as possible.
     keep_going: bool,
@@ -72,11 +87,66 @@ pub struct Dag <'a> {
     keep_going_errored: Arc<AtomicBool>,
     /// The execution sequence of tasks.
     exe_sequence: Vec<usize>,
-    /// MPSC channel for sending back task outputs
-    tx: Option<UnboundedSender<OutputMessage>>,
+    /// Notified of the lifecycle events of the execution, see [`DagObserver`].
+    observers: Vec<Arc<dyn DagObserver>>,
+    /// The maximum execution time of the whole dag.
+    timeout: Option<Duration>,
+    /// Cancels the execution of the dag. It is shared with `env`.
+    cancellation: CancellationHandle,
+    /// Limits the number of tasks of this dag executing at the same time.
+    max_parallelism: Option<Arc<Semaphore>>,
+    /// Limits the number of tasks executing at the same time, shared with other dags of an [`Engine`](crate::Engine).
+    shared_parallelism: Option<Arc<Semaphore>>,
+    /// Named resource pools with their capacities, from which tasks acquire the amounts they
+    /// declare before executing.
+    resource_pools: HashMap<String, (Arc<Semaphore>, u32)>,
+    /// Named resource pools shared with other dags of an [`Engine`](crate::Engine).
+    shared_resource_pools: HashMap<String, (Arc<Semaphore>, u32)>,
+    /// Whether the graph has been built and sorted, this is only done once.
+    initialized: bool,
+    /// Whether the dag has been executed since it was created or reset.
+    executed: bool,
+    /// A reusable dag is reset before each execution, see [`Dag::reusable`].
+    reusable: bool,
+    /// Where the checkpoints of the tasks are saved, see [`CheckpointStore`].
+    checkpoint_store: Option<Arc<dyn CheckpointStore>>,
+    /// Restore the tasks that succeeded in the previous execution from the checkpoints.
+    resume: bool,
+    /// The types of outputs that can be stored in the checkpoints and the cache.
+    checkpoint_codecs: Codecs,
+    /// Where the outputs of the cached tasks are stored, see [`CacheStore`].
+    cache_store: Option<Arc<dyn CacheStore>>,
+    /// The names of the tasks executed even when they are up to date or cached.
+    forced: HashSet<String>,
+    /// Decides which of the ready tasks is started first, see [`SchedulingPolicy`].
+    scheduling_policy: SchedulingPolicy,
+    /// The durations of the tasks measured during the previous executions, by task name.
+    durations: HashMap<String, Duration>,
+    /// Runs the actions of the tasks without their own executor, see [`Executor`].
+    executor: Option<Arc<dyn Executor>>,
+    /// Execute the dag deterministically, the seed shuffles the tasks ready at the same time.
+    seed: Option<u64>,
+    /// The tasks expanded at runtime during the last execution, see [`Output::expand`].
+    expanded: Arc<Mutex<Vec<ExpandedTask>>>,
+    /// The input of the tasks without predecessors, see [`SubDagTask`](crate::SubDagTask).
+    input: Input,
+    /// Describes the dag to the users of an [`Engine`](crate::Engine).
+    metadata: DagMetadata,
+}
+
+/// Information about a dag, for the users managing the dags of an [`Engine`](crate::Engine).
+/// It does not affect the execution.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DagMetadata {
+    /// What the dag does.
+    pub description: Option<String>,
+    /// Labels to group or filter the dags.
+    pub tags: Vec<String>,
+    /// Who is responsible for the dag.
+    pub owner: Option<String>,
 }
 
-/// message sent back at each task execution
+/// message sent back at each successful task execution, and when the dag finishes
 #[derive(Debug)]
 pub enum OutputMessage {
     Finish,
@@ -93,38 +163,56 @@ pub struct OutputMessageContent {
 enum ExecResult {
     Success,
     Failure,
+    /// The task did not finish in time, it is handled as a failure.
+    Timeout,
     Termination,
 }
 
-impl <'a> Dag <'a> {
+impl<'a> Dag<'a> {
     /// Create a dag. This function is not open to the public. There are three ways to create a new
     /// dag, corresponding to three functions: `with_tasks`, `with_yaml`, `with_config_file_and_parser`.
     fn new() -> Dag<'a> {
+        let cancellation = CancellationHandle::new();
+        let mut env = EnvVar::new();
+        env.set_cancellation(cancellation.clone());
         Dag {
             tasks: HashMap::new(),
             rely_graph: Graph::new(),
             execute_states: HashMap::new(),
-            env: Arc::new(EnvVar::new()),
+            env: Arc::new(env),
             can_continue: Arc::new(AtomicBool::new(true)),
             exe_sequence: Vec::new(),
             keep_going: false,
             keep_going_errored: Arc::new(AtomicBool::new(false)),
-            tx: None,
+            observers: vec![Arc::new(LoggingObserver)],
+            timeout: None,
+            cancellation,
+            max_parallelism: None,
+            shared_parallelism: None,
+            resource_pools: HashMap::new(),
+            shared_resource_pools: HashMap::new(),
+            initialized: false,
+            executed: false,
+            reusable: false,
+            checkpoint_store: None,
+            resume: false,
+            checkpoint_codecs: Codecs::default(),
+            cache_store: None,
+            forced: HashSet::new(),
+            scheduling_policy: SchedulingPolicy::default(),
+            durations: HashMap::new(),
+            executor: None,
+            seed: None,
+            expanded: Arc::default(),
+            input: Input::new(Vec::new()),
+            metadata: DagMetadata::default(),
         }
     }
 
     fn new_with_sender(tx: UnboundedSender<OutputMessage>) -> Dag<'a> {
-        Dag {
-            tasks: HashMap::new(),
-            rely_graph: Graph::new(),
-            execute_states: HashMap::new(),
-            env: Arc::new(EnvVar::new()),
-            can_continue: Arc::new(AtomicBool::new(true)),
-            exe_sequence: Vec::new(),
-            keep_going: false,
-            keep_going_errored: Arc::new(AtomicBool::new(false)),
-            tx: Some(tx),
-        }
+        let mut dag = Dag::new();
+        dag.add_observer(Arc::new(SenderObserver(tx)));
+        dag
     }
 
     /// Create a dag by adding a series of tasks.
@@ -167,7 +255,7 @@ impl <'a> Dag <'a> {
     pub fn with_yaml(
         file: &str,
         specific_actions: HashMap<String, Action>,
-    ) -> Result<Dag, DagError> {
+    ) -> Result<Dag<'_>, DagError> {
         use crate::YamlParser;
         let parser = Box::new(YamlParser);
         Dag::read_tasks(file, parser, specific_actions)
@@ -178,7 +266,7 @@ impl <'a> Dag <'a> {
     pub fn with_yaml_str(
         content: &str,
         specific_actions: HashMap<String, Action>,
-    ) -> Result<Dag, DagError> {
+    ) -> Result<Dag<'_>, DagError> {
         use crate::YamlParser;
         let parser = Box::new(YamlParser);
         Dag::read_tasks_from_str(content, parser, specific_actions)
@@ -189,7 +277,7 @@ impl <'a> Dag <'a> {
         file: &str,
         parser: Box<dyn Parser>,
         specific_actions: HashMap<String, Action>,
-    ) -> Result<Dag, DagError> {
+    ) -> Result<Dag<'_>, DagError> {
         Dag::read_tasks(file, parser, specific_actions)
     }
 
@@ -198,7 +286,7 @@ impl <'a> Dag <'a> {
         content: &str,
         parser: Box<dyn Parser>,
         specific_actions: HashMap<String, Action>,
-    ) -> Result<Dag, DagError> {
+    ) -> Result<Dag<'_>, DagError> {
         Dag::read_tasks_from_str(content, parser, specific_actions)
     }
 
@@ -210,13 +298,284 @@ impl <'a> Dag <'a> {
         self
     }
 
-    /// Parse the content of the configuration file into a series of tasks and generate a dag.
+    /// Make the dag executable repeatedly: each call to `start` resets the dag first, see [`Dag::reset`].
+    /// The graph is built and checked by the first execution only.
+    ///
+    /// # Example
+    /// ```rust
+    /// use dagrs::{Dag, DefaultTask, EnvVar, Output};
+    /// let task = DefaultTask::with_closure("Task", |_input, env| {
+    ///     Output::new(env.get::<usize>("base").unwrap() * 2)
+    /// });
+    /// let mut dag = Dag::with_tasks(vec![task]).reusable();
+    /// for base in 0..3usize {
+    ///     let mut env = EnvVar::new();
+    ///     env.set("base", base);
+    ///     dag.set_env(env);
+    ///     assert!(dag.start().unwrap());
+    ///     assert_eq!(*dag.get_result::<usize>().unwrap(), base * 2);
+    /// }
+    /// ```
+    pub fn reusable(mut self) -> Dag<'a> {
+        self.reusable = true;
+        self
+    }
+
+    /// Bring the dag back to its state before the execution, so that it can be started again.
+    ///
+    /// The outputs and execution records of the tasks are cleared. If the dag was cancelled
+    /// during its last execution, it gets a new [`CancellationHandle`], which should be obtained
+    /// again with [`Dag::cancellation_handle`].
+    pub fn reset(&mut self) {
+        self.execute_states = self
+            .tasks
+            .iter()
+            .map(|(id, task)| (*id, Arc::new(ExecState::new(*id, task.name()))))
+            .collect();
+        self.expanded = Arc::default();
+        self.can_continue.store(true, Ordering::Release);
+        self.keep_going_errored.store(false, Ordering::Release);
+        if self.executed && self.cancellation.is_cancelled() {
+            self.cancellation = CancellationHandle::new();
+            self.set_env(self.env.as_ref().clone());
+        }
+        self.executed = false;
+    }
+
+    /// Bound the execution time of the whole dag.
+    ///
+    /// When the timeout is reached, the running tasks are aborted and reported as timed out,
+    /// which is handled like any other task failure. The per-task timeouts still apply.
+    /// Note that an aborted synchronous action keeps its thread busy until it returns,
+    /// its output is discarded.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    /// Limit the number of tasks executing at the same time to `max_parallelism`, at least 1.
+    ///
+    /// # Example
+    /// ```rust
+    /// use dagrs::{Dag, DefaultTask, Output};
+    /// let tasks = (0..10).map(|i| DefaultTask::with_closure(&i.to_string(), |_, _| Output::empty()));
+    /// let mut dag = Dag::with_tasks(tasks.collect());
+    /// dag.set_max_parallelism(2);
+    /// assert!(dag.start().unwrap());
+    /// ```
+    pub fn set_max_parallelism(&mut self, max_parallelism: usize) {
+        self.max_parallelism = Some(Arc::new(Semaphore::new(max_parallelism.max(1))));
+    }
+
+    /// Add a named resource pool of the given capacity. A task declaring that it consumes some amount
+    /// of this resource, see [`Task::resources`], waits until the amount is available before executing,
+    /// and gives it back after.
+    ///
+    /// # Example
+    /// ```rust
+    /// use dagrs::{Dag, DefaultTask, Output};
+    /// let mut train = DefaultTask::with_closure("train", |_, _| Output::empty());
+    /// train.add_resource("gpu-slot", 1);
+    /// let mut dag = Dag::with_tasks(vec![train]);
+    /// dag.add_resource_pool("gpu-slot", 1);
+    /// assert!(dag.start().unwrap());
+    /// ```
+    pub fn add_resource_pool(&mut self, name: &str, capacity: u32) {
+        self.resource_pools.insert(
+            name.to_string(),
+            (Arc::new(Semaphore::new(capacity as usize)), capacity),
+        );
+    }
+
+    /// Share the limits of an [`Engine`](crate::Engine) with this dag. The resource pools of the
+    /// dag take precedence over the ones of the engine with the same name.
+    pub(crate) fn set_shared_limits(
+        &mut self,
+        parallelism: Option<Arc<Semaphore>>,
+        resource_pools: &HashMap<String, (Arc<Semaphore>, u32)>,
+    ) {
+        self.shared_parallelism = parallelism;
+        self.shared_resource_pools = resource_pools.clone();
+    }
+
+    /// The resource pools available to the tasks, the ones of the dag and the ones of its engine.
+    fn all_resource_pools(&self) -> HashMap<String, (Arc<Semaphore>, u32)> {
+        let mut resource_pools = self.shared_resource_pools.clone();
+        resource_pools.extend(self.resource_pools.clone());
+        resource_pools
+    }
+
+    /// Get a handle that cancels the execution of the dag, see [`CancellationHandle`].
+    ///
+    /// The handle should be obtained before the dag starts, since starting it borrows the dag.
+    pub fn cancellation_handle(&self) -> CancellationHandle {
+        self.cancellation.clone()
+    }
+
+    /// Save the checkpoint of each task in `store` once it finished, so that a failed execution can
+    /// be resumed later, see [`Dag::resume_from`]. The checkpoints of the previous execution are
+    /// removed when the dag starts.
+    pub fn set_checkpoint_store(&mut self, store: Arc<dyn CheckpointStore>) {
+        self.checkpoint_store = Some(store);
+        self.resume = false;
+    }
+
+    /// Resume the execution saved in `store`: a task that succeeded previously is not executed
+    /// again if its output could be stored and all its predecessors are restored too. Its stored
+    /// output is given to its successors instead. The checkpoints of the resumed execution are
+    /// saved in `store` too.
+    pub fn resume_from(&mut self, store: Arc<dyn CheckpointStore>) {
+        self.checkpoint_store = Some(store);
+        self.resume = true;
+    }
+
+    /// Allow the outputs of type `T` to be stored in the checkpoints and the cache, see [`Checkpointable`].
+    pub fn register_checkpoint_type<T: Checkpointable>(&mut self) {
+        self.checkpoint_codecs.register::<T>();
+    }
+
+    /// Cache the outputs of the tasks with a [`CachePolicy`] in `store`. Such a task is not executed
+    /// when its fingerprint is found in the store, its cached output is given to its successors.
+    pub fn set_cache_store(&mut self, store: Arc<dyn CacheStore>) {
+        self.cache_store = Some(store);
+    }
+
+    /// Always execute the tasks named `name`, even when their action is up to date, see
+    /// [`Complex::is_up_to_date`](crate::Complex::is_up_to_date), or their output is cached.
+    pub fn force_task(&mut self, name: &str) {
+        self.forced.insert(name.to_string());
+    }
+
+    /// Set the policy deciding which of the ready tasks is started first when they compete for the
+    /// parallelism or the resources of the dag, see [`SchedulingPolicy`].
+    pub fn set_scheduling_policy(&mut self, policy: SchedulingPolicy) {
+        self.scheduling_policy = policy;
+    }
+
+    /// Run the actions of the tasks on `executor`, unless a task has its own executor, see
+    /// [`Task::executor`]. By default they run on the tokio runtime executing the dag.
+    pub fn set_executor(&mut self, executor: Arc<dyn Executor>) {
+        self.executor = Some(executor);
+    }
+
+    /// Execute the dag deterministically, so that the order of execution of the tasks and the
+    /// logs are the same across executions.
+    ///
+    /// [`Dag::start`] then executes the dag on a single thread, and the actions of the tasks without
+    /// their own executor run one at a time on that thread. The tasks ready at the same time start
+    /// in an order given by `seed`: the same seed always gives the same order, another seed may
+    /// give another one. A synchronous action cannot be aborted on timeout in this mode.
+    ///
+    /// When executed with [`Dag::async_start`], the dag is only deterministic on a current-thread
+    /// runtime.
+    ///
+    /// # Example
+    /// ```rust
+    /// use dagrs::{Dag, DefaultTask, Output};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let log = Arc::new(Mutex::new(Vec::new()));
+    /// let tasks: Vec<_> = (0..4)
+    ///     .map(|i| {
+    ///         let log = log.clone();
+    ///         DefaultTask::with_closure(&format!("t{}", i), move |_input, _env| {
+    ///             log.lock().unwrap().push(i);
+    ///             Output::empty()
+    ///         })
+    ///     })
+    ///     .collect();
+    /// let mut dag = Dag::with_tasks(tasks).reusable();
+    /// dag.set_deterministic(7);
+    /// assert!(dag.start().unwrap());
+    /// let first = log.lock().unwrap().drain(..).collect::<Vec<_>>();
+    /// assert!(dag.start().unwrap());
+    /// assert_eq!(*log.lock().unwrap(), first);
+    /// ```
+    pub fn set_deterministic(&mut self, seed: u64) {
+        self.seed = Some(seed);
+        // The execution sequence is sorted again with the seed when the dag starts.
+        self.initialized = false;
+    }
+
+    /// Set the description, tags and owner of the dag, see [`DagMetadata`].
+    pub fn set_metadata(&mut self, metadata: DagMetadata) {
+        self.metadata = metadata;
+    }
+
+    /// Get the description, tags and owner of the dag, see [`DagMetadata`].
+    pub fn metadata(&self) -> &DagMetadata {
+        &self.metadata
+    }
+
+    /// Remember the durations of the tasks that succeeded in `report`, they are used to find the
+    /// critical path of the next executions, see [`SchedulingPolicy::CriticalPath`]. The durations
+    /// of each execution of the dag are recorded automatically, a report saved from a previous run
+    /// of the program can be given here.
+    pub fn record_durations(&mut self, report: &DagReport) {
+        for task in report.tasks.iter() {
+            if let (TaskStatus::Succeeded, Some(duration)) = (task.status, task.duration) {
+                self.durations.insert(task.name.clone(), duration);
+            }
+        }
+    }
+
+    /// The rank of each task of the dag under its scheduling policy, see [`Rank`].
+    ///
+    /// The duration of a task is its estimated duration, otherwise its duration during the previous
+    /// execution, otherwise the average duration of the other tasks.
+    fn ranks(&self) -> HashMap<usize, Rank> {
+        let policy = self.scheduling_policy;
+        let paths = if policy == SchedulingPolicy::CriticalPath {
+            let known: Vec<Option<Duration>> = (0..self.tasks.len())
+                .map(|index| {
+                    let task = &self.tasks[&self.rely_graph.find_id_by_index(index).unwrap()];
+                    task.estimated_duration()
+                        .or_else(|| self.durations.get(task.name()).copied())
+                })
+                .collect();
+            let durations: Vec<Duration> = known.iter().flatten().copied().collect();
+            let average = match durations.len() {
+                0 => Duration::from_secs(1),
+                n => durations.iter().sum::<Duration>() / n as u32,
+            };
+            let weights: Vec<Duration> = known
+                .into_iter()
+                .map(|duration| duration.unwrap_or(average))
+                .collect();
+            self.rely_graph.longest_paths(&weights)
+        } else {
+            vec![Duration::ZERO; self.tasks.len()]
+        };
+        self.tasks
+            .iter()
+            .map(|(id, task)| {
+                let index = self.rely_graph.find_index_by_id(id).unwrap();
+                (*id, (task.priority(), paths[index]))
+            })
+            .collect()
+    }
 
+    /// The ids of the successors of each task.
+    fn successors(&self) -> HashMap<usize, Vec<usize>> {
+        let mut successors: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (id, task) in self.tasks.iter() {
+            for precursor in task.precursors() {
+                successors.entry(*precursor).or_default().push(*id);
+            }
+        }
+        successors
+    }
+
+    /// Register an observer notified of the lifecycle events of the execution, see [`DagObserver`].
+    pub fn add_observer(&mut self, observer: Arc<dyn DagObserver>) {
+        self.observers.push(observer);
+    }
+
+    /// Parse the content of the configuration file into a series of tasks and generate a dag.
     fn read_tasks(
         file: &str,
         parser: Box<dyn Parser>,
         specific_actions: HashMap<String, Action>,
-    ) -> Result<Dag, DagError> {
+    ) -> Result<Dag<'_>, DagError> {
         let tasks = parser.parse_tasks(file, specific_actions)?;
 
         let mut dag = Dag::new();
@@ -226,12 +585,11 @@ impl <'a> Dag <'a> {
     }
 
     /// Parse the content of the configuration file into a series of tasks and generate a dag.
-
     fn read_tasks_from_str(
         content: &str,
         parser: Box<dyn Parser>,
         specific_actions: HashMap<String, Action>,
-    ) -> Result<Dag, DagError> {
+    ) -> Result<Dag<'_>, DagError> {
         let tasks = parser.parse_tasks_from_str(content, specific_actions)?;
 
         let mut dag = Dag::new();
@@ -244,13 +602,19 @@ impl <'a> Dag <'a> {
     ///
     /// This operation will initialize `dagrs.rely_graph` if no error occurs.
     fn create_graph(&mut self) -> Result<(), DagError> {
+        // Start over from an empty graph, a previous attempt may have failed halfway.
+        self.rely_graph = Graph::new();
         let size = self.tasks.len();
         self.rely_graph.set_graph_size(size);
+        if let Some(seed) = self.seed {
+            self.rely_graph.set_seed(seed);
+        }
 
-        // Add Node (create id - index mapping)
-        self.tasks
-            .iter()
-            .for_each(|(&n, _)| self.rely_graph.add_node(n));
+        // Add Node (create id - index mapping), in the order the tasks were created so that
+        // the execution sequence does not depend on the order of the map.
+        let mut ids: Vec<usize> = self.tasks.keys().copied().collect();
+        ids.sort_unstable();
+        ids.into_iter().for_each(|id| self.rely_graph.add_node(id));
 
         // Form Graph
         for (&id, task) in self.tasks.iter() {
@@ -270,18 +634,44 @@ impl <'a> Dag <'a> {
         Ok(())
     }
 
+    /// Check that the resources required by each task can be acquired, otherwise the task would wait forever.
+    fn check_resources(&self) -> Result<(), DagError> {
+        for task in self.tasks.values() {
+            for (name, amount) in task.resources() {
+                // The pools of an engine are shared, their permits may be held by other dags.
+                let capacity = self
+                    .resource_pools
+                    .get(name)
+                    .or_else(|| self.shared_resource_pools.get(name))
+                    .map_or(0, |(_, capacity)| *capacity);
+                if *amount > capacity {
+                    return Err(DagError::ResourceUnavailable(
+                        task.name().to_string(),
+                        name.clone(),
+                        *amount,
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Initialize dags. The initialization process completes three actions:
     /// - Initialize the status of each task execution result.
     /// - Create a graph from task dependencies.
     /// - Generate task heart sequence according to topological sorting of graph.
+    ///
+    /// The graph is only built once, initializing the dag again only creates the missing states.
     pub(crate) fn init(&mut self) -> Result<(), DagError> {
-        self.execute_states.reserve(self.tasks.len());
-        self.tasks.values().for_each(|task| {
-            self.execute_states
-                .insert(task.id(), Arc::new(ExecState::new()));
-        });
+        if self.execute_states.len() != self.tasks.len() {
+            self.reset();
+        }
+        if self.initialized {
+            return Ok(());
+        }
 
         self.create_graph()?;
+        self.check_resources()?;
 
         match self.rely_graph.topo_sort() {
             Some(seq) => {
@@ -293,77 +683,199 @@ impl <'a> Dag <'a> {
                     .map(|index| self.rely_graph.find_id_by_index(index).unwrap())
                     .collect();
                 self.exe_sequence = exe_seq;
+                self.initialized = true;
                 Ok(())
             }
             None => Err(DagError::LoopGraph),
         }
     }
 
+    /// This function is used for the execution of a single dag asynchronously.
+    ///
+    /// The tasks are executed on the runtime polling the returned future, which is `Send`, so it
+    /// can be spawned on any multi-threaded runtime, such as the one of a web service.
     pub async fn async_start(&mut self) -> Result<bool, DagError> {
+        self.async_start_with_report()
+            .await
+            .map(|report| report.is_success())
+    }
+
+    /// This function is used for the execution of a single dag.
+    ///
+    /// It builds a runtime of its own, and must not be called from within an asynchronous
+    /// context, see [`Dag::async_start`].
+    pub fn start(&mut self) -> Result<bool, DagError> {
+        self.start_with_report().map(|report| report.is_success())
+    }
+
+    /// Execute the dag asynchronously and return its execution report, see [`DagReport`].
+    pub async fn async_start_with_report(&mut self) -> Result<DagReport, DagError> {
+        if self.reusable && self.executed {
+            self.reset();
+        }
         // If the current continuable state is false, the task will start failing.
         if self.can_continue.load(Ordering::Acquire) {
-            match self.init() {
-                Ok(_) => Ok(self.run().await),
-                Err(err) => Err(err),
-            }
+            self.init()?;
+            Ok(self.run().await)
         } else {
-            Ok(false)
+            Ok(self.report(SystemTime::now(), DagOutcome::Failed))
         }
     }
 
-    /// This function is used for the execution of a single dag.
-    pub fn start(&mut self) -> Result<bool, DagError> {
+    /// Execute the dag and return its execution report, see [`DagReport`].
+    /// Like [`Dag::start`], it must not be called from within an asynchronous context.
+    ///
+    /// # Example
+    /// ```rust
+    /// use dagrs::{Dag, DefaultTask, Output, TaskStatus};
+    /// let task = DefaultTask::with_closure("Simple Task", |_input, _env| Output::new(1));
+    /// let mut dag = Dag::with_tasks(vec![task]);
+    /// let report = dag.start_with_report().unwrap();
+    /// assert!(report.is_success());
+    /// assert_eq!(report.task_by_name("Simple Task").unwrap().status, TaskStatus::Succeeded);
+    /// ```
+    pub fn start_with_report(&mut self) -> Result<DagReport, DagError> {
+        if self.reusable && self.executed {
+            self.reset();
+        }
         // If the current continuable state is false, the task will start failing.
         if self.can_continue.load(Ordering::Acquire) {
-            self.init().map_or_else(Err, |_| {
-                Ok(tokio::runtime::Runtime::new()
-                    .unwrap()
-                    .block_on(async { self.run().await }))
-            })
+            self.init()?;
+            let runtime = match self.seed {
+                Some(_) => tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap(),
+                None => tokio::runtime::Runtime::new().unwrap(),
+            };
+            let report = runtime.block_on(async { self.run().await });
+            // Do not wait for the synchronous actions that were aborted but still occupy
+            // a blocking thread, their outputs are discarded anyway.
+            runtime.shutdown_background();
+            Ok(report)
         } else {
-            Ok(false)
+            Ok(self.report(SystemTime::now(), DagOutcome::Failed))
         }
     }
 
     /// Execute tasks sequentially according to the execution sequence given by
     /// topological sorting, and cancel the execution of subsequent tasks if an
     /// error is encountered during task execution.
-    pub(crate) async fn run(&self) -> bool {
-        debug!("[Start]{} -> [End]", {
-            self.exe_sequence
-                .iter()
-                .map(|id| self.tasks[id].name())
-                .collect::<Vec<&str>>()
-                .join(" -> ")
-        });
+    pub(crate) async fn run(&mut self) -> DagReport {
+        let env = self.env.clone();
+        self.run_in(env).await
+    }
+
+    /// Execute the dag like [`Dag::run`], with the contents `extra` added to its environment for
+    /// this execution only.
+    pub(crate) async fn run_with_env_contents(
+        &mut self,
+        extra: Vec<(String, Content)>,
+    ) -> DagReport {
+        let mut env = self.env.as_ref().clone();
+        for (name, content) in extra {
+            env.set_content(&name, content);
+        }
+        self.run_in(Arc::new(env)).await
+    }
 
-        let handles = self
+    /// Execute the dag in `env`, the environment of the dag or an extension of it.
+    async fn run_in(&mut self, env: Arc<EnvVar>) -> DagReport {
+        self.executed = true;
+        let ranks = self.ranks();
+        let mut observers = self.observers.clone();
+        if let Some(store) = &self.checkpoint_store {
+            observers.push(Arc::new(CheckpointObserver {
+                store: store.clone(),
+                codecs: self.checkpoint_codecs.clone(),
+                clear_on_start: !self.resume,
+            }));
+        }
+        let mut restored = self.restore_checkpoints();
+        let sequence: Vec<(usize, &str)> = self
             .exe_sequence
             .iter()
-            .map(|id| (*id, self.execute_task(self.tasks[id].as_ref())))
+            .map(|id| (*id, self.tasks[id].name()))
+            .collect();
+        observers
+            .iter()
+            .for_each(|observer| observer.on_dag_start(&sequence));
+
+        let started_at = SystemTime::now();
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let context = Arc::new(ExecContext {
+            env,
+            deadline,
+            can_continue: self.can_continue.clone(),
+            error_flags: self.error_flags(),
+            observers,
+            resource_pools: self.all_resource_pools(),
+            parallelism: self
+                .max_parallelism
+                .iter()
+                .chain(self.shared_parallelism.iter())
+                .cloned()
+                .collect(),
+            expanded: self.expanded.clone(),
+            cache: self.cache_store.clone().map(|store| Cache {
+                store,
+                codecs: self.checkpoint_codecs.clone(),
+            }),
+            forced: self.forced.clone(),
+            ready_queue: (self.scheduling_policy != SchedulingPolicy::Fifo)
+                .then(|| ReadyQueue::new(ranks.clone(), self.successors())),
+            executor: match (&self.executor, self.seed) {
+                (Some(executor), _) => executor.clone(),
+                (None, Some(_)) => Arc::new(InlineExecutor),
+                (None, None) => Arc::new(TokioExecutor),
+            },
+        });
+        // Spawn the tasks of higher rank first, so that they are the first to become ready.
+        let mut spawn_sequence = self.exe_sequence.clone();
+        if context.ready_queue.is_some() {
+            spawn_sequence.sort_by_key(|id| Reverse(ranks[id]));
+        }
+        let handles = spawn_sequence
+            .iter()
+            .map(|id| {
+                let task = self.tasks[id].as_ref();
+                let mut execution = TaskExecution::new(task, context.clone());
+                execution.rank = ranks[id];
+                let handle = self.execute_task(task, execution, restored.remove(id));
+                (*id, handle)
+            })
             .collect::<Vec<_>>();
 
         // Wait for the status of each task to execute. If there is an error in the execution of a task,
         // the engine will fail to execute and give up executing tasks that have not yet been executed.
         for (tid, handle) in handles {
-            match handle.await {
-                Ok(succeed) => {
-                    if matches!(succeed, ExecResult::Failure) {
-                        self.handle_error(tid)
-                    }
-                }
-                Err(err) => {
-                    error!("Task execution encountered an unexpected error! {}", err);
-                    self.handle_error(tid);
-                }
+            if let Err(err) = handle.await {
+                let state = &self.execute_states[&tid];
+                context.handle_join_error(tid, self.tasks[&tid].name(), state, err);
+                state
+                    .semaphore()
+                    .add_permits(self.rely_graph.get_node_out_degree(&tid));
             }
         }
 
-        if let Some(tx) = self.tx.clone() {
-            let _ = tx.send(OutputMessage::Finish);
-        }
+        let outcome = if self.cancellation.is_cancelled() {
+            self.can_continue.store(false, Ordering::Release);
+            DagOutcome::Cancelled
+        } else {
+            self.outcome(deadline)
+        };
+        let report = self.report(started_at, outcome);
+        self.record_durations(&report);
+        context
+            .observers
+            .iter()
+            .for_each(|observer| observer.on_dag_finish(&report));
+        report
+    }
 
-        if self.keep_going {
+    /// The outcome of a dag execution that has not been cancelled.
+    fn outcome(&self, deadline: Option<Instant>) -> DagOutcome {
+        let success = if self.keep_going {
             // when keep_going is true, the task will continue to execute as much as possible.
             // So, the success is evaluated by keep_going_errored.
             !self.keep_going_errored.load(Ordering::Relaxed)
@@ -371,14 +883,95 @@ impl <'a> Dag <'a> {
             self.can_continue
                 .compare_exchange(true, false, Ordering::Relaxed, Ordering::Relaxed)
                 .is_ok()
+        };
+        if success {
+            DagOutcome::Succeeded
+        } else if deadline.is_some_and(|d| d <= Instant::now()) {
+            DagOutcome::TimedOut
+        } else {
+            DagOutcome::Failed
+        }
+    }
+
+    /// Load the outputs of the tasks that can be restored when resuming from checkpoints.
+    ///
+    /// A task is restored if it succeeded in the previous execution, its output can be decoded,
+    /// and all its predecessors are restored. Otherwise, its predecessors could produce different
+    /// inputs this time.
+    fn restore_checkpoints(&self) -> HashMap<usize, Output> {
+        let mut restored = HashMap::new();
+        let store = match &self.checkpoint_store {
+            Some(store) if self.resume => store,
+            _ => return restored,
+        };
+        for id in &self.exe_sequence {
+            let task = &self.tasks[id];
+            if !task.precursors().iter().all(|p| restored.contains_key(p)) {
+                continue;
+            }
+            let checkpoint = match store.load(task.name()) {
+                Ok(checkpoint) => checkpoint,
+                Err(err) => {
+                    warn!("Cannot restore task[{}]: {}", task.name(), err);
+                    None
+                }
+            };
+            if let Some(output) = checkpoint
+                .filter(|c| {
+                    matches!(
+                        c.status,
+                        TaskStatus::Succeeded
+                            | TaskStatus::Restored
+                            | TaskStatus::Cached
+                            | TaskStatus::UpToDate
+                    )
+                })
+                .and_then(|c| c.output)
+                .and_then(|output| self.checkpoint_codecs.decode(&output))
+            {
+                restored.insert(*id, output);
+            }
+        }
+        restored
+    }
+
+    /// Build the execution report of the dag from the records of its tasks.
+    ///
+    /// The tasks expanded at runtime are reported right after the task they expanded from.
+    fn report(&self, started_at: SystemTime, outcome: DagOutcome) -> DagReport {
+        let finished_at = SystemTime::now();
+        let expanded = self.expanded.lock().unwrap();
+        let mut tasks = Vec::with_capacity(self.exe_sequence.len() + expanded.len());
+        for id in &self.exe_sequence {
+            tasks.push(
+                self.execute_states[id]
+                    .record()
+                    .to_report(*id, self.tasks[id].name()),
+            );
+            report_expanded(&expanded, *id, &mut tasks);
+        }
+        DagReport {
+            outcome,
+            started_at,
+            finished_at,
+            duration: finished_at.duration_since(started_at).unwrap_or_default(),
+            tasks,
         }
     }
 
     /// Execute a given task asynchronously.
-    fn execute_task(&self, task: &dyn Task) -> JoinHandle<ExecResult> {
-        let env = self.env.clone();
+    ///
+    /// A task restored from a checkpoint is not executed, its `restored` output is given to its successors.
+    /// Otherwise, once all its predecessors are done, the task is executed if its [`TriggerRule`] is met
+    /// and its [`Condition`](crate::Condition) holds. Whatever happens, its successors are woken up once
+    /// it reached its final status.
+    fn execute_task(
+        &self,
+        task: &dyn Task,
+        execution: TaskExecution,
+        restored: Option<Output>,
+    ) -> JoinHandle<ExecResult> {
         let task_id = task.id();
-        let task_name = task.name().to_string();
         let execute_state = self.execute_states[&task_id].clone();
         let task_out_degree = self.rely_graph.get_node_out_degree(&task_id);
         let wait_for_input: Vec<Arc<ExecState>> = task
@@ -386,158 +979,134 @@ impl <'a> Dag <'a> {
             .iter()
             .map(|id| self.execute_states[id].clone())
             .collect();
-        let action = task.action();
-        let can_continue = self.can_continue.clone();
-        let tx = self.tx.clone();
+        let trigger_rule = task.trigger_rule();
+        let condition = task.condition();
+        let root_input = task.precursors().is_empty().then(|| self.input.clone());
 
         tokio::spawn(async move {
-            // Wait for the execution result of the predecessor task
-            let mut inputs = Vec::with_capacity(wait_for_input.len());
-            for wait_for in wait_for_input {
-                wait_for.semaphore().acquire().await.unwrap().forget();
-                // When the task execution result of the predecessor can be obtained, judge whether
-                // the continuation flag is set to false, if it is set to false, cancel the specific
-                // execution logic of the task and return immediately.
-                if !can_continue.load(Ordering::Acquire) || !wait_for.success() {
-                    return ExecResult::Termination;
+            let context = execution.context.clone();
+            let result = async {
+                if let Some(output) = restored {
+                    execution.finish(&execute_state, TaskStatus::Restored, Some(&output));
+                    execute_state.set_output(output);
+                    return ExecResult::Success;
                 }
-                // Set the outputs of predecessors as inputs of the current
-                let output = wait_for.get_output();
-                // If at least one of the inputs is termination, also terminate this node early,
-                // applies to all except exit node
-                // TODO: `task_out_degree` > 0 is a way to check for non-exit nodes. Fix this with something more reliable
-                if matches!(output, Output::Termination) && task_out_degree > 0 {
-                    execute_state.set_and_propagate_success(output, task_out_degree);
-                    return ExecResult::Termination;
-                }
-                if let Some(content) = output.get_out() {
-                    inputs.push(content);
+                // Wait for the final status of all the predecessors.
+                for wait_for in wait_for_input.iter() {
+                    wait_for.semaphore().acquire().await.unwrap().forget();
+                    if context.env.cancellation().is_cancelled() {
+                        execution.finish(&execute_state, TaskStatus::Terminated, None);
+                        return ExecResult::Termination;
+                    }
                 }
-            }
-            debug!("Executing task [name: {}, id: {}]", task_name, task_id);
-            // Concrete logical behavior for performing tasks.
-            match panic::catch_unwind(AssertUnwindSafe(|| action.run(Input::new(inputs), env))) {
-                Err(_) => {
-                    error!("Execution failed [name: {}, id: {}]", task_name, task_id);
-                    ExecResult::Failure
+                // When the continuation flag is set to false, cancel the specific execution logic
+                // of the task and return immediately.
+                if !context.can_continue.load(Ordering::Acquire) {
+                    execution.finish(&execute_state, TaskStatus::Terminated, None);
+                    return ExecResult::Termination;
                 }
-                Ok(out) => {
-                    let out = out.await;
-                    if let Some(tx) = tx {
-                        let _ = tx.send(OutputMessage::Message(OutputMessageContent {
-                            task_name: task_name.to_string(),
-                            result: out.clone(),
-                        }));
-                    };
-                    // Store execution results
-                    if out.is_err() {
-                        error!(
-                            "Execution failed [name: {}, id: {}]\nerr: {}",
-                            task_name,
-                            task_id,
-                            out.get_err().unwrap_or("DAGRS: couldn't parse error message".to_string())
-                        );
-                        ExecResult::Failure
-                    } else {
-                        let is_termination = out.is_termination().clone();
-                        execute_state.set_and_propagate_success(out, task_out_degree);
-                        if is_termination {
-                            debug!(
-                                "Execution terminated [name: {}, id: {}]",
-                                task_name, task_id,
-                            );
-                            ExecResult::Termination
-                        } else {
-                            debug!("Execution succeed [name: {}, id: {}]", task_name, task_id);
-                            ExecResult::Success
+                // An expanded predecessor is replaced by the tasks it expanded into.
+                let gathered: Vec<Arc<ExecState>> = wait_for_input
+                    .iter()
+                    .flat_map(|wait_for| wait_for.gathered())
+                    .collect();
+                let (mut bypassed, mut failed) = (0, 0);
+                let mut input = root_input.unwrap_or_default();
+                for wait_for in gathered.iter() {
+                    let output = wait_for.get_output();
+                    match wait_for.record().status() {
+                        TaskStatus::Succeeded
+                        | TaskStatus::Restored
+                        | TaskStatus::Cached
+                        | TaskStatus::UpToDate => {
+                            if !output.selects(&execution.name) {
+                                bypassed += 1;
+                            } else {
+                                input.push(wait_for.id(), wait_for.name(), output.get_out());
+                            }
+                        }
+                        // If at least one of the inputs is termination, also terminate this node early,
+                        // applies to all except exit node
+                        // TODO: `task_out_degree` > 0 is a way to check for non-exit nodes. Fix this with something more reliable
+                        TaskStatus::Terminated if wait_for.success() && output.is_termination() => {
+                            if task_out_degree > 0 {
+                                execution.finish(&execute_state, TaskStatus::Terminated, None);
+                                execute_state.set_output(output);
+                                return ExecResult::Termination;
+                            }
+                        }
+                        TaskStatus::Terminated => {
+                            execution.finish(&execute_state, TaskStatus::Terminated, None);
+                            return ExecResult::Termination;
                         }
+                        TaskStatus::Bypassed => bypassed += 1,
+                        _ => failed += 1,
                     }
                 }
+                let succeeded = gathered.len() - bypassed - failed;
+                if !trigger_rule.is_met(succeeded, bypassed, failed) {
+                    let status = if failed > 0 {
+                        TaskStatus::Skipped
+                    } else {
+                        TaskStatus::Bypassed
+                    };
+                    execution.finish(&execute_state, status, None);
+                    return ExecResult::Termination;
+                }
+                if condition.is_some_and(|condition| !condition(&input, &context.env)) {
+                    execution.finish(&execute_state, TaskStatus::Bypassed, None);
+                    return ExecResult::Termination;
+                }
+                execution.execute(input, &execute_state).await
+            }
+            .await;
+            if let Some(queue) = &context.ready_queue {
+                queue.leave(execution.id);
             }
+            if matches!(result, ExecResult::Failure | ExecResult::Timeout) {
+                context.error_flags.handle_error();
+            }
+            // Wake up the successors, they find out the final status of this task.
+            execute_state.semaphore().add_permits(task_out_degree);
+            result
         })
     }
 
-    /// error handling.
-    /// When a task execution error occurs, the error handling logic is:
-    /// First, set the continuation status to false, and then release the semaphore of the
-    /// error task and the tasks after the error task, so that subsequent tasks can quickly
-    /// know that some tasks have errors and cannot continue to execute.
-    /// After that, the follow-up task finds that the flag that can continue to execute is set
-    /// to false, and the specific behavior of executing the task will be cancelled.
-    fn handle_error(&self, error_task_id: usize) {
-        if self.keep_going {
-            self.handle_errored_keep_going(error_task_id);
-        } else {
-            self.handle_errored_stopping(error_task_id);
+    /// The flags updated when a task fails, see [`ErrorFlags::handle_error`].
+    fn error_flags(&self) -> ErrorFlags {
+        ErrorFlags {
+            keep_going: self.keep_going,
+            can_continue: self.can_continue.clone(),
+            keep_going_errored: self.keep_going_errored.clone(),
         }
     }
 
-    /// When the keep_going flag is set to false, the error handling logic is:
-    /// - Set the continuation status to false
-    /// - Adding permits for all the subsequent tasks
-    fn handle_errored_stopping(&self, error_task_id: usize) {
-        if self
-            .can_continue
-            .compare_exchange(true, false, Ordering::SeqCst, Ordering::Relaxed)
-            .is_err()
-        {
-            return;
-        }
-
-        // Find the position of the faulty task in the execution sequence.
-        let index = self
-            .exe_sequence
-            .iter()
-            .position(|tid| *tid == error_task_id)
-            .unwrap();
-
-        // Add permits for all the subsequent tasks
-        for tid in self.exe_sequence.iter().skip(index) {
-            self.handle_errored_successor(tid, false);
-        }
+    /// Set the input of the tasks without predecessors.
+    pub(crate) fn set_input(&mut self, input: Input) {
+        self.input = input;
     }
 
-    /// When the keep_going flag is set to true, the error handling logic is:
-    /// - Set the keep_going_errored flag to true
-    /// - Adding permits for all tasks that rely on the error task
-    /// - Setting them as failed
-    fn handle_errored_keep_going(&self, error_task_id: usize) {
-        self.keep_going_errored.store(true, Ordering::SeqCst);
-
-        // Add permits for the tasks that rely on the error task
-        for successor in self
-            .rely_graph
-            .get_node_successors(&error_task_id)
-            .into_iter()
-        {
-            let tid = self.rely_graph.find_id_by_index(successor).unwrap();
-            self.handle_errored_successor(&tid, true);
-        }
+    /// The output of the last task of the execution sequence.
+    pub(crate) fn exit_output(&self) -> Option<Output> {
+        self.exe_sequence
+            .last()
+            .map(|id| self.execute_states[id].get_output())
     }
 
-    /// Add permits for a task and mark the task as failed if necessary.
-    fn handle_errored_successor(&self, tid: &usize, exe_fail: bool) {
-        let out_degree = self.rely_graph.get_node_out_degree(tid);
-        let exec_state = &self.execute_states[tid];
-
-        exec_state.semaphore().add_permits(out_degree);
+    /// Get the final execution result.
+    pub fn get_result<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.final_output().and_then(|content| content.into_inner())
+    }
 
-        if exe_fail {
-            exec_state.exe_fail();
-        }
+    /// The content of the output of the last task of the execution sequence.
+    pub(crate) fn final_output(&self) -> Option<Content> {
+        let last_id = self.exe_sequence.last()?;
+        self.execute_states[last_id].get_output().get_out()
     }
 
-    /// Get the final execution result.
-    pub fn get_result<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
-        if self.exe_sequence.is_empty() {
-            None
-        } else {
-            let last_id = self.exe_sequence.last().unwrap();
-            self.execute_states[last_id].get_output()
-                .get_out()
-                .map(|content| content.into_inner())
-                .unwrap_or(None)
-        }
+    /// The report of a dag that was not executed because a dag it depends on did not succeed.
+    pub(crate) fn skipped_report(&self) -> DagReport {
+        self.report(SystemTime::now(), DagOutcome::Skipped)
     }
 
     /// Get the output of all tasks.
@@ -545,11 +1114,19 @@ impl <'a> Dag <'a> {
         let hm = self
             .execute_states
             .iter()
+            .chain(
+                self.expanded
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|task| (&task.id, &task.state)),
+            )
             .map(|(&id, state)| {
-                let output = state.get_output()
-                        .get_out()
-                        .map(|content| content.into_inner())
-                        .unwrap_or(None);
+                let output = state
+                    .get_output()
+                    .get_out()
+                    .map(|content| content.into_inner())
+                    .unwrap_or(None);
                 (id, output)
             })
             .collect();
@@ -557,7 +1134,460 @@ impl <'a> Dag <'a> {
     }
 
     /// Before the dag starts executing, set the dag's global environment variable.
-    pub fn set_env(&mut self, env: EnvVar) {
+    pub fn set_env(&mut self, mut env: EnvVar) {
+        env.set_cancellation(self.cancellation.clone());
         self.env = Arc::new(env);
     }
 }
+
+/// The flags of a dag recording that a task failed.
+struct ErrorFlags {
+    keep_going: bool,
+    can_continue: Arc<AtomicBool>,
+    keep_going_errored: Arc<AtomicBool>,
+}
+
+impl ErrorFlags {
+    /// error handling.
+    /// When the keep_going flag is set to false, set the continuation status to false. The
+    /// tasks that have not started yet find out that the flag is false once their predecessors
+    /// are done, and the specific behavior of executing them is cancelled.
+    /// When the keep_going flag is set to true, set the keep_going_errored flag to true. The
+    /// tasks that rely on the error task are skipped, unless their trigger rule allows
+    /// failed predecessors.
+    fn handle_error(&self) {
+        if self.keep_going {
+            self.keep_going_errored.store(true, Ordering::SeqCst);
+        } else {
+            self.can_continue.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+/// The settings of an execution of a dag, shared by all its tasks including the tasks
+/// expanded at runtime.
+struct ExecContext {
+    env: Arc<EnvVar>,
+    deadline: Option<Instant>,
+    can_continue: Arc<AtomicBool>,
+    error_flags: ErrorFlags,
+    observers: Vec<Arc<dyn DagObserver>>,
+    /// The resource pools of the dag, with their capacities.
+    resource_pools: HashMap<String, (Arc<Semaphore>, u32)>,
+    /// The parallelism limits of the dag, and of its engine.
+    parallelism: Vec<Arc<Semaphore>>,
+    /// The tasks expanded during this execution.
+    expanded: Arc<Mutex<Vec<ExpandedTask>>>,
+    /// The cache of the dag, if any.
+    cache: Option<Cache>,
+    /// The names of the tasks executed even when they are up to date or cached.
+    forced: HashSet<String>,
+    /// Orders the ready tasks by rank, unless they are started in the order they became ready.
+    ready_queue: Option<ReadyQueue>,
+    /// Runs the actions of the tasks without their own executor.
+    executor: Arc<dyn Executor>,
+}
+
+impl ExecContext {
+    /// The semaphores a task acquires permits from before executing, and the number of permits.
+    ///
+    /// They are always acquired in the same order: resource pools sorted by name, then the parallelism
+    /// limits. This way two tasks never wait for each other's permits.
+    ///
+    /// The resources of the tasks of the dag are checked when it is initialized, but those of the
+    /// tasks expanded at runtime are only checked here.
+    fn required_permits(
+        &self,
+        task_name: &str,
+        resources: &[(String, u32)],
+    ) -> Result<Vec<(Arc<Semaphore>, u32)>, DagError> {
+        let mut resources: Vec<&(String, u32)> = resources.iter().collect();
+        resources.sort();
+        let mut permits = Vec::with_capacity(resources.len() + self.parallelism.len());
+        for (name, amount) in resources.into_iter().filter(|(_, amount)| *amount > 0) {
+            match self.resource_pools.get(name) {
+                Some((pool, capacity)) if amount <= capacity => {
+                    permits.push((pool.clone(), *amount))
+                }
+                _ => {
+                    return Err(DagError::ResourceUnavailable(
+                        task_name.to_string(),
+                        name.clone(),
+                        *amount,
+                    ))
+                }
+            }
+        }
+        permits.extend(self.parallelism.iter().map(|s| (s.clone(), 1)));
+        Ok(permits)
+    }
+
+    /// Acquire the permits required by a task, in the order of the ready queue if any.
+    async fn acquire_permits(
+        &self,
+        id: usize,
+        rank: Rank,
+        permits: Vec<(Arc<Semaphore>, u32)>,
+    ) -> Vec<OwnedSemaphorePermit> {
+        match &self.ready_queue {
+            // A task without permits to acquire does not compete with the others.
+            Some(queue) if !permits.is_empty() => queue.acquire(id, rank, permits).await,
+            _ => acquire_permits(permits).await,
+        }
+    }
+
+    /// Record that the future executing a task failed unexpectedly.
+    fn handle_join_error(&self, id: usize, name: &str, state: &ExecState, err: JoinError) {
+        state.record().finish_with_error(
+            TaskStatus::Panicked,
+            format!("Task execution encountered an unexpected error! {}", err),
+        );
+        let report = state.record().to_report(id, name);
+        if let Some(queue) = &self.ready_queue {
+            queue.leave(id);
+        }
+        self.observers
+            .iter()
+            .for_each(|observer| observer.on_task_failure(&report));
+        self.error_flags.handle_error();
+    }
+}
+
+/// A task expanded at runtime from another task, see [`Output::expand`].
+struct ExpandedTask {
+    parent: usize,
+    id: usize,
+    name: String,
+    state: Arc<ExecState>,
+}
+
+/// Add the reports of the tasks expanded from `parent`, each followed by its own expanded tasks.
+fn report_expanded(expanded: &[ExpandedTask], parent: usize, reports: &mut Vec<TaskReport>) {
+    for task in expanded.iter().filter(|task| task.parent == parent) {
+        reports.push(task.state.record().to_report(task.id, &task.name));
+        report_expanded(expanded, task.id, reports);
+    }
+}
+
+/// The way the last attempt of a task ended.
+enum ActionResult {
+    /// The action returned an output, which may be an error.
+    Finished(Output),
+    /// The action panicked, with the panic message.
+    Panicked(String),
+    /// The action did not finish before its timeout or the deadline of the dag.
+    TimedOut,
+    /// The dag has been cancelled during the execution.
+    Cancelled,
+}
+
+/// The execution settings of a task, extracted from the task so that they can be moved
+/// into the spawned future.
+struct TaskExecution {
+    id: usize,
+    name: String,
+    action: Action,
+    retry_policy: Option<RetryPolicy>,
+    timeout: Option<Duration>,
+    resources: Vec<(String, u32)>,
+    cache_policy: Option<CachePolicy>,
+    /// The rank of the task, used when the ready tasks are ordered, see [`ReadyQueue`].
+    rank: Rank,
+    /// Runs the attempts of the action.
+    executor: Arc<dyn Executor>,
+    context: Arc<ExecContext>,
+}
+
+impl TaskExecution {
+    fn new(task: &dyn Task, context: Arc<ExecContext>) -> Self {
+        Self {
+            id: task.id(),
+            name: task.name().to_string(),
+            action: task.action(),
+            retry_policy: task.retry_policy(),
+            timeout: task.timeout(),
+            resources: task.resources().to_vec(),
+            cache_policy: task.cache_policy(),
+            rank: (task.priority(), Duration::ZERO),
+            executor: task.executor().unwrap_or_else(|| context.executor.clone()),
+            context,
+        }
+    }
+
+    fn notify(&self, event: impl Fn(&dyn DagObserver)) {
+        self.context
+            .observers
+            .iter()
+            .for_each(|observer| event(observer.as_ref()));
+    }
+
+    /// Record the final status of the task, with the output it returned if any, and notify
+    /// the observers.
+    fn finish(&self, state: &ExecState, status: TaskStatus, output: Option<&Output>) {
+        state.record().finish(status, output);
+        // The successors of an expanded task are not ready before its children are done.
+        if let Some(queue) = &self.context.ready_queue {
+            if !matches!(output, Some(Output::Expand(_))) {
+                queue.finished(self.id);
+            }
+        }
+        let report = state.record().to_report(self.id, &self.name);
+        match (status, output) {
+            (TaskStatus::Failed | TaskStatus::TimedOut | TaskStatus::Panicked, _) => {
+                self.notify(|observer| observer.on_task_failure(&report))
+            }
+            (_, Some(output)) => self.notify(|observer| observer.on_task_success(&report, output)),
+            _ => self.notify(|observer| observer.on_task_skipped(&report)),
+        }
+    }
+
+    /// Execute the task once it is ready: acquire its resources, run its action and record
+    /// the outcome. When the task expands, its children are executed before this returns.
+    async fn execute(&self, input: Input, state: &ExecState) -> ExecResult {
+        let context = &self.context;
+        self.notify(|observer| observer.on_task_ready(self.id, &self.name));
+        // A task which is up to date or whose output is cached is not executed, unless it is forced.
+        let forced = context.forced.contains(&self.name);
+        if !forced && self.action.is_up_to_date().await {
+            let output = Output::empty();
+            self.finish(state, TaskStatus::UpToDate, Some(&output));
+            state.set_output(output);
+            return ExecResult::Success;
+        }
+        let fingerprint = self.fingerprint(&input).await;
+        if let Some((cache, fingerprint)) = context.cache.as_ref().zip(fingerprint.as_ref()) {
+            if let Some(output) = (!forced)
+                .then(|| cache.get(&self.name, fingerprint))
+                .flatten()
+            {
+                self.finish(state, TaskStatus::Cached, Some(&output));
+                state.set_output(output);
+                return ExecResult::Success;
+            }
+        }
+        let permits = match context.required_permits(&self.name, &self.resources) {
+            Ok(permits) => permits,
+            Err(err) => {
+                state
+                    .record()
+                    .finish_with_error(TaskStatus::Failed, err.to_string());
+                self.finish(state, TaskStatus::Failed, None);
+                return ExecResult::Failure;
+            }
+        };
+        // Wait for the resources and a slot to execute the task, they are released once it is done.
+        let permits = tokio::select! {
+            permits = context.acquire_permits(self.id, self.rank, permits) => permits,
+            _ = context.env.cancellation().cancelled() => Vec::new(),
+        };
+        if context.env.cancellation().is_cancelled()
+            || !context.can_continue.load(Ordering::Acquire)
+        {
+            self.finish(state, TaskStatus::Terminated, None);
+            return ExecResult::Termination;
+        }
+        // Concrete logical behavior for performing tasks.
+        match self.run(input.clone(), state).await {
+            ActionResult::Cancelled => {
+                self.finish(state, TaskStatus::Terminated, None);
+                ExecResult::Termination
+            }
+            ActionResult::Panicked(msg) => {
+                state.record().finish_with_error(TaskStatus::Panicked, msg);
+                self.finish(state, TaskStatus::Panicked, None);
+                ExecResult::Failure
+            }
+            ActionResult::TimedOut => {
+                state
+                    .record()
+                    .finish_with_error(TaskStatus::TimedOut, "execution timed out".to_string());
+                self.finish(state, TaskStatus::TimedOut, None);
+                ExecResult::Timeout
+            }
+            // Store execution results
+            ActionResult::Finished(out) if out.is_err() => {
+                self.finish(state, TaskStatus::Failed, Some(&out));
+                ExecResult::Failure
+            }
+            ActionResult::Finished(out) => {
+                let (status, result) = if out.is_termination() {
+                    (TaskStatus::Terminated, ExecResult::Termination)
+                } else {
+                    (TaskStatus::Succeeded, ExecResult::Success)
+                };
+                self.finish(state, status, Some(&out));
+                if let Some((cache, fingerprint)) = context.cache.as_ref().zip(fingerprint) {
+                    if status == TaskStatus::Succeeded {
+                        cache.put(&self.name, &fingerprint, &out);
+                    }
+                }
+                if let Output::Expand(expansion) = &out {
+                    // The children need the resources more than the task which is done.
+                    drop(permits);
+                    self.expand(expansion, input, state).await;
+                }
+                state.set_output(out);
+                result
+            }
+        }
+    }
+
+    /// The fingerprint of the execution of the task with `input`, if its output can be cached.
+    async fn fingerprint(&self, input: &Input) -> Option<String> {
+        let cache = self.context.cache.as_ref()?;
+        let policy = self.cache_policy.as_ref()?;
+        let cache_key = self.action.cache_key().await;
+        cache.fingerprint(
+            &self.name,
+            cache_key.as_deref(),
+            policy,
+            input,
+            &self.context.env,
+        )
+    }
+
+    /// Execute the tasks this task expanded into, in parallel, and wait for them.
+    async fn expand(&self, expansion: &Expansion, input: Input, state: &ExecState) {
+        let mut children = Vec::with_capacity(expansion.len());
+        for task in expansion.tasks() {
+            let child_state = Arc::new(ExecState::new(task.id(), task.name()));
+            self.context.expanded.lock().unwrap().push(ExpandedTask {
+                parent: self.id,
+                id: task.id(),
+                name: task.name().to_string(),
+                state: child_state.clone(),
+            });
+            let mut execution = TaskExecution::new(task.as_ref(), self.context.clone());
+            // The children are on the path of the task, they start the same chain.
+            execution.rank.1 = self.rank.1;
+            let handle = spawn_expanded(execution, input.clone(), child_state.clone());
+            children.push((task, child_state, handle));
+        }
+        let mut states = Vec::with_capacity(children.len());
+        for (task, child_state, handle) in children {
+            match handle.await {
+                Ok(ExecResult::Failure | ExecResult::Timeout) => {
+                    self.context.error_flags.handle_error()
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    self.context
+                        .handle_join_error(task.id(), task.name(), &child_state, err)
+                }
+            }
+            states.push(child_state);
+        }
+        state.set_expanded(states);
+    }
+
+    /// Run the action of the task, executing it again as long as the retry policy allows it.
+    ///
+    /// Each attempt is spawned on the executor of the task, so that a panicking action is caught and can be retried,
+    /// and an attempt exceeding the task timeout or `deadline` is aborted.
+    async fn run(&self, input: Input, state: &ExecState) -> ActionResult {
+        let (task_name, task_id) = (&self.name, self.id);
+        let (env, deadline) = (&self.context.env, self.context.deadline);
+        let mut attempt = 1;
+        loop {
+            // The attempt must end before both the task timeout and the deadline of the dag.
+            let attempt_deadline = match (self.timeout.map(|t| Instant::now() + t), deadline) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            if attempt_deadline.is_some_and(|d| d <= Instant::now()) {
+                return ActionResult::TimedOut;
+            }
+            state.record().start_attempt();
+            self.notify(|observer| observer.on_task_start(task_id, task_name, attempt));
+            let (action, input, env) = (self.action.clone(), input.clone(), env.clone());
+            let cancellation = env.cancellation().clone();
+            let mut handle = self.executor.spawn(task_name, action, input, env);
+            let joined = async {
+                match attempt_deadline {
+                    Some(d) => tokio::time::timeout_at(d, &mut handle).await.ok(),
+                    None => Some((&mut handle).await),
+                }
+            };
+            let result = tokio::select! {
+                joined = joined => match joined {
+                    Some(joined) => joined.map_or_else(
+                        |err| ActionResult::Panicked(join_error_message(err)),
+                        |output| {
+                            // The output of a sub-dag carries the report of the inner dag.
+                            let (output, sub_dag) = SubDagOutput::unwrap(output);
+                            if let Some(report) = sub_dag {
+                                state.record().set_sub_dag(report);
+                            }
+                            ActionResult::Finished(output)
+                        },
+                    ),
+                    None => ActionResult::TimedOut,
+                },
+                _ = cancellation.cancelled() => ActionResult::Cancelled,
+            };
+            if matches!(result, ActionResult::TimedOut | ActionResult::Cancelled) {
+                // Dropping the action releases its resources, e.g. kills a child process.
+                handle.abort();
+            }
+            let retry = match (&self.retry_policy, &result) {
+                (Some(policy), ActionResult::Finished(out)) if out.is_err() => {
+                    policy.should_retry(attempt, Some(out))
+                }
+                (Some(policy), ActionResult::Panicked(_) | ActionResult::TimedOut) => {
+                    policy.should_retry(attempt, None)
+                }
+                _ => false,
+            };
+            if !retry {
+                return result;
+            }
+            let policy = self.retry_policy.as_ref().unwrap();
+            let delay = policy.delay(attempt);
+            if deadline.is_some_and(|d| Instant::now() + delay >= d) {
+                return result;
+            }
+            warn!(
+                "Execution failed, retry in {:?} [name: {}, id: {}, attempt: {}/{}]",
+                delay,
+                task_name,
+                task_id,
+                attempt,
+                policy.max_attempts()
+            );
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => attempt += 1,
+                _ = cancellation.cancelled() => return ActionResult::Cancelled,
+            }
+        }
+    }
+}
+
+/// Spawn the execution of an expanded task.
+///
+/// The future is boxed, since an expanded task may expand in turn.
+fn spawn_expanded(
+    execution: TaskExecution,
+    input: Input,
+    state: Arc<ExecState>,
+) -> JoinHandle<ExecResult> {
+    let future: Pin<Box<dyn Future<Output = ExecResult> + Send>> =
+        Box::pin(async move { execution.execute(input, &state).await });
+    tokio::spawn(future)
+}
+
+/// Extract the message of a panicked attempt.
+fn join_error_message(err: JoinError) -> String {
+    match err.try_into_panic() {
+        Ok(payload) => panic_message(payload.as_ref()),
+        Err(err) => err.to_string(),
+    }
+}
+
+/// Extract the message of a panic from its payload.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|msg| msg.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "the task panicked".to_string())
+}
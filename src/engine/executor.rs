@@ -0,0 +1,172 @@
+//! Executors of the actions of the tasks
+//!
+//! # [`Executor`]
+//!
+//! Once a task is ready and acquired its resources, each attempt of its action is dispatched to
+//! an executor. The executor of a dag is set with [`Dag::set_executor`](super::Dag::set_executor),
+//! and a task can use another one, see [`Task::executor`](crate::Task::executor).
+//!
+//! - [`TokioExecutor`], the default, runs the actions in the current process: asynchronous actions
+//!   are spawned on the tokio runtime, synchronous ones on its blocking thread pool.
+//! - [`SerialExecutor`] runs the actions one at a time on a dedicated thread, in the order they are
+//!   dispatched. It is meant for tests, where the actions should not run concurrently.
+//! - With the `serde` feature, [`WorkerPool`](crate::WorkerPool) runs the actions in a pool of
+//!   worker processes, so that a panic, a crash or a memory leak of an untrusted action does not
+//!   affect the dag.
+//!
+//! # Example
+//!
+//! ```rust
+//! use dagrs::{Dag, DefaultTask, Output, SerialExecutor};
+//! use std::sync::Arc;
+//!
+//! let a = DefaultTask::with_closure("a", |_input, _env| Output::new(1usize));
+//! let mut b = DefaultTask::with_closure("b", |input, _env| {
+//!     Output::new(input.get_from::<usize>("a").unwrap() + 1)
+//! });
+//! b.set_predecessors(&[&a]);
+//! let mut dag = Dag::with_tasks(vec![a, b]);
+//! dag.set_executor(Arc::new(SerialExecutor::new()));
+//! assert!(dag.start().unwrap());
+//! assert_eq!(*dag.get_result::<usize>().unwrap(), 2);
+//! ```
+
+use std::{
+    any::Any,
+    panic::AssertUnwindSafe,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+use tokio::{
+    sync::oneshot::{self, error::TryRecvError},
+    task::JoinHandle,
+};
+
+use crate::{Action, EnvVar, Input, Output};
+
+/// Runs the attempts of the actions of the tasks.
+pub trait Executor: Send + Sync {
+    /// Start an attempt of `action`, the action of the task named `task`, with its input and the
+    /// environment of the dag. It is called from within the tokio runtime executing the dag.
+    ///
+    /// The handle is aborted when the attempt times out or the dag is cancelled, and it must report
+    /// a panic of the action as a panic, see [`JoinError::is_panic`](tokio::task::JoinError::is_panic).
+    fn spawn(
+        &self,
+        task: &str,
+        action: Action,
+        input: Input,
+        env: Arc<EnvVar>,
+    ) -> JoinHandle<Output>;
+}
+
+/// Runs the actions in the current process, on the tokio runtime executing the dag.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioExecutor;
+
+impl Executor for TokioExecutor {
+    fn spawn(
+        &self,
+        _task: &str,
+        action: Action,
+        input: Input,
+        env: Arc<EnvVar>,
+    ) -> JoinHandle<Output> {
+        tokio::spawn(async move { action.run(input, env).await })
+    }
+}
+
+/// Runs the actions within the tokio task executing the task, on the thread polling it.
+///
+/// Used by the deterministic execution of a dag, see [`Dag::set_deterministic`](super::Dag::set_deterministic).
+pub(crate) struct InlineExecutor;
+
+impl Executor for InlineExecutor {
+    fn spawn(
+        &self,
+        _task: &str,
+        action: Action,
+        input: Input,
+        env: Arc<EnvVar>,
+    ) -> JoinHandle<Output> {
+        tokio::spawn(async move { action.run_inline(input, env).await })
+    }
+}
+
+type Job = Box<dyn FnOnce(&tokio::runtime::Runtime) + Send>;
+
+/// Runs the actions one at a time on a dedicated thread, in the order they are dispatched.
+///
+/// When an attempt is aborted, for example because it timed out, an asynchronous action is
+/// dropped, which kills the process of a [`CommandAction`](crate::CommandAction), and an action
+/// that did not start yet is not executed. A synchronous action cannot be interrupted: it keeps
+/// the thread busy until it returns and its output is discarded. The thread stops once the
+/// executor is dropped.
+pub struct SerialExecutor {
+    jobs: Mutex<mpsc::Sender<Job>>,
+}
+
+impl SerialExecutor {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        thread::Builder::new()
+            .name("dagrs-serial".to_string())
+            .spawn(move || {
+                // Drives the asynchronous actions on this thread too.
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap();
+                while let Ok(job) = receiver.recv() {
+                    job(&runtime);
+                }
+            })
+            .unwrap();
+        Self {
+            jobs: Mutex::new(sender),
+        }
+    }
+}
+
+impl Default for SerialExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Executor for SerialExecutor {
+    fn spawn(
+        &self,
+        _task: &str,
+        action: Action,
+        input: Input,
+        env: Arc<EnvVar>,
+    ) -> JoinHandle<Output> {
+        let (sender, receiver) = oneshot::channel::<Result<Output, Box<dyn Any + Send>>>();
+        // Closed when the attempt is aborted, which drops the task below.
+        let (abort_guard, mut aborted) = oneshot::channel::<()>();
+        let job: Job = Box::new(move |runtime| {
+            if let Err(TryRecvError::Closed) = aborted.try_recv() {
+                return;
+            }
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                action.run_on(input, env, runtime, aborted)
+            }));
+            // The attempt may have been aborted meanwhile.
+            let _ = sender.send(result);
+        });
+        let sent = self.jobs.lock().unwrap().send(job).is_ok();
+        tokio::spawn(async move {
+            let _abort_guard = abort_guard;
+            match receiver.await {
+                Ok(Ok(output)) => output,
+                Ok(Err(panic)) => std::panic::resume_unwind(panic),
+                Err(_) => {
+                    assert!(sent, "The serial executor stopped.");
+                    panic!("The serial executor dropped the action.")
+                }
+            }
+        })
+    }
+}
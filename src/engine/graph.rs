@@ -23,6 +23,8 @@ task1->task2->task3->task4->task5->task6->task7->task8->task9
 
 */
 
+use std::{cmp::Reverse, collections::BinaryHeap, time::Duration};
+
 use bimap::BiMap;
 
 #[derive(Debug, Clone)]
@@ -35,6 +37,9 @@ pub(crate) struct Graph {
     adj: Vec<Vec<usize>>,
     /// Node's in_degree, used for topological sort
     in_degree: Vec<usize>,
+    /// Shuffles the nodes ready at the same time in the topological sort, they are sorted by
+    /// index otherwise.
+    seed: Option<u64>,
 }
 
 impl Graph {
@@ -45,6 +50,7 @@ impl Graph {
             nodes: BiMap::new(),
             adj: Vec::new(),
             in_degree: Vec::new(),
+            seed: None,
         }
     }
 
@@ -73,6 +79,26 @@ impl Graph {
         self.in_degree[w] += 1;
     }
 
+    /// Order the nodes ready at the same time in the topological sort by a hash of their index
+    /// and `seed`, instead of by index.
+    pub(crate) fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    /// The key deciding which of the nodes ready at the same time comes first in the sequence.
+    fn tie_break(&self, index: usize) -> u64 {
+        match self.seed {
+            // splitmix64, so that close seeds give unrelated orders.
+            Some(seed) => {
+                let mut z = seed ^ (index as u64).wrapping_mul(0x9e3779b97f4a7c15);
+                z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+                z ^ (z >> 31)
+            }
+            None => index as u64,
+        }
+    }
+
     /// Find a task's index by its ID
     pub(crate) fn find_index_by_id(&self, id: &usize) -> Option<usize> {
         self.nodes.get_by_left(id).copied()
@@ -101,25 +127,29 @@ impl Graph {
     ///
     /// 4. Just repeat step 2, 3 until no more zero degree nodes can be generated.
     ///    If all tasks have been executed, then it's a DAG, or there must be a loop in the graph.
+    ///
+    /// Among the zero in-degree nodes, the one with the smallest index is taken first, unless
+    /// the graph has a seed, so the sequence only depends on the graph.
     pub(crate) fn topo_sort(&self) -> Option<Vec<usize>> {
         let mut queue = self
             .in_degree
             .iter()
             .enumerate()
-            .filter_map(|(index, &degree)| if degree == 0 { Some(index) } else { None })
-            .collect::<Vec<_>>();
+            .filter(|(_, &degree)| degree == 0)
+            .map(|(index, _)| Reverse((self.tie_break(index), index)))
+            .collect::<BinaryHeap<_>>();
 
         let mut in_degree = self.in_degree.clone();
 
         let mut sequence = Vec::with_capacity(self.size);
 
-        while let Some(v) = queue.pop() {
+        while let Some(Reverse((_, v))) = queue.pop() {
             sequence.push(v);
 
             for &index in self.adj[v].iter() {
                 in_degree[index] -= 1;
                 if in_degree[index] == 0 {
-                    queue.push(index)
+                    queue.push(Reverse((self.tie_break(index), index)))
                 }
             }
         }
@@ -131,6 +161,18 @@ impl Graph {
         }
     }
 
+    /// Get the length of the longest path starting at each node, given the weight of each node.
+    /// The lengths are indexed like the nodes, they are all zero if the graph has a loop.
+    pub(crate) fn longest_paths(&self, weights: &[Duration]) -> Vec<Duration> {
+        let mut lengths = vec![Duration::ZERO; self.size];
+        // The successors of a node come after it in the sequence, so they are computed first.
+        for &v in self.topo_sort().unwrap_or_default().iter().rev() {
+            let longest = self.adj[v].iter().map(|&w| lengths[w]).max();
+            lengths[v] = weights[v] + longest.unwrap_or_default();
+        }
+        lengths
+    }
+
     /// Get the out degree of a node.
     pub(crate) fn get_node_out_degree(&self, id: &usize) -> usize {
         match self.nodes.get_by_left(id) {
@@ -138,46 +180,6 @@ impl Graph {
             None => 0,
         }
     }
-
-    /// Get all the successors of a node (direct or indirect).
-    /// This function will return a vector of indices of successors (including itself).
-    pub(crate) fn get_node_successors(&self, id: &usize) -> Vec<usize> {
-        match self.nodes.get_by_left(id) {
-            Some(index) => {
-                // initialize a vector to store successors with max possible size
-                let mut successors = Vec::with_capacity(self.adj[*index].len());
-
-                // create a visited array to avoid visiting a node more than once
-                let mut visited = vec![false; self.size];
-
-                // do BFS traversal starting from current node
-
-                // mark the current node as visited and enqueue it
-                visited[*index] = true;
-                successors.push(*index);
-
-                // the index of the queue
-                let mut i_queue = 0;
-
-                // while the queue is not empty
-                while i_queue < successors.len() {
-                    let v = successors[i_queue];
-
-                    for &index in self.adj[v].iter() {
-                        // if not visited, mark it as visited and collect it
-                        if !visited[index] {
-                            visited[index] = true;
-                            successors.push(index);
-                        }
-                    }
-                    i_queue += 1;
-                }
-                successors
-            }
-            // If node not found, return empty vector
-            None => Vec::new(),
-        }
-    }
 }
 
 impl Default for Graph {
@@ -187,6 +189,7 @@ impl Default for Graph {
             nodes: BiMap::new(),
             adj: Vec::new(),
             in_degree: Vec::new(),
+            seed: None,
         }
     }
 }
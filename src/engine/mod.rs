@@ -8,17 +8,62 @@
 //! [`Engine`] stores each Dag in the form of a key-value pair (<name:String,dag:Dag>), and the user
 //! can specify which task to execute by giving the name of the Dag, or follow the order in which
 //! the Dags are added to the Engine , executing each Dag in turn.
+//! The Dags can also be executed concurrently, see [`Engine::run_parallel`], and a Dag can depend
+//! on the results of other Dags, see [`Engine::add_dependency`]. A Dag can be executed repeatedly
+//! on a schedule, see [`Engine::schedule`]. The Dags can be replaced or removed at any time, see
+//! [`Engine::replace_dag`] and [`Engine::remove_dag`].
+//!
+//! The Engine executes the Dags on its own runtime by default. Within an asynchronous service, it
+//! can use the runtime of the service instead, see [`Engine::with_handle`], and each way of executing
+//! the Dags has an asynchronous counterpart, such as [`Engine::async_run_dag`].
 
-pub use dag::{Dag, OutputMessage};
+pub use cache::{CacheError, CachePolicy, CacheStore, FileCacheStore, MemoryCacheStore};
+pub use cancel::CancellationHandle;
+pub use checkpoint::{
+    CheckpointError, CheckpointStore, Checkpointable, FileCheckpointStore, StoredOutput,
+    TaskCheckpoint,
+};
+pub use dag::{Dag, DagMetadata, OutputMessage};
+pub use executor::{Executor, SerialExecutor, TokioExecutor};
 use log::error;
+pub use observer::{DagObserver, LoggingObserver};
+pub(crate) use report::TaskRecord;
+pub use report::{DagOutcome, DagReport, TaskReport, TaskStatus};
+pub use schedule::SchedulingPolicy;
+pub use scheduler::{Clock, ManualClock, MissedRuns, Schedule, ScheduledRun, SystemClock};
 use thiserror::Error;
+#[cfg(feature = "serde")]
+pub use worker::WorkerPool;
 
+mod cache;
+pub(crate) use cache::StableHasher;
+mod cancel;
+mod checkpoint;
+#[cfg(feature = "serde")]
+pub(crate) use checkpoint::{wrapped_tag, wrapping_tag, Codecs};
 mod dag;
+mod executor;
 mod graph;
+mod observer;
+mod report;
+mod schedule;
+mod scheduler;
+#[cfg(feature = "serde")]
+mod worker;
 
-use crate::ParseError;
-use std::{collections::HashMap, sync::Arc};
-use tokio::runtime::Runtime;
+use crate::{task::Content, ParseError};
+use graph::Graph;
+use std::{
+    collections::HashMap,
+    future::{poll_fn, Future},
+    pin::Pin,
+    sync::Arc,
+    task::Poll,
+};
+use tokio::{
+    runtime::{Handle, Runtime},
+    sync::{watch, Semaphore},
+};
 
 /// The Engine. Manage multiple Dags.
 pub struct Engine {
@@ -26,9 +71,23 @@ pub struct Engine {
     /// According to the order in which Dags are added to the Engine, assign a sequence number to each Dag.
     /// Sequence numbers can be used to execute Dags sequentially.
     sequence: HashMap<usize, String>,
-    /// A tokio runtime.
+    /// A tokio runtime, owned unless the Engine was built with [`Engine::with_handle`].
     /// In order to save computer resources, multiple Dags share one runtime.
-    runtime: Runtime,
+    runtime: Option<Runtime>,
+    /// The handle of the runtime executing the Dags.
+    handle: Handle,
+    /// Limits the number of tasks executing at the same time across all Dags.
+    max_parallelism: Option<Arc<Semaphore>>,
+    /// Named resource pools shared by all Dags, with their capacities.
+    resource_pools: HashMap<String, (Arc<Semaphore>, u32)>,
+    /// Observers registered on all Dags.
+    observers: Vec<Arc<dyn DagObserver>>,
+    /// The names of the Dags each Dag depends on, see [`Engine::add_dependency`].
+    dependencies: HashMap<String, Vec<String>>,
+    /// The schedules of the scheduled Dags, see [`Engine::schedule`].
+    schedules: HashMap<String, Schedule>,
+    /// The clock of the scheduler.
+    clock: Arc<dyn Clock>,
 }
 
 /// Errors that may be raised by building and running dag jobs.
@@ -47,46 +106,477 @@ pub enum DagError {
     /// There are no tasks in the job.
     #[error("There are no tasks in the job.")]
     EmptyJob,
+    /// A task requires more of a resource than its pool can provide, or the pool does not exist.
+    #[error("Task[{0}] requires {2} of resource '{1}', which is not available.")]
+    ResourceUnavailable(String, String, u32),
+    /// There is no Dag with the given name in the Engine.
+    #[error("No job named '{0}'.")]
+    DagNotFound(String),
+    /// There is already a Dag with the given name in the Engine.
+    #[error("There is already a job named '{0}'.")]
+    DuplicateDag(String),
+    /// A cron expression cannot be parsed.
+    #[error("Invalid schedule '{0}': {1}.")]
+    InvalidSchedule(String, String),
 }
 
 impl Engine {
+    /// Create an Engine executing the Dags on the runtime of `handle`, instead of a runtime of its
+    /// own. Unlike [`Engine::default`], it can be created and dropped within an asynchronous context.
+    ///
+    /// Its synchronous methods, such as [`Engine::run_dag`], block the current thread until the Dags
+    /// are executed, so they must not be called from the runtime threads, but can be called from
+    /// [`tokio::task::spawn_blocking`]. Its asynchronous methods, such as [`Engine::async_run_dag`],
+    /// can be awaited directly.
+    ///
+    /// # Example
+    /// ```rust
+    /// use dagrs::{Dag, DefaultTask, Engine, Output};
+    /// use tokio::runtime::Handle;
+    ///
+    /// let runtime = tokio::runtime::Runtime::new().unwrap();
+    /// let report = runtime.block_on(async {
+    ///     let mut engine = Engine::with_handle(Handle::current());
+    ///     let task = DefaultTask::with_closure("task", |_input, _env| Output::new(1usize));
+    ///     engine.append_dag("job", Dag::with_tasks(vec![task])).unwrap();
+    ///     tokio::spawn(async move { engine.async_run_dag("job").await })
+    ///         .await
+    ///         .unwrap()
+    /// });
+    /// assert!(report.unwrap().is_success());
+    /// ```
+    pub fn with_handle(handle: Handle) -> Self {
+        Self {
+            dags: HashMap::new(),
+            runtime: None,
+            handle,
+            sequence: HashMap::new(),
+            max_parallelism: None,
+            resource_pools: HashMap::new(),
+            observers: Vec::new(),
+            dependencies: HashMap::new(),
+            schedules: HashMap::new(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
     /// Add a Dag to the Engine and assign a sequence number to the Dag.
-    /// It should be noted that different Dags should specify different names.
-    pub fn append_dag(&mut self, name: &str, mut dag: Dag<'static>) {
+    ///
+    /// Fails if there is already a Dag with this name in the Engine, or if the Dag cannot be
+    /// initialized, for example because its tasks form a cycle, see [`DagError`].
+    pub fn append_dag(&mut self, name: &str, mut dag: Dag<'static>) -> Result<(), DagError> {
+        if self.dags.contains_key(name) {
+            return Err(DagError::DuplicateDag(name.to_string()));
+        }
+        self.prepare(&mut dag)?;
+        self.dags.insert(name.to_string(), dag);
+        let len = self.sequence.len();
+        self.sequence.insert(len + 1, name.to_string());
+        Ok(())
+    }
+
+    /// Remove the Dag `name` from the Engine and return it. Its schedule is removed too, and the
+    /// Dags depending on it no longer wait for it, see [`Engine::add_dependency`]. The following
+    /// Dags move up in the sequence.
+    ///
+    /// # Example
+    /// ```rust
+    /// use dagrs::{Dag, DefaultTask, Engine, Output};
+    ///
+    /// let mut engine = Engine::default();
+    /// for name in ["extract", "load"] {
+    ///     let task = DefaultTask::with_closure(name, |_input, _env| Output::empty());
+    ///     engine.append_dag(name, Dag::with_tasks(vec![task])).unwrap();
+    /// }
+    /// assert!(engine.append_dag("load", Dag::with_tasks(Vec::<DefaultTask>::new())).is_err());
+    /// assert!(engine.remove_dag("extract").is_ok());
+    /// assert_eq!(engine.list_dags(), vec!["load"]);
+    /// assert!(engine.remove_dag("extract").is_err());
+    /// ```
+    pub fn remove_dag(&mut self, name: &str) -> Result<Dag<'static>, DagError> {
+        let dag = self
+            .dags
+            .remove(name)
+            .ok_or_else(|| DagError::DagNotFound(name.to_string()))?;
+        let names: Vec<String> = (1..self.sequence.len() + 1)
+            .map(|seq| self.sequence.remove(&seq).unwrap())
+            .filter(|other| other != name)
+            .collect();
+        self.sequence = (1..).zip(names).collect();
+        self.dependencies.remove(name);
+        for dependencies in self.dependencies.values_mut() {
+            dependencies.retain(|dependency| dependency != name);
+        }
+        self.schedules.remove(name);
+        Ok(dag)
+    }
+
+    /// Replace the Dag `name` by `dag` and return the previous one. The new Dag keeps the place
+    /// of the previous one in the sequence, its dependencies and its schedule.
+    ///
+    /// Fails if there is no Dag with this name in the Engine, or if the new Dag cannot be
+    /// initialized, then the previous Dag is kept.
+    pub fn replace_dag(
+        &mut self,
+        name: &str,
+        mut dag: Dag<'static>,
+    ) -> Result<Dag<'static>, DagError> {
         if !self.dags.contains_key(name) {
-            match dag.init() {
-                Ok(()) => {
-                    self.dags.insert(name.to_string(), dag);
-                    let len = self.sequence.len();
-                    self.sequence.insert(len + 1, name.to_string());
-                }
-                Err(err) => {
-                    error!("Some error occur: {}", err);
-                }
-            }
+            return Err(DagError::DagNotFound(name.to_string()));
+        }
+        self.prepare(&mut dag)?;
+        Ok(self.dags.insert(name.to_string(), dag).unwrap())
+    }
+
+    /// Get the names of the Dags in the Engine, in the order of the sequence.
+    pub fn list_dags(&self) -> Vec<&str> {
+        (1..self.sequence.len() + 1)
+            .map(|seq| self.sequence[&seq].as_str())
+            .collect()
+    }
+
+    /// Get the description, tags and owner of the Dag `name`, see [`DagMetadata`].
+    pub fn get_dag_metadata(&self, name: &str) -> Option<&DagMetadata> {
+        self.dags.get(name).map(|dag| dag.metadata())
+    }
+
+    /// Set the description, tags and owner of the Dag `name`, see [`DagMetadata`].
+    ///
+    /// Fails if there is no Dag with this name in the Engine.
+    pub fn set_dag_metadata(&mut self, name: &str, metadata: DagMetadata) -> Result<(), DagError> {
+        self.dags
+            .get_mut(name)
+            .ok_or_else(|| DagError::DagNotFound(name.to_string()))?
+            .set_metadata(metadata);
+        Ok(())
+    }
+
+    /// Share the limits and observers of the Engine with a Dag, and initialize it.
+    fn prepare(&self, dag: &mut Dag<'static>) -> Result<(), DagError> {
+        dag.set_shared_limits(self.max_parallelism.clone(), &self.resource_pools);
+        self.observers
+            .iter()
+            .for_each(|observer| dag.add_observer(observer.clone()));
+        dag.init()
+    }
+
+    /// Limit the number of tasks executing at the same time across all the Dags of the Engine,
+    /// at least 1. Each Dag can set its own stricter limit too.
+    pub fn set_max_parallelism(&mut self, max_parallelism: usize) {
+        self.max_parallelism = Some(Arc::new(Semaphore::new(max_parallelism.max(1))));
+        self.share_limits();
+    }
+
+    /// Add a named resource pool shared by all the Dags of the Engine. A Dag defining a
+    /// pool with the same name uses its own pool instead. Adding a pool again replaces it in all
+    /// the Dags.
+    ///
+    /// It should be added before the Dags whose tasks consume it, since appending a Dag checks
+    /// that the resources required by its tasks are available.
+    pub fn add_resource_pool(&mut self, name: &str, capacity: u32) {
+        self.resource_pools.insert(
+            name.to_string(),
+            (Arc::new(Semaphore::new(capacity as usize)), capacity),
+        );
+        self.share_limits();
+    }
+
+    /// Register an observer on all the Dags of the Engine, including the ones appended later,
+    /// see [`DagObserver`].
+    pub fn add_observer(&mut self, observer: Arc<dyn DagObserver>) {
+        for dag in self.dags.values_mut() {
+            dag.add_observer(observer.clone());
+        }
+        self.observers.push(observer);
+    }
+
+    fn share_limits(&mut self) {
+        for dag in self.dags.values_mut() {
+            dag.set_shared_limits(self.max_parallelism.clone(), &self.resource_pools);
         }
     }
 
-    /// Given a Dag name, execute this Dag.
-    /// Returns true if the given Dag executes successfully, otherwise false.
-    pub fn run_dag(&mut self, name: &str) -> bool {
-        if let Some(dag) = self.dags.get(name) {
-            self.runtime.block_on(dag.run())
+    /// Given a Dag name, execute this Dag and return its execution report, see [`DagReport`].
+    ///
+    /// The Dag is reset before each execution, so it can be executed repeatedly.
+    ///
+    /// It must not be called from within an asynchronous context, see [`Engine::async_run_dag`].
+    pub fn run_dag(&mut self, name: &str) -> Result<DagReport, DagError> {
+        let handle = self.handle.clone();
+        handle.block_on(self.async_run_dag(name))
+    }
+
+    /// Given a Dag name, execute this Dag asynchronously and return its execution report, see
+    /// [`Engine::run_dag`]. The returned future is `Send`, it can be spawned on any runtime.
+    pub async fn async_run_dag(&mut self, name: &str) -> Result<DagReport, DagError> {
+        if let Some(dag) = self.dags.get_mut(name) {
+            dag.reset();
+            Ok(dag.run().await)
         } else {
             error!("No job named '{}'", name);
-            false
+            Err(DagError::DagNotFound(name.to_string()))
         }
     }
 
     /// Execute all the Dags in the Engine in sequence according to the order numbers of the Dags in
-    /// the sequence from small to large. The return value is the execution status of all tasks.
+    /// the sequence from small to large, each Dag after the Dags it depends on, see
+    /// [`Engine::add_dependency`]. The return value is the execution status of all tasks, in the
+    /// order of the sequence.
+    ///
+    /// It must not be called from within an asynchronous context, see [`Engine::async_run_sequential`].
     pub fn run_sequential(&mut self) -> Vec<bool> {
-        let mut res = Vec::with_capacity(self.sequence.len());
-        for seq in 1..self.sequence.len() + 1 {
-            let name = self.sequence.get(&seq).unwrap().clone();
-            res.push(self.run_dag(name.as_str()));
+        let handle = self.handle.clone();
+        handle.block_on(self.async_run_sequential())
+    }
+
+    /// Execute all the Dags in the Engine in sequence asynchronously, see [`Engine::run_sequential`].
+    pub async fn async_run_sequential(&mut self) -> Vec<bool> {
+        let mut finished: HashMap<String, Finished> = HashMap::new();
+        // The dependencies were checked for cycles when they were added.
+        for name in self.execution_order().unwrap_or_default() {
+            let dependencies = self.finished_dependencies(&name, &finished);
+            let dag = self.dags.get_mut(&name).unwrap();
+            let report = run_after(dag, dependencies).await;
+            finished.insert(name, Finished::of(dag, &report));
         }
-        res
+        (1..self.sequence.len() + 1)
+            .map(|seq| {
+                matches!(
+                    finished.get(&self.sequence[&seq]),
+                    Some(Finished::Succeeded(_))
+                )
+            })
+            .collect()
+    }
+
+    /// Execute all the Dags in the Engine concurrently and return their execution reports by name,
+    /// see [`DagReport`].
+    ///
+    /// At most `max_concurrent` Dags are executed at the same time if given, at least 1, the others
+    /// wait for their turn in the order they were added to the Engine. A Dag also waits for the Dags
+    /// it depends on, see [`Engine::add_dependency`]. The tasks of all the Dags are still subject to
+    /// the parallelism limit and resource pools of the Engine.
+    ///
+    /// # Example
+    /// ```rust
+    /// use dagrs::{Dag, DefaultTask, Engine, Output};
+    ///
+    /// let mut engine = Engine::default();
+    /// for name in ["a", "b", "c"] {
+    ///     let task = DefaultTask::with_closure(name, |_input, _env| Output::new(1usize));
+    ///     engine.append_dag(name, Dag::with_tasks(vec![task])).unwrap();
+    /// }
+    /// let reports = engine.run_parallel(Some(2));
+    /// assert!(reports.values().all(|report| report.is_success()));
+    /// assert_eq!(*engine.get_dag_result::<usize>("b").unwrap(), 1);
+    /// ```
+    ///
+    /// It must not be called from within an asynchronous context, see [`Engine::async_run_parallel`].
+    pub fn run_parallel(&mut self, max_concurrent: Option<usize>) -> HashMap<String, DagReport> {
+        let handle = self.handle.clone();
+        handle.block_on(self.async_run_parallel(max_concurrent))
+    }
+
+    /// Execute all the Dags in the Engine concurrently and asynchronously, see [`Engine::run_parallel`].
+    /// The Dags are executed by the returned future, so they stay in the Engine even if it is dropped.
+    pub async fn async_run_parallel(
+        &mut self,
+        max_concurrent: Option<usize>,
+    ) -> HashMap<String, DagReport> {
+        let slots = Semaphore::new(max_concurrent.map_or(Semaphore::MAX_PERMITS, |max| max.max(1)));
+        // Each Dag publishes how it finished to the Dags depending on it.
+        let order = self.execution_order().unwrap_or_default();
+        let (mut senders, receivers): (HashMap<_, _>, HashMap<_, _>) = order
+            .iter()
+            .map(|name| {
+                let (sender, receiver) = watch::channel(None::<Finished>);
+                ((name.clone(), sender), (name.clone(), receiver))
+            })
+            .unzip();
+        // The Dags are executed in place, so they stay in the Engine whatever happens to them.
+        let (slots, dependencies) = (&slots, &self.dependencies);
+        let mut dags: HashMap<&String, &mut Dag<'static>> = self.dags.iter_mut().collect();
+        let runs = order
+            .iter()
+            .filter_map(|name| dags.remove(name).map(|dag| (name, dag)))
+            .map(|(name, dag)| {
+                let sender = senders.remove(name).unwrap();
+                let dependencies: Vec<_> = dependencies
+                    .get(name)
+                    .into_iter()
+                    .flatten()
+                    .map(|dependency| (dependency.clone(), receivers[dependency].clone()))
+                    .collect();
+                async move {
+                    let mut finished = Vec::with_capacity(dependencies.len());
+                    for (dependency, mut receiver) in dependencies {
+                        // Every sender publishes a result before it is dropped.
+                        let result = receiver
+                            .wait_for(Option::is_some)
+                            .await
+                            .map_or(Finished::Failed, |result| result.clone().unwrap());
+                        finished.push((dependency, result));
+                    }
+                    // The semaphore is never closed, and serves the Dags in order.
+                    let _slot = slots.acquire().await.unwrap();
+                    let report = run_after(dag, finished).await;
+                    sender.send_replace(Some(Finished::of(dag, &report)));
+                    (name.clone(), report)
+                }
+            });
+        join_all(runs).await.into_iter().collect()
+    }
+
+    /// Execute all the Dags in the Engine concurrently, without limiting the number of Dags executed
+    /// at the same time, and return their execution reports by name. See [`Engine::run_parallel`].
+    pub fn run_all(&mut self) -> HashMap<String, DagReport> {
+        self.run_parallel(None)
+    }
+
+    /// Execute all the Dags in the Engine concurrently and asynchronously, see [`Engine::run_all`].
+    pub async fn async_run_all(&mut self) -> HashMap<String, DagReport> {
+        self.async_run_parallel(None).await
+    }
+
+    /// Execute the Dag `name` only once the Dags `dependencies` succeeded, when the Dags are executed
+    /// by [`Engine::run_sequential`], [`Engine::run_parallel`] or [`Engine::run_all`]. A Dag whose
+    /// dependency did not succeed is not executed, its outcome is [`DagOutcome::Skipped`], and so
+    /// are the Dags depending on it.
+    ///
+    /// The final output of each dependency, see [`Dag::get_result`], is set in the environment of
+    /// the Dag under the name of the dependency, for that execution only.
+    ///
+    /// Fails if one of the Dags is not in the Engine, or if the dependencies would form a cycle.
+    ///
+    /// # Example
+    /// ```rust
+    /// use dagrs::{Dag, DefaultTask, Engine, Output};
+    ///
+    /// let mut engine = Engine::default();
+    /// let extract = DefaultTask::with_closure("extract", |_input, _env| Output::new(20usize));
+    /// engine.append_dag("extract", Dag::with_tasks(vec![extract])).unwrap();
+    /// let load = DefaultTask::with_closure("load", |_input, env| {
+    ///     Output::new(env.get::<usize>("extract").unwrap() + 1)
+    /// });
+    /// engine.append_dag("load", Dag::with_tasks(vec![load])).unwrap();
+    /// engine.add_dependency("load", &["extract"]).unwrap();
+    /// assert!(engine.add_dependency("extract", &["load"]).is_err());
+    /// assert_eq!(engine.run_sequential(), vec![true, true]);
+    /// assert_eq!(*engine.get_dag_result::<usize>("load").unwrap(), 21);
+    /// ```
+    pub fn add_dependency(&mut self, name: &str, dependencies: &[&str]) -> Result<(), DagError> {
+        if let Some(missing) = std::iter::once(&name)
+            .chain(dependencies)
+            .find(|dag| !self.dags.contains_key(**dag))
+        {
+            return Err(DagError::DagNotFound(missing.to_string()));
+        }
+        let previous = self.dependencies.get(name).cloned();
+        let entry = self.dependencies.entry(name.to_string()).or_default();
+        for dependency in dependencies {
+            if !entry.iter().any(|known| known == dependency) {
+                entry.push(dependency.to_string());
+            }
+        }
+        if self.execution_order().is_none() {
+            match previous {
+                Some(previous) => self.dependencies.insert(name.to_string(), previous),
+                None => self.dependencies.remove(name),
+            };
+            return Err(DagError::LoopGraph);
+        }
+        Ok(())
+    }
+
+    /// The names of the Dags, each after the Dags it depends on and otherwise in the order they
+    /// were added. `None` if the dependencies form a cycle.
+    fn execution_order(&self) -> Option<Vec<String>> {
+        let names: Vec<&String> = (1..self.sequence.len() + 1)
+            .map(|seq| &self.sequence[&seq])
+            .collect();
+        let indices: HashMap<&str, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (name.as_str(), index))
+            .collect();
+        let mut graph = Graph::new();
+        graph.set_graph_size(names.len());
+        (0..names.len()).for_each(|index| graph.add_node(index));
+        for (name, dependencies) in &self.dependencies {
+            for dependency in dependencies {
+                graph.add_edge(indices[dependency.as_str()], indices[name.as_str()]);
+            }
+        }
+        let sequence = graph.topo_sort()?;
+        Some(
+            sequence
+                .into_iter()
+                .map(|index| names[index].clone())
+                .collect(),
+        )
+    }
+
+    /// How the dependencies of the Dag `name` finished, a dependency not executed yet is failed.
+    fn finished_dependencies(
+        &self,
+        name: &str,
+        finished: &HashMap<String, Finished>,
+    ) -> Vec<(String, Finished)> {
+        self.dependencies
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|dependency| {
+                let result = finished.get(dependency).cloned();
+                (dependency.clone(), result.unwrap_or(Finished::Failed))
+            })
+            .collect()
+    }
+
+    /// Execute the Dag `name` at the times given by `schedule`, once the scheduler runs, see
+    /// [`Engine::run_scheduler`]. It replaces the previous schedule of the Dag.
+    ///
+    /// Fails if the Dag is not in the Engine.
+    pub fn schedule(&mut self, name: &str, schedule: Schedule) -> Result<(), DagError> {
+        if !self.dags.contains_key(name) {
+            return Err(DagError::DagNotFound(name.to_string()));
+        }
+        self.schedules.insert(name.to_string(), schedule);
+        Ok(())
+    }
+
+    /// Set the clock of the scheduler, the system time by default. See [`ManualClock`] for tests.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Execute the scheduled Dags whenever they are due, until `shutdown` completes, then wait for
+    /// the runs in progress and return all the runs, by due time. See [`Schedule`].
+    ///
+    /// The runs of a Dag are executed one after the other, the dependencies between the Dags are
+    /// not considered. It must not be called from within an asynchronous context, see
+    /// [`Engine::async_run_scheduler`].
+    pub fn run_scheduler(
+        &mut self,
+        shutdown: impl Future<Output = ()> + Send,
+    ) -> Vec<ScheduledRun> {
+        let handle = self.handle.clone();
+        handle.block_on(self.async_run_scheduler(shutdown))
+    }
+
+    /// Execute the scheduled Dags asynchronously until `shutdown` completes, see
+    /// [`Engine::run_scheduler`]. The Dags are executed in place, so they stay in the Engine even if
+    /// the returned future is dropped.
+    pub async fn async_run_scheduler(
+        &mut self,
+        shutdown: impl Future<Output = ()> + Send,
+    ) -> Vec<ScheduledRun> {
+        let dags = self
+            .dags
+            .iter_mut()
+            .filter(|(name, _)| self.schedules.contains_key(*name))
+            .collect();
+        scheduler::run_schedules(dags, &self.schedules, self.clock.clone(), shutdown).await
     }
 
     /// Given the name of the Dag, get the execution result of the specified Dag.
@@ -95,12 +585,78 @@ impl Engine {
     }
 }
 
+/// How a Dag finished, as seen by the Dags depending on it.
+#[derive(Clone)]
+enum Finished {
+    /// The Dag succeeded, with its final output.
+    Succeeded(Option<Content>),
+    Failed,
+}
+
+impl Finished {
+    fn of(dag: &Dag, report: &DagReport) -> Self {
+        if report.is_success() {
+            Self::Succeeded(dag.final_output())
+        } else {
+            Self::Failed
+        }
+    }
+}
+
+/// Poll the futures concurrently within the current task until they all complete, and return
+/// their outputs in the order they completed.
+pub(crate) async fn join_all<F: Future>(futures: impl IntoIterator<Item = F>) -> Vec<F::Output> {
+    let mut pending: Vec<Pin<Box<F>>> = futures.into_iter().map(Box::pin).collect();
+    let mut outputs = Vec::with_capacity(pending.len());
+    poll_fn(|cx| {
+        pending.retain_mut(|future| match future.as_mut().poll(cx) {
+            Poll::Ready(output) => {
+                outputs.push(output);
+                false
+            }
+            Poll::Pending => true,
+        });
+        if pending.is_empty() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await;
+    outputs
+}
+
+/// Execute a Dag once its dependencies finished, with their final outputs in its environment.
+/// It is skipped if one of them did not succeed.
+async fn run_after(dag: &mut Dag<'static>, dependencies: Vec<(String, Finished)>) -> DagReport {
+    dag.reset();
+    let mut outputs = Vec::with_capacity(dependencies.len());
+    for (name, finished) in dependencies {
+        match finished {
+            Finished::Succeeded(Some(output)) => outputs.push((name, output)),
+            Finished::Succeeded(None) => {}
+            Finished::Failed => return dag.skipped_report(),
+        }
+    }
+    // The outputs are only in the environment of this execution.
+    dag.run_with_env_contents(outputs).await
+}
+
 impl Default for Engine {
     fn default() -> Self {
-        Self {
-            dags: HashMap::new(),
-            runtime: Runtime::new().unwrap(),
-            sequence: HashMap::new(),
+        let runtime = Runtime::new().unwrap();
+        let mut engine = Self::with_handle(runtime.handle().clone());
+        engine.runtime = Some(runtime);
+        engine
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        // Dropping a runtime waits for its blocking threads, which panics within an asynchronous
+        // context, and the actions still running are aborted anyway.
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
         }
     }
 }
@@ -0,0 +1,171 @@
+//! Lifecycle events of a Dag execution
+//!
+//! # [`DagObserver`]
+//!
+//! An observer is notified of the progress of a dag: when it starts, when each task becomes
+//! ready, starts an attempt and ends, and when the whole dag finishes. All the methods have an
+//! empty default implementation, so an observer only implements the events it cares about.
+//!
+//! Observers are registered with [`Dag::add_observer`](super::Dag::add_observer), or with
+//! [`Engine::add_observer`](super::Engine::add_observer) for all the dags of an engine. They
+//! are called from the threads executing the tasks, so they should return quickly.
+//!
+//! A [`LoggingObserver`] is registered on every dag, it logs the execution with the `log` crate.
+//!
+//! # Example
+//!
+//! ```rust
+//! use dagrs::{Dag, DagObserver, DefaultTask, Output, TaskReport};
+//! use std::sync::{
+//!     atomic::{AtomicUsize, Ordering},
+//!     Arc,
+//! };
+//!
+//! #[derive(Default)]
+//! struct Progress(AtomicUsize);
+//!
+//! impl DagObserver for Progress {
+//!     fn on_task_success(&self, _report: &TaskReport, _output: &Output) {
+//!         self.0.fetch_add(1, Ordering::SeqCst);
+//!     }
+//! }
+//!
+//! let progress = Arc::new(Progress::default());
+//! let task = DefaultTask::with_closure("Task", |_input, _env| Output::empty());
+//! let mut dag = Dag::with_tasks(vec![task]);
+//! dag.add_observer(progress.clone());
+//! assert!(dag.start().unwrap());
+//! assert_eq!(progress.0.load(Ordering::SeqCst), 1);
+//! ```
+
+use log::{debug, error, warn};
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::{
+    dag::OutputMessageContent, DagOutcome, DagReport, OutputMessage, TaskReport, TaskStatus,
+};
+use crate::Output;
+
+/// Receives the lifecycle events of the execution of a dag.
+pub trait DagObserver: Send + Sync {
+    /// The dag starts, `tasks` are the ids and names of its tasks in execution order.
+    fn on_dag_start(&self, _tasks: &[(usize, &str)]) {}
+
+    /// The outputs of all the predecessors of the task are available, the task now waits
+    /// for its resources before starting.
+    fn on_task_ready(&self, _id: usize, _name: &str) {}
+
+    /// An attempt of the task starts, attempts are counted from 1.
+    fn on_task_start(&self, _id: usize, _name: &str, _attempt: u32) {}
+
+    /// The task succeeded with the given output, which may be [`Output::Termination`].
+    fn on_task_success(&self, _report: &TaskReport, _output: &Output) {}
+
+    /// The task failed, timed out or panicked, after all its attempts.
+    fn on_task_failure(&self, _report: &TaskReport) {}
+
+    /// The task will not produce an output, because of an upstream failure, a bypass, a
+    /// termination or a cancellation, see the status of `report`.
+    fn on_task_skipped(&self, _report: &TaskReport) {}
+
+    /// The dag finished, `report` describes the whole execution.
+    fn on_dag_finish(&self, _report: &DagReport) {}
+}
+
+/// Logs the execution of a dag, it is registered on every dag.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LoggingObserver;
+
+impl DagObserver for LoggingObserver {
+    fn on_dag_start(&self, tasks: &[(usize, &str)]) {
+        debug!("[Start]{} -> [End]", {
+            tasks
+                .iter()
+                .map(|(_, name)| *name)
+                .collect::<Vec<&str>>()
+                .join(" -> ")
+        });
+    }
+
+    fn on_task_start(&self, id: usize, name: &str, attempt: u32) {
+        debug!(
+            "Executing task [name: {}, id: {}, attempt: {}]",
+            name, id, attempt
+        );
+    }
+
+    fn on_task_success(&self, report: &TaskReport, _output: &Output) {
+        debug!(
+            "Execution {} [name: {}, id: {}]",
+            match report.status {
+                TaskStatus::Terminated => "terminated",
+                TaskStatus::Restored => "restored",
+                TaskStatus::Cached => "cached",
+                TaskStatus::UpToDate => "up to date",
+                _ => "succeed",
+            },
+            report.name,
+            report.id
+        );
+    }
+
+    fn on_task_failure(&self, report: &TaskReport) {
+        let reason = match report.status {
+            TaskStatus::TimedOut => "timed out",
+            _ => "failed",
+        };
+        error!(
+            "Execution {} [name: {}, id: {}]\nerr: {}",
+            reason,
+            report.name,
+            report.id,
+            report
+                .error
+                .as_deref()
+                .unwrap_or("DAGRS: couldn't parse error message")
+        );
+    }
+
+    fn on_task_skipped(&self, report: &TaskReport) {
+        if report.attempts > 0 {
+            // The task has been aborted while running.
+            warn!(
+                "Execution cancelled [name: {}, id: {}]",
+                report.name, report.id
+            );
+            return;
+        }
+        debug!(
+            "Execution {} [name: {}, id: {}]",
+            match report.status {
+                TaskStatus::Skipped => "skipped",
+                TaskStatus::Bypassed => "bypassed",
+                _ => "terminated",
+            },
+            report.name,
+            report.id
+        );
+    }
+
+    fn on_dag_finish(&self, report: &DagReport) {
+        if report.outcome == DagOutcome::Cancelled {
+            error!("The execution of the dag has been cancelled.");
+        }
+    }
+}
+
+/// Sends the outputs of the succeeded tasks, then [`OutputMessage::Finish`], over a channel.
+pub(crate) struct SenderObserver(pub(crate) UnboundedSender<OutputMessage>);
+
+impl DagObserver for SenderObserver {
+    fn on_task_success(&self, report: &TaskReport, output: &Output) {
+        let _ = self.0.send(OutputMessage::Message(OutputMessageContent {
+            task_name: report.name.clone(),
+            result: output.clone(),
+        }));
+    }
+
+    fn on_dag_finish(&self, _report: &DagReport) {
+        let _ = self.0.send(OutputMessage::Finish);
+    }
+}
@@ -0,0 +1,224 @@
+//! Execution report of a Dag
+//!
+//! # [`DagReport`]
+//!
+//! Running a dag with [`Dag::start_with_report`](super::Dag::start_with_report) returns a
+//! [`DagReport`], which records the overall [`DagOutcome`] and a [`TaskReport`] for each task:
+//! its final [`TaskStatus`], when it started and finished, how many attempts were made, and the
+//! exit code and error message of a failed execution.
+//!
+//! # Example
+//!
+//! ```rust
+//! use dagrs::{Dag, DefaultTask, Output, TaskStatus};
+//!
+//! let a = DefaultTask::with_closure("a", |_input, _env| Output::error("boom".to_string()));
+//! let mut b = DefaultTask::with_closure("b", |_input, _env| Output::empty());
+//! b.set_predecessors(&[&a]);
+//! let mut dag = Dag::with_tasks(vec![a, b]).keep_going();
+//! let report = dag.start_with_report().unwrap();
+//! assert!(!report.is_success());
+//! let a = report.task_by_name("a").unwrap();
+//! assert_eq!(a.status, TaskStatus::Failed);
+//! assert_eq!(a.error.as_deref(), Some("boom"));
+//! assert_eq!(report.task_by_name("b").unwrap().status, TaskStatus::Skipped);
+//! ```
+
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::Output;
+
+/// The final status of a task in a [`DagReport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TaskStatus {
+    /// The task has not been executed.
+    Pending,
+    /// The task returned a normal output.
+    Succeeded,
+    /// The task succeeded in a previous execution, its output was restored from a checkpoint
+    /// instead of executing it again.
+    Restored,
+    /// The output of the task was found in the cache of the dag instead of executing it,
+    /// see [`CacheStore`](super::CacheStore).
+    Cached,
+    /// The task was not executed because its action was up to date, like a makefile target
+    /// newer than its prerequisites, see [`Complex::is_up_to_date`](crate::Complex::is_up_to_date).
+    UpToDate,
+    /// The task returned an error output.
+    Failed,
+    /// The task was not executed because one of its predecessors failed.
+    Skipped,
+    /// The task was not executed because it was not selected by the branch output of a
+    /// predecessor, its condition did not hold, or its trigger rule was not met without any
+    /// failed predecessor. This is not a failure.
+    Bypassed,
+    /// The task was not executed or was aborted, because the dag stopped after an error or was
+    /// cancelled, or because it received or returned [`Output::Termination`].
+    Terminated,
+    /// The task did not finish before its timeout or the deadline of the dag.
+    TimedOut,
+    /// The action of the task panicked.
+    Panicked,
+}
+
+/// The overall outcome of the execution of a dag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DagOutcome {
+    /// All the tasks were executed successfully, or were restored from a checkpoint or the cache,
+    /// or were up to date.
+    Succeeded,
+    /// At least one task failed, timed out or panicked.
+    Failed,
+    /// The timeout of the dag was reached.
+    TimedOut,
+    /// The dag was cancelled through its [`CancellationHandle`](super::CancellationHandle).
+    Cancelled,
+    /// The dag was not executed because a dag it depends on in an [`Engine`](crate::Engine) did
+    /// not succeed, see [`Engine::add_dependency`](crate::Engine::add_dependency).
+    Skipped,
+}
+
+/// What happened to a task during the execution of a dag.
+#[derive(Debug, Clone)]
+pub struct TaskReport {
+    pub id: usize,
+    pub name: String,
+    pub status: TaskStatus,
+    /// When the first attempt started, `None` if the task was never executed.
+    pub started_at: Option<SystemTime>,
+    /// When the task reached its final status.
+    pub finished_at: Option<SystemTime>,
+    /// The time from the start of the first attempt to the final status.
+    pub duration: Option<Duration>,
+    /// The number of executions of the action, including retries.
+    pub attempts: u32,
+    /// The exit code of a failed command.
+    pub exit_code: Option<i32>,
+    /// The error message of a failed, timed out or panicked task.
+    pub error: Option<String>,
+    /// The report of the inner dag of a [`SubDagTask`](crate::SubDagTask).
+    pub sub_dag: Option<Box<DagReport>>,
+}
+
+/// The execution report of a dag.
+#[derive(Debug, Clone)]
+pub struct DagReport {
+    pub outcome: DagOutcome,
+    pub started_at: SystemTime,
+    pub finished_at: SystemTime,
+    pub duration: Duration,
+    /// The reports of all the tasks, in execution order.
+    pub tasks: Vec<TaskReport>,
+}
+
+impl DagReport {
+    /// Whether all the tasks were executed successfully.
+    pub fn is_success(&self) -> bool {
+        self.outcome == DagOutcome::Succeeded
+    }
+
+    /// Get the report of the task with the given id.
+    pub fn task(&self, id: usize) -> Option<&TaskReport> {
+        self.tasks.iter().find(|task| task.id == id)
+    }
+
+    /// Get the report of the first task with the given name.
+    pub fn task_by_name(&self, name: &str) -> Option<&TaskReport> {
+        self.tasks.iter().find(|task| task.name == name)
+    }
+
+    /// The reports of the tasks that failed, timed out or panicked.
+    pub fn failed_tasks(&self) -> impl Iterator<Item = &TaskReport> {
+        self.tasks.iter().filter(|task| {
+            matches!(
+                task.status,
+                TaskStatus::Failed | TaskStatus::TimedOut | TaskStatus::Panicked
+            )
+        })
+    }
+}
+
+/// The execution record of a task, stored in its [`ExecState`](crate::task::ExecState)
+/// while the dag runs.
+#[derive(Debug, Clone)]
+pub(crate) struct TaskRecord {
+    status: TaskStatus,
+    started: Option<(SystemTime, Instant)>,
+    finished_at: Option<SystemTime>,
+    duration: Option<Duration>,
+    attempts: u32,
+    exit_code: Option<i32>,
+    error: Option<String>,
+    sub_dag: Option<DagReport>,
+}
+
+impl TaskRecord {
+    pub(crate) fn new() -> Self {
+        Self {
+            status: TaskStatus::Pending,
+            started: None,
+            finished_at: None,
+            duration: None,
+            attempts: 0,
+            exit_code: None,
+            error: None,
+            sub_dag: None,
+        }
+    }
+
+    pub(crate) fn status(&self) -> TaskStatus {
+        self.status
+    }
+
+    /// Record the start of an attempt.
+    pub(crate) fn start_attempt(&mut self) {
+        if self.started.is_none() {
+            self.started = Some((SystemTime::now(), Instant::now()));
+        }
+        self.attempts += 1;
+    }
+
+    /// Record the report of the inner dag executed by the last attempt.
+    pub(crate) fn set_sub_dag(&mut self, report: DagReport) {
+        self.sub_dag = Some(report);
+    }
+
+    /// Record the final status of the task, only the first one is kept.
+    pub(crate) fn finish(&mut self, status: TaskStatus, output: Option<&Output>) {
+        if self.status != TaskStatus::Pending {
+            return;
+        }
+        self.status = status;
+        self.finished_at = Some(SystemTime::now());
+        self.duration = self.started.map(|(_, instant)| instant.elapsed());
+        if let Some(output) = output {
+            if let Output::ErrWithExitCode(code, _) = output {
+                self.exit_code = *code;
+            }
+            self.error = output.get_err();
+        }
+    }
+
+    /// Record the final status of the task with an error message.
+    pub(crate) fn finish_with_error(&mut self, status: TaskStatus, error: String) {
+        if self.status == TaskStatus::Pending {
+            self.finish(status, None);
+            self.error = Some(error);
+        }
+    }
+
+    pub(crate) fn to_report(&self, id: usize, name: &str) -> TaskReport {
+        TaskReport {
+            id,
+            name: name.to_string(),
+            status: self.status,
+            started_at: self.started.map(|(time, _)| time),
+            finished_at: self.finished_at,
+            duration: self.duration,
+            attempts: self.attempts,
+            exit_code: self.exit_code,
+            error: self.error.clone(),
+            sub_dag: self.sub_dag.clone().map(Box::new),
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Scheduling of the ready tasks
+//!
+//! # [`SchedulingPolicy`]
+//!
+//! A task is ready once its predecessors are done, it then waits for a slot of the parallelism
+//! limits and for the resources it declares, see [`Dag::set_max_parallelism`](super::Dag::set_max_parallelism)
+//! and [`Dag::add_resource_pool`](super::Dag::add_resource_pool). The scheduling policy of the dag
+//! decides which of the ready tasks gets them first:
+//! - [`SchedulingPolicy::Fifo`], the default, serves the tasks in the order they became ready.
+//! - [`SchedulingPolicy::Priority`] serves the tasks with the highest [`Task::priority`](crate::Task::priority) first.
+//! - [`SchedulingPolicy::CriticalPath`] also serves the tasks with the highest priority first, then
+//!   the ones starting the longest chain of tasks, so that the longest chain is not delayed by
+//!   shorter ones. The length of a chain is the sum of the durations of its tasks, either estimated
+//!   by [`Task::estimated_duration`](crate::Task::estimated_duration) or measured during the previous
+//!   execution of the dag, see [`Dag::record_durations`](super::Dag::record_durations).
+//!
+//! Under the last two policies, a ready task waits while a task of higher rank waits for its slot
+//! or resources, even if the resources it needs are available. Without any parallelism limit or
+//! resource, all the ready tasks start at once and the policy has no effect.
+//!
+//! # Example
+//!
+//! ```rust
+//! use dagrs::{Dag, DefaultTask, Output, SchedulingPolicy};
+//!
+//! let mut urgent = DefaultTask::with_closure("urgent", |_input, _env| Output::empty());
+//! urgent.set_priority(10);
+//! let later = DefaultTask::with_closure("later", |_input, _env| Output::empty());
+//! let mut dag = Dag::with_tasks(vec![urgent, later]);
+//! dag.set_max_parallelism(1);
+//! dag.set_scheduling_policy(SchedulingPolicy::Priority);
+//! assert!(dag.start().unwrap());
+//! ```
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap},
+    fmt::Display,
+    pin::pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+
+/// Decides which of the ready tasks of a dag is started first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SchedulingPolicy {
+    /// The tasks are started in the order they became ready.
+    #[default]
+    Fifo,
+    /// The tasks with the highest priority are started first.
+    Priority,
+    /// The tasks with the highest priority are started first, then the ones starting the longest
+    /// chain of tasks.
+    CriticalPath,
+}
+
+impl FromStr for SchedulingPolicy {
+    type Err = String;
+
+    /// Parse the snake case name of a policy, such as `critical_path`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fifo" => Ok(Self::Fifo),
+            "priority" => Ok(Self::Priority),
+            "critical_path" => Ok(Self::CriticalPath),
+            _ => Err(format!("unknown scheduling policy '{}'", s)),
+        }
+    }
+}
+
+impl Display for SchedulingPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Fifo => "fifo",
+            Self::Priority => "priority",
+            Self::CriticalPath => "critical_path",
+        })
+    }
+}
+
+/// The rank of a task, the priority then the length of the longest chain it starts. The tasks of
+/// higher rank are served first.
+pub(crate) type Rank = (i32, Duration);
+
+/// The ready tasks waiting for their turn to acquire their permits, by rank then arrival order.
+///
+/// A task releases its permits once it finished, before its successors are woken up. So that a
+/// waiting task of lower rank does not take them first, the successors whose predecessors are all
+/// done keep their place in the queue as soon as the last predecessor finished.
+pub(crate) struct ReadyQueue {
+    waiting: Mutex<Waiting>,
+    /// Notified each time the first waiting task changes.
+    notify: Notify,
+    /// The rank of each task of the dag.
+    ranks: HashMap<usize, Rank>,
+    /// The successors of each task of the dag.
+    successors: HashMap<usize, Vec<usize>>,
+    /// The number of predecessors of each task that did not finish yet.
+    unfinished: HashMap<usize, AtomicUsize>,
+}
+
+type Entry = (Reverse<Rank>, u64);
+
+#[derive(Default)]
+struct Waiting {
+    next_arrival: u64,
+    entries: BTreeSet<Entry>,
+    /// The places kept for the tasks that became ready but did not ask for their turn yet.
+    reserved: HashMap<usize, Entry>,
+}
+
+impl Waiting {
+    fn enter(&mut self, rank: Rank) -> Entry {
+        let entry = (Reverse(rank), self.next_arrival);
+        self.next_arrival += 1;
+        self.entries.insert(entry);
+        entry
+    }
+}
+
+impl ReadyQueue {
+    pub(crate) fn new(ranks: HashMap<usize, Rank>, successors: HashMap<usize, Vec<usize>>) -> Self {
+        let mut unfinished: HashMap<usize, AtomicUsize> = HashMap::new();
+        for id in successors.values().flatten() {
+            *unfinished.entry(*id).or_default().get_mut() += 1;
+        }
+        Self {
+            waiting: Mutex::default(),
+            notify: Notify::new(),
+            ranks,
+            successors,
+            unfinished,
+        }
+    }
+
+    /// Record that a task reached its final status, its successors whose predecessors are all
+    /// done keep their place in the queue.
+    pub(crate) fn finished(&self, id: usize) {
+        let Some(successors) = self.successors.get(&id) else {
+            return;
+        };
+        for successor in successors {
+            if self.unfinished[successor].fetch_sub(1, Ordering::AcqRel) == 1 {
+                let mut waiting = self.waiting.lock().unwrap();
+                let entry = waiting.enter(self.ranks[successor]);
+                waiting.reserved.insert(*successor, entry);
+                drop(waiting);
+                self.notify.notify_waiters();
+            }
+        }
+    }
+
+    /// Give up the place kept for a task, which does not need a turn.
+    pub(crate) fn leave(&self, id: usize) {
+        let mut waiting = self.waiting.lock().unwrap();
+        if let Some(entry) = waiting.reserved.remove(&id) {
+            waiting.entries.remove(&entry);
+            drop(waiting);
+            self.notify.notify_waiters();
+        }
+    }
+
+    /// Acquire the permits of a task once no task of higher rank, or of the same rank arrived
+    /// earlier, is waiting. A task overtaken by a task of higher rank while it waits for its permits
+    /// gives them up and waits for its turn again.
+    pub(crate) async fn acquire(
+        &self,
+        id: usize,
+        rank: Rank,
+        permits: Vec<(Arc<Semaphore>, u32)>,
+    ) -> Vec<OwnedSemaphorePermit> {
+        let entry = {
+            let mut waiting = self.waiting.lock().unwrap();
+            match waiting.reserved.remove(&id) {
+                Some(entry) => entry,
+                None => waiting.enter(rank),
+            }
+        };
+        // Leaves the queue even if the wait is cancelled.
+        let _place = Place { queue: self, entry };
+        loop {
+            self.wait_until(|first| first == Some(&entry)).await;
+            tokio::select! {
+                // A successor of the task releasing the permits may overtake this task.
+                biased;
+                _ = self.wait_until(|first| first != Some(&entry)) => continue,
+                permits = acquire_permits(permits.clone()) => return permits,
+            }
+        }
+    }
+
+    /// Wait until the first waiting task satisfies `condition`.
+    async fn wait_until(&self, condition: impl Fn(Option<&Entry>) -> bool) {
+        loop {
+            let mut notified = pin!(self.notify.notified());
+            // Register before checking, so that a notification sent in between is not missed.
+            notified.as_mut().enable();
+            if condition(self.waiting.lock().unwrap().entries.first()) {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// The place of a task in a [`ReadyQueue`], the next task gets its turn once it is dropped.
+struct Place<'a> {
+    queue: &'a ReadyQueue,
+    entry: Entry,
+}
+
+impl Drop for Place<'_> {
+    fn drop(&mut self) {
+        self.queue
+            .waiting
+            .lock()
+            .unwrap()
+            .entries
+            .remove(&self.entry);
+        self.queue.notify.notify_waiters();
+    }
+}
+
+/// Acquire the permits required by a task, in the given order.
+pub(crate) async fn acquire_permits(
+    permits: Vec<(Arc<Semaphore>, u32)>,
+) -> Vec<OwnedSemaphorePermit> {
+    let mut acquired = Vec::with_capacity(permits.len());
+    for (semaphore, amount) in permits {
+        // The semaphores are never closed.
+        acquired.push(semaphore.acquire_many_owned(amount).await.unwrap());
+    }
+    acquired
+}
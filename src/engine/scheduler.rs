@@ -0,0 +1,590 @@
+//! Scheduled executions of the Dags of an Engine
+//!
+//! # [`Schedule`]
+//!
+//! A Dag of an [`Engine`](super::Engine) can be executed repeatedly, at the times given by a
+//! [`Schedule`], see [`Engine::schedule`](super::Engine::schedule). A schedule is either a fixed
+//! interval, see [`Schedule::every`], or a cron expression, see [`Schedule::cron`].
+//!
+//! The scheduler runs until it is shut down, see [`Engine::run_scheduler`](super::Engine::run_scheduler).
+//! The runs of a Dag are executed one after the other, since they share its tasks. A run due while
+//! the previous ones are still in progress is queued until they finish, unless the queue of the Dag
+//! is full, see [`Schedule::set_max_queued_runs`], then it is dropped. Once a Dag is cancelled, see
+//! [`Dag::cancellation_handle`], its queued runs are dropped too.
+//!
+//! The runs missed while the scheduler was not running, or could not keep up, are handled by the
+//! [`MissedRuns`] policy of the schedule: by default only the latest of them is executed.
+//!
+//! # [`Clock`]
+//!
+//! The scheduler reads the time and waits for the next run through a [`Clock`]. [`SystemClock`], the
+//! default, follows the system time, and [`ManualClock`] is only moved forward explicitly, so that the
+//! schedules can be tested without waiting, see [`Engine::set_clock`](super::Engine::set_clock).
+//!
+//! # Example
+//!
+//! ```rust
+//! use dagrs::{Dag, DefaultTask, Engine, ManualClock, Output, Schedule};
+//! use std::{sync::Arc, time::{Duration, SystemTime}};
+//!
+//! let mut engine = Engine::default();
+//! let task = DefaultTask::with_closure("report", |_input, _env| Output::empty());
+//! engine.append_dag("hourly", Dag::with_tasks(vec![task])).unwrap();
+//!
+//! let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+//! engine.set_clock(clock.clone());
+//! engine.schedule("hourly", Schedule::cron("0 * * * *").unwrap()).unwrap();
+//! // Move the clock forward by three hours and a half, then stop the scheduler.
+//! let runs = engine.run_scheduler(async move {
+//!     clock.advance(Duration::from_secs(12600));
+//!     tokio::task::yield_now().await;
+//! });
+//! // The runs due at 1:00 and 2:00 were missed, only the latest one is executed.
+//! assert_eq!(runs.len(), 1);
+//! assert_eq!(runs[0].scheduled_at, SystemTime::UNIX_EPOCH + Duration::from_secs(10800));
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use log::{info, warn};
+use tokio::sync::{mpsc, watch};
+
+use super::{Dag, DagError, DagReport};
+
+/// The source of the time of a scheduler.
+#[async_trait]
+pub trait Clock: Send + Sync {
+    /// Get the current time.
+    fn now(&self) -> SystemTime;
+    /// Wait until the current time is `deadline` or later.
+    async fn sleep_until(&self, deadline: SystemTime);
+}
+
+/// A clock following the system time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    async fn sleep_until(&self, deadline: SystemTime) {
+        // The system time may be adjusted meanwhile, the scheduler reads it again once woken up.
+        let duration = deadline
+            .duration_since(SystemTime::now())
+            .unwrap_or_default();
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// A clock whose time only changes when it is moved forward explicitly, for tests.
+pub struct ManualClock {
+    now: watch::Sender<SystemTime>,
+}
+
+impl ManualClock {
+    /// Create a clock starting at `now`.
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: watch::Sender::new(now),
+        }
+    }
+
+    /// Move the clock forward by `duration`, waking up the schedulers whose next run is due.
+    pub fn advance(&self, duration: Duration) {
+        self.now.send_modify(|now| *now += duration);
+    }
+
+    /// Set the time of the clock, it is not moved backward.
+    pub fn set(&self, time: SystemTime) {
+        self.now.send_if_modified(|now| {
+            let later = time > *now;
+            if later {
+                *now = time;
+            }
+            later
+        });
+    }
+}
+
+#[async_trait]
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.borrow()
+    }
+
+    async fn sleep_until(&self, deadline: SystemTime) {
+        // The sender lives as long as the clock.
+        let _ = self.now.subscribe().wait_for(|now| *now >= deadline).await;
+    }
+}
+
+/// What to do with the runs of a schedule that are due at the same time, because they were missed
+/// while the scheduler was not running or was late.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MissedRuns {
+    /// Only the latest run is executed, the others are skipped.
+    #[default]
+    Skip,
+    /// All the runs are executed, in the order they were due, up to the latest 1000 of them.
+    CatchUp,
+}
+
+/// The maximum number of missed runs executed at once with [`MissedRuns::CatchUp`], the earlier
+/// ones are skipped.
+const MAX_CAUGHT_UP_RUNS: usize = 1000;
+
+/// When a Dag of an Engine is executed, see [`Engine::schedule`](super::Engine::schedule).
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    trigger: Trigger,
+    missed_runs: MissedRuns,
+    max_queued_runs: usize,
+    start: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone)]
+enum Trigger {
+    Interval(Duration),
+    Cron(Cron),
+}
+
+impl Schedule {
+    /// Execute the Dag every `interval`, at least a millisecond, starting one interval after the
+    /// start of the schedule.
+    pub fn every(interval: Duration) -> Self {
+        Self::with_trigger(Trigger::Interval(interval.max(Duration::from_millis(1))))
+    }
+
+    /// Execute the Dag at the times matching a cron expression, in UTC.
+    ///
+    /// The expression has five fields: minute (0-59), hour (0-23), day of the month (1-31), month
+    /// (1-12 or `jan`-`dec`) and day of the week (0-7 or `sun`-`sat`, both 0 and 7 are Sunday). Each
+    /// field is `*`, a value, a range `a-b`, or a comma separated list of them, and a step `/n` can
+    /// follow `*` or a range. When both the day of the month and the day of the week are restricted,
+    /// a day matching either is a match. `@yearly`, `@monthly`, `@weekly`, `@daily` and `@hourly`
+    /// are also accepted.
+    ///
+    /// # Example
+    /// ```rust
+    /// use dagrs::Schedule;
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// // At 2:30 on weekdays.
+    /// let schedule = Schedule::cron("30 2 * * mon-fri").unwrap();
+    /// // 1970-01-01 was a Thursday.
+    /// let next = schedule.next_after(SystemTime::UNIX_EPOCH).unwrap();
+    /// assert_eq!(next, SystemTime::UNIX_EPOCH + Duration::from_secs(9000));
+    /// assert!(Schedule::cron("60 * * * *").is_err());
+    /// ```
+    pub fn cron(expression: &str) -> Result<Self, DagError> {
+        Cron::parse(expression)
+            .map(|cron| Self::with_trigger(Trigger::Cron(cron)))
+            .map_err(|err| DagError::InvalidSchedule(expression.to_string(), err))
+    }
+
+    fn with_trigger(trigger: Trigger) -> Self {
+        Self {
+            trigger,
+            missed_runs: MissedRuns::default(),
+            max_queued_runs: 1,
+            start: None,
+        }
+    }
+
+    /// Set what to do with the runs missed while the scheduler was not running or was late.
+    /// By default only the latest of them is executed.
+    pub fn set_missed_runs(&mut self, missed_runs: MissedRuns) {
+        self.missed_runs = missed_runs;
+    }
+
+    /// Set the maximum number of runs of the Dag in its queue, including the one executing, at
+    /// least 1. The runs are executed one after the other, so it does not execute more runs at the
+    /// same time: a run due while the queue is full is dropped. The default is 1, a run is dropped
+    /// while the previous one is executing.
+    pub fn set_max_queued_runs(&mut self, max_queued_runs: usize) {
+        self.max_queued_runs = max_queued_runs.max(1);
+    }
+
+    /// Set the time after which the runs are due. By default it is the time the scheduler starts,
+    /// an earlier time executes the runs missed since then, according to [`Schedule::set_missed_runs`].
+    pub fn set_start(&mut self, start: SystemTime) {
+        self.start = Some(start);
+    }
+
+    /// Get the time of the first run due strictly after `time`, if any. An interval is counted from
+    /// the start of the schedule, or from `time` if the start is not set.
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        match &self.trigger {
+            Trigger::Interval(interval) => {
+                let start = self.start.unwrap_or(time);
+                let elapsed = time.duration_since(start).unwrap_or_default();
+                occurrence(
+                    start,
+                    *interval,
+                    elapsed.as_nanos() / interval.as_nanos() + 1,
+                )
+            }
+            Trigger::Cron(cron) => cron.next_after(time),
+        }
+    }
+}
+
+/// A run of a scheduled Dag.
+#[derive(Debug, Clone)]
+pub struct ScheduledRun {
+    /// The name of the Dag.
+    pub dag: String,
+    /// The time the run was due.
+    pub scheduled_at: SystemTime,
+    /// The report of the execution, `None` if the run was dropped because the queue of the Dag
+    /// was full, or the Dag was cancelled, see [`Dag::cancellation_handle`].
+    pub report: Option<DagReport>,
+}
+
+/// The due times of the next runs of a Dag.
+struct Timeline<'a> {
+    schedule: &'a Schedule,
+    next: Option<SystemTime>,
+}
+
+impl Timeline<'_> {
+    /// Take the runs due at `now`, keeping only the latest unless they are caught up, and the
+    /// number of the earlier ones skipped.
+    fn take_due(&mut self, now: SystemTime) -> (Vec<SystemTime>, u128) {
+        let Some(next) = self.next.filter(|next| *next <= now) else {
+            return (Vec::new(), 0);
+        };
+        let kept = match self.schedule.missed_runs {
+            MissedRuns::Skip => 1,
+            MissedRuns::CatchUp => MAX_CAUGHT_UP_RUNS,
+        };
+        match &self.schedule.trigger {
+            // The occurrences are computed directly, however long the scheduler was late.
+            Trigger::Interval(interval) => {
+                let last =
+                    now.duration_since(next).unwrap_or_default().as_nanos() / interval.as_nanos();
+                let first = (last + 1).saturating_sub(kept as u128);
+                self.next = occurrence(next, *interval, last + 1);
+                let due = (first..=last)
+                    .filter_map(|count| occurrence(next, *interval, count))
+                    .collect();
+                (due, first)
+            }
+            Trigger::Cron(_) => {
+                let mut due = VecDeque::with_capacity(kept);
+                let mut skipped = 0;
+                while let Some(next) = self.next.filter(|next| *next <= now) {
+                    if due.len() == kept {
+                        due.pop_front();
+                        skipped += 1;
+                    }
+                    due.push_back(next);
+                    self.next = self.schedule.next_after(next);
+                }
+                (due.into(), skipped)
+            }
+        }
+    }
+}
+
+/// The time `count` intervals after `start`, `None` if it cannot be represented.
+fn occurrence(start: SystemTime, interval: Duration, count: u128) -> Option<SystemTime> {
+    let offset = interval.as_nanos().checked_mul(count)?;
+    start.checked_add(Duration::from_nanos(u64::try_from(offset).ok()?))
+}
+
+/// Execute the scheduled Dags until `shutdown` completes, then wait for the runs in progress, and
+/// return the runs sorted by due time. The Dags are executed in place, within the current task.
+pub(crate) async fn run_schedules(
+    dags: Vec<(&String, &mut Dag<'static>)>,
+    schedules: &HashMap<String, Schedule>,
+    clock: Arc<dyn Clock>,
+    shutdown: impl Future<Output = ()> + Send,
+) -> Vec<ScheduledRun> {
+    let start = clock.now();
+    let mut timelines = Vec::with_capacity(dags.len());
+    let mut workers = Vec::with_capacity(dags.len());
+    for (name, dag) in dags {
+        let schedule = &schedules[name];
+        let first = schedule.next_after(schedule.start.unwrap_or(start));
+        let (sender, mut receiver) = mpsc::unbounded_channel::<SystemTime>();
+        let in_progress = Arc::new(AtomicUsize::new(0));
+        timelines.push((
+            name,
+            Timeline {
+                schedule,
+                next: first,
+            },
+            sender,
+            in_progress.clone(),
+        ));
+        workers.push(async move {
+            let mut runs = Vec::new();
+            while let Some(scheduled_at) = receiver.recv().await {
+                // A cancelled Dag keeps its state, its queued runs are dropped instead of executed
+                // with the fresh cancellation handle given by `reset`.
+                let report = if dag.cancellation_handle().is_cancelled() {
+                    warn!(
+                        "Dropping the run of '{}' due at {:?}, the dag is cancelled.",
+                        name, scheduled_at
+                    );
+                    None
+                } else {
+                    dag.reset();
+                    Some(dag.run().await)
+                };
+                in_progress.fetch_sub(1, Ordering::AcqRel);
+                runs.push(ScheduledRun {
+                    dag: name.clone(),
+                    scheduled_at,
+                    report,
+                });
+            }
+            runs
+        });
+    }
+
+    let timer = async move {
+        let mut runs = Vec::new();
+        let mut shutdown = std::pin::pin!(shutdown);
+        loop {
+            let now = clock.now();
+            for (name, timeline, sender, in_progress) in &mut timelines {
+                let (due, skipped) = timeline.take_due(now);
+                if skipped > 0 && timeline.schedule.missed_runs == MissedRuns::CatchUp {
+                    warn!(
+                        "Skipping {} missed runs of '{}', only the latest {} are caught up.",
+                        skipped, name, MAX_CAUGHT_UP_RUNS
+                    );
+                }
+                for scheduled_at in due {
+                    if in_progress.load(Ordering::Acquire) < timeline.schedule.max_queued_runs {
+                        info!("Queueing the run of '{}' due at {:?}.", name, scheduled_at);
+                        in_progress.fetch_add(1, Ordering::AcqRel);
+                        // The worker only stops once the sender is dropped.
+                        sender.send(scheduled_at).unwrap();
+                    } else {
+                        warn!(
+                            "Dropping the run of '{}' due at {:?}, the queue is full.",
+                            name, scheduled_at
+                        );
+                        runs.push(ScheduledRun {
+                            dag: name.to_string(),
+                            scheduled_at,
+                            report: None,
+                        });
+                    }
+                }
+            }
+            let wake = timelines
+                .iter()
+                .filter_map(|(_, timeline, _, _)| timeline.next)
+                .min();
+            tokio::select! {
+                // The runs due when the scheduler is shut down are still queued.
+                biased;
+                _ = async {
+                    match wake {
+                        Some(wake) => clock.sleep_until(wake).await,
+                        None => std::future::pending().await,
+                    }
+                } => continue,
+                _ = &mut shutdown => break,
+            }
+        }
+        // Dropping the senders stops the workers once their queues are empty.
+        drop(timelines);
+        runs
+    };
+
+    let (mut runs, worker_runs) = tokio::join!(timer, super::join_all(workers));
+    runs.extend(worker_runs.into_iter().flatten());
+    runs.sort_by(|a, b| (a.scheduled_at, &a.dag).cmp(&(b.scheduled_at, &b.dag)));
+    runs
+}
+
+/// A parsed cron expression, each field as the set of its matching values.
+#[derive(Debug, Clone)]
+struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of the month, or the day of the week, is `*`.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+impl Cron {
+    fn parse(expression: &str) -> Result<Self, String> {
+        let expression = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expression => expression,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!("expected 5 fields, found {}", fields.len()));
+        };
+        let mut weekday_set = parse_field(weekdays, 0, 7, &WEEKDAYS)?;
+        // 7 is Sunday too.
+        if weekday_set & 1 << 7 != 0 {
+            weekday_set = weekday_set & !(1 << 7) | 1;
+        }
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59, &[])?,
+            hours: parse_field(hours, 0, 23, &[])?,
+            days: parse_field(days, 1, 31, &[])?,
+            months: parse_field(months, 1, 12, &MONTHS)?,
+            weekdays: weekday_set,
+            any_day: days.starts_with('*'),
+            any_weekday: weekdays.starts_with('*'),
+        })
+    }
+
+    fn day_matches(&self, day: u32, weekday: u32) -> bool {
+        let day = self.days & 1 << day != 0;
+        let weekday = self.weekdays & 1 << weekday != 0;
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let seconds = time.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+        let mut minute = seconds.div_euclid(60) + 1;
+        // A day such as February 29 matches at least once every 8 years.
+        let limit = minute + 9 * 366 * 1440;
+        while minute < limit {
+            let day = minute.div_euclid(1440);
+            let (year, month, day_of_month) = civil_from_days(day);
+            if self.months & 1 << month == 0 {
+                let (year, month) = if month == 12 {
+                    (year + 1, 1)
+                } else {
+                    (year, month + 1)
+                };
+                minute = days_from_civil(year, month, 1) * 1440;
+                continue;
+            }
+            let weekday = (day + 4).rem_euclid(7) as u32;
+            if !self.day_matches(day_of_month, weekday) {
+                minute = (day + 1) * 1440;
+                continue;
+            }
+            let hour = minute.rem_euclid(1440) / 60;
+            if self.hours & 1 << hour == 0 {
+                minute = day * 1440 + (hour + 1) * 60;
+                continue;
+            }
+            if self.minutes & 1 << minute.rem_euclid(60) == 0 {
+                minute += 1;
+                continue;
+            }
+            return Some(UNIX_EPOCH + Duration::from_secs(minute as u64 * 60));
+        }
+        None
+    }
+}
+
+/// Parse a field of a cron expression into the set of its values, as a bit mask.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |value: &str| -> Result<u32, String> {
+        let parsed = match names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(value))
+        {
+            Some(index) => index as u32 + min,
+            None => value
+                .parse()
+                .map_err(|_| format!("invalid value '{}'", value))?,
+        };
+        if (min..=max).contains(&parsed) {
+            Ok(parsed)
+        } else {
+            Err(format!("value {} out of range {}-{}", parsed, min, max))
+        }
+    };
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or(format!("invalid step '{}'", step))?;
+                (range, Some(step))
+            }
+            None => (part, None),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (value(first)?, value(last)?),
+            None if step.is_some() => return Err(format!("a step needs a range in '{}'", part)),
+            None => {
+                let value = value(range)?;
+                (value, value)
+            }
+        };
+        if first > last {
+            return Err(format!("empty range '{}'", range));
+        }
+        for value in (first..=last).step_by(step.unwrap_or(1) as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+/// The year, month and day of a number of days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The number of days since 1970-01-01 of a date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let shifted_month = i64::from((month + 9) % 12);
+    let day_of_year = (153 * shifted_month + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
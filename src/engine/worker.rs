@@ -0,0 +1,411 @@
+//! Execution of the actions in worker processes
+//!
+//! # [`WorkerPool`]
+//!
+//! A [`WorkerPool`] is an [`Executor`] running the actions of the tasks in a pool of worker
+//! processes, so that a panic, a crash or a memory leak of an untrusted action does not affect
+//! the process executing the dag. A worker whose action panicked exits, the task is then reported
+//! as [`TaskStatus::Panicked`](crate::TaskStatus::Panicked), as is a task whose worker crashed.
+//! A timed out or cancelled attempt kills its worker.
+//!
+//! The workers are started from the current executable, unless another command is given with
+//! [`WorkerPool::with_command`]. They are recognized by the `DAGRS_WORKER` environment variable:
+//! early in its `main` function, the program calls [`WorkerPool::serve`] with the actions of the
+//! tasks by name, which executes the requests of the pool until it is dropped and returns `true`.
+//! In any other process, it returns `false` at once.
+//!
+//! The input of an action and the serializable variables of the environment are sent to the
+//! worker, see [`Serializable`](crate::Serializable) and [`EnvVar::set_serializable`]. The input
+//! must be serializable, and so must the output of the action: their types are decoded with a
+//! [`ContentRegistry`]. A worker can only return a normal output, a branch, an error or a
+//! termination, a task cannot expand into child tasks in a worker.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use dagrs::{Dag, DefaultTask, Output, Task, WorkerPool};
+//! use std::sync::Arc;
+//!
+//! fn tasks() -> Vec<DefaultTask> {
+//!     vec![DefaultTask::with_closure("untrusted", |_input, _env| {
+//!         Output::serializable(42usize)
+//!     })]
+//! }
+//!
+//! let actions = tasks()
+//!     .iter()
+//!     .map(|task| (task.name().to_string(), task.action()))
+//!     .collect();
+//! if WorkerPool::serve(actions, &Default::default()) {
+//!     return;
+//! }
+//! let mut dag = Dag::with_tasks(tasks());
+//! dag.set_executor(Arc::new(WorkerPool::new(4)));
+//! assert!(dag.start().unwrap());
+//! ```
+
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    io::{self, BufRead, Write},
+    panic::AssertUnwindSafe,
+    path::PathBuf,
+    process::Stdio,
+    sync::{Arc, Mutex},
+};
+
+use log::info;
+use serde::{de::DeserializeSeed, Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    process::{Child, ChildStdin, ChildStdout, Command},
+    sync::Semaphore,
+    task::JoinHandle,
+};
+
+use super::{dag::panic_message, Executor};
+use crate::{task::Content, Action, ContentRegistry, EnvVar, Input, Output};
+
+/// The environment variable set in the worker processes.
+const WORKER_VAR: &str = "DAGRS_WORKER";
+
+/// Precedes the replies of a worker on its standard output, the other outputs are logged.
+const REPLY_PREFIX: &str = "\u{1e}dagrs ";
+
+/// Runs the actions of the tasks in a pool of worker processes.
+pub struct WorkerPool {
+    program: PathBuf,
+    args: Vec<OsString>,
+    /// The types of the outputs returned by the workers.
+    registry: Arc<ContentRegistry>,
+    /// A worker is replaced after executing this number of actions.
+    max_tasks_per_worker: Option<usize>,
+    /// Limits the number of workers executing an action at the same time.
+    slots: Arc<Semaphore>,
+    /// The workers waiting for an action.
+    idle: Arc<Mutex<Vec<Worker>>>,
+}
+
+impl WorkerPool {
+    /// Create a pool of at most `size` workers started from the current executable, at least 1.
+    pub fn new(size: usize) -> Self {
+        let program = std::env::current_exe().expect("The current executable is not available.");
+        Self::with_command(size, program, Vec::<OsString>::new())
+    }
+
+    /// Create a pool of at most `size` workers started with the given command, at least 1.
+    pub fn with_command(
+        size: usize,
+        program: impl Into<PathBuf>,
+        args: impl IntoIterator<Item = impl Into<OsString>>,
+    ) -> Self {
+        Self {
+            program: program.into(),
+            args: args.into_iter().map(Into::into).collect(),
+            registry: Arc::new(ContentRegistry::default()),
+            max_tasks_per_worker: None,
+            slots: Arc::new(Semaphore::new(size.max(1))),
+            idle: Arc::default(),
+        }
+    }
+
+    /// Set the types of the outputs the workers may return, see [`ContentRegistry`].
+    pub fn set_registry(&mut self, registry: ContentRegistry) {
+        self.registry = Arc::new(registry);
+    }
+
+    /// Replace a worker by a new process once it executed `max_tasks` actions, at least 1, so that
+    /// the memory it leaked is released.
+    pub fn set_max_tasks_per_worker(&mut self, max_tasks: usize) {
+        self.max_tasks_per_worker = Some(max_tasks.max(1));
+    }
+
+    /// Execute the requests of the pool when the current process is a worker, with the actions
+    /// of the tasks by name and the types of their inputs, then return `true` once the pool is
+    /// dropped. Return `false` at once in any other process.
+    pub fn serve(actions: HashMap<String, Action>, registry: &ContentRegistry) -> bool {
+        if std::env::var_os(WORKER_VAR).is_none() {
+            return false;
+        }
+        // Drives the asynchronous actions.
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let reply = execute(&line, &actions, registry, &runtime);
+            let mut stdout = io::stdout().lock();
+            let sent = serde_json::to_string(&reply)
+                .map_err(io::Error::other)
+                .and_then(|reply| writeln!(stdout, "{}{}", REPLY_PREFIX, reply))
+                .and_then(|_| stdout.flush());
+            // The state of a panicked worker may be corrupted.
+            if sent.is_err() || matches!(reply, Reply::Panic(_)) {
+                break;
+            }
+        }
+        true
+    }
+}
+
+impl Executor for WorkerPool {
+    fn spawn(
+        &self,
+        task: &str,
+        _action: Action,
+        input: Input,
+        env: Arc<EnvVar>,
+    ) -> JoinHandle<Output> {
+        let request = Request::encode(task, &input, &env);
+        let task = task.to_string();
+        let (program, args) = (self.program.clone(), self.args.clone());
+        let (registry, max_tasks) = (self.registry.clone(), self.max_tasks_per_worker);
+        let (slots, idle) = (self.slots.clone(), self.idle.clone());
+        tokio::spawn(async move {
+            let request = match request {
+                Ok(request) => request,
+                Err(err) => {
+                    return Output::error(format!(
+                        "The input of task {} cannot be sent to a worker: {}",
+                        task, err
+                    ))
+                }
+            };
+            // The semaphore is never closed.
+            let _slot = slots.acquire_owned().await.unwrap();
+            let worker = idle.lock().unwrap().pop();
+            let mut worker = match worker {
+                Some(worker) => worker,
+                None => match Worker::start(&program, &args) {
+                    Ok(worker) => worker,
+                    Err(err) => {
+                        return Output::error(format!("Failed to start a worker process: {}", err))
+                    }
+                },
+            };
+            // Dropping the worker kills its process, e.g. when the attempt is aborted.
+            let reply = match worker.call(&request).await {
+                Ok(Some(Reply::Panic(msg))) => panic!("{}", msg),
+                Ok(Some(reply)) => reply,
+                Ok(None) | Err(_) => panic!("The worker process exited unexpectedly."),
+            };
+            worker.served += 1;
+            if max_tasks.is_none_or(|max_tasks| worker.served < max_tasks) {
+                idle.lock().unwrap().push(worker);
+            }
+            reply.decode(&registry).unwrap_or_else(|err| {
+                Output::error(format!(
+                    "Failed to decode the output of task {}: {}",
+                    task, err
+                ))
+            })
+        })
+    }
+}
+
+/// A worker process.
+struct Worker {
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    /// The number of actions it executed.
+    served: usize,
+}
+
+impl Worker {
+    fn start(program: &PathBuf, args: &[OsString]) -> io::Result<Self> {
+        let mut child = Command::new(program)
+            .args(args)
+            .env(WORKER_VAR, "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+        Ok(Self {
+            _child: child,
+            stdin,
+            stdout,
+            served: 0,
+        })
+    }
+
+    /// Send a request and wait for its reply, `None` if the worker exited before replying.
+    async fn call(&mut self, request: &str) -> io::Result<Option<Reply>> {
+        self.stdin.write_all(request.as_bytes()).await?;
+        self.stdin.write_all(b"\n").await?;
+        self.stdin.flush().await?;
+        while let Some(line) = self.stdout.next_line().await? {
+            // An action may print a line without ending it before the reply.
+            match line.split_once(REPLY_PREFIX) {
+                Some((printed, reply)) => {
+                    if !printed.is_empty() {
+                        info!("{}", printed);
+                    }
+                    return serde_json::from_str(reply)
+                        .map(Some)
+                        .map_err(io::Error::other);
+                }
+                None => info!("{}", line),
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// An action to execute in a worker, its contents are serialized as `(tag, value)` pairs.
+#[derive(Serialize, Deserialize)]
+struct Request {
+    task: String,
+    /// The outputs of the predecessors, by id and name.
+    sources: Vec<(usize, String, Option<Value>)>,
+    /// The contents of the input not keyed by any predecessor.
+    contents: Vec<Value>,
+    /// The serializable variables of the environment.
+    env: Vec<(String, Value)>,
+}
+
+impl Request {
+    fn encode(task: &str, input: &Input, env: &EnvVar) -> serde_json::Result<String> {
+        let request = Self {
+            task: task.to_string(),
+            sources: input
+                .sources()
+                .map(|(id, name, content)| {
+                    Ok((
+                        id,
+                        name.to_string(),
+                        content.map(serde_json::to_value).transpose()?,
+                    ))
+                })
+                .collect::<serde_json::Result<_>>()?,
+            contents: input
+                .unkeyed()
+                .iter()
+                .map(serde_json::to_value)
+                .collect::<serde_json::Result<_>>()?,
+            env: env
+                .variables()
+                .filter(|(_, variable)| variable.is_serializable())
+                .map(|(name, variable)| Ok((name.to_string(), serde_json::to_value(variable)?)))
+                .collect::<serde_json::Result<_>>()?,
+        };
+        serde_json::to_string(&request)
+    }
+
+    fn decode(self, registry: &ContentRegistry) -> serde_json::Result<(Input, EnvVar)> {
+        let mut input = Input::new(
+            self.contents
+                .into_iter()
+                .map(|value| registry.deserialize(value))
+                .collect::<serde_json::Result<_>>()?,
+        );
+        for (id, name, content) in self.sources {
+            let content = content
+                .map(|value| registry.deserialize(value))
+                .transpose()?;
+            input.push(id, &name, content);
+        }
+        let mut env = EnvVar::new();
+        for (name, value) in self.env {
+            env.set_content(&name, registry.deserialize(value)?);
+        }
+        Ok((input, env))
+    }
+}
+
+/// The output of an action executed in a worker, its contents are serialized as `(tag, value)`
+/// pairs. An error message is serialized as a string if its content is not serializable.
+#[derive(Serialize, Deserialize)]
+enum Reply {
+    Out(Option<Value>),
+    Err(Option<Value>),
+    ErrWithExitCode(Option<i32>, Option<Value>),
+    Termination,
+    Branch(Option<Value>, Vec<String>),
+    /// The action panicked, with the panic message.
+    Panic(String),
+}
+
+impl Reply {
+    fn error(msg: String) -> Self {
+        Self::Err(serde_json::to_value(Content::serializable(msg)).ok())
+    }
+
+    fn encode(output: Output) -> Self {
+        let content = |content: &Option<Content>| match content {
+            Some(content) => serde_json::to_value(content).map(Some),
+            None => Ok(None),
+        };
+        let message = |output: &Output, content: &Option<Content>| match content {
+            Some(c) if c.is_serializable() => serde_json::to_value(c).ok(),
+            _ => output
+                .get_err()
+                .and_then(|msg| serde_json::to_value(Content::serializable(msg)).ok()),
+        };
+        let reply = match &output {
+            Output::Out(out) => content(out).map(Self::Out),
+            Output::Branch(out, successors) => {
+                content(out).map(|out| Self::Branch(out, successors.clone()))
+            }
+            Output::Err(err) => Ok(Self::Err(message(&output, err))),
+            Output::ErrWithExitCode(code, err) => {
+                Ok(Self::ErrWithExitCode(*code, message(&output, err)))
+            }
+            Output::Termination => Ok(Self::Termination),
+            Output::Expand(_) => {
+                return Self::error("A task cannot expand in a worker.".to_string())
+            }
+        };
+        reply.unwrap_or_else(|err| Self::error(format!("The output is not serializable: {}", err)))
+    }
+
+    fn decode(self, registry: &ContentRegistry) -> serde_json::Result<Output> {
+        let content =
+            |value: Option<Value>| value.map(|value| registry.deserialize(value)).transpose();
+        Ok(match self {
+            Self::Out(out) => Output::Out(content(out)?),
+            Self::Err(err) => Output::Err(content(err)?),
+            Self::ErrWithExitCode(code, err) => Output::ErrWithExitCode(code, content(err)?),
+            Self::Termination => Output::Termination,
+            Self::Branch(out, successors) => Output::Branch(content(out)?, successors),
+            Self::Panic(_) => unreachable!("a panic is not an output"),
+        })
+    }
+}
+
+/// Execute a request in a worker.
+fn execute(
+    request: &str,
+    actions: &HashMap<String, Action>,
+    registry: &ContentRegistry,
+    runtime: &tokio::runtime::Runtime,
+) -> Reply {
+    let request: Request = match serde_json::from_str(request) {
+        Ok(request) => request,
+        Err(err) => return Reply::error(format!("Invalid request: {}", err)),
+    };
+    let Some(action) = actions.get(&request.task) else {
+        return Reply::error(format!(
+            "No action for task {} in the worker.",
+            request.task
+        ));
+    };
+    let (input, env) = match request.decode(registry) {
+        Ok(decoded) => decoded,
+        Err(err) => return Reply::error(format!("Failed to decode the input: {}", err)),
+    };
+    match std::panic::catch_unwind(AssertUnwindSafe(|| {
+        // The worker process is killed when the attempt is aborted.
+        action.run_on(input, Arc::new(env), runtime, std::future::pending::<()>())
+    })) {
+        Ok(output) => Reply::encode(output),
+        Err(payload) => Reply::Panic(panic_message(payload.as_ref())),
+    }
+}
@@ -8,11 +8,22 @@ extern crate yaml_rust;
 
 #[cfg(feature = "derive")]
 pub use derive::*;
-pub use engine::{Dag, DagError, Engine, OutputMessage};
+#[cfg(feature = "serde")]
+pub use engine::WorkerPool;
+pub use engine::{
+    CacheError, CachePolicy, CacheStore, CancellationHandle, CheckpointError, CheckpointStore,
+    Checkpointable, Clock, Dag, DagError, DagMetadata, DagObserver, DagOutcome, DagReport, Engine,
+    Executor, FileCacheStore, FileCheckpointStore, LoggingObserver, ManualClock, MemoryCacheStore,
+    MissedRuns, OutputMessage, Schedule, ScheduledRun, SchedulingPolicy, SerialExecutor,
+    StoredOutput, SystemClock, TaskCheckpoint, TaskReport, TaskStatus, TokioExecutor,
+};
 pub use task::{
-    alloc_id, Action, CommandAction, Complex, DefaultTask, Input, Output, Simple, Task,
-    ToErrorMessage,
+    alloc_id, Action, Backoff, CommandAction, Complex, Condition, DefaultTask, Expansion, Input,
+    Output, RetryPolicy, Simple, SubDagTask, Task, ToErrorMessage, TriggerRule, TypedOutput,
+    TypedTask,
 };
+#[cfg(feature = "serde")]
+pub use task::{ContentRegistry, Serializable};
 pub use utils::{EnvVar, ParseError, Parser};
 #[cfg(feature = "yaml")]
 pub use yaml::{FileContentError, FileNotFound, YamlParser, YamlTask, YamlTaskError};
@@ -1,6 +1,6 @@
 use crate::{EnvVar, Input, Output};
 use async_trait::async_trait;
-use std::sync::Arc;
+use std::{future::Future, sync::Arc};
 
 /// The type of closure that performs logic.
 /// # [`Simple`]
@@ -51,6 +51,9 @@ pub type Simple = dyn Fn(Input, Arc<EnvVar>) -> Output + Send + Sync;
 /// };
 /// let action = Action::Structure(Arc::new(hello));
 /// ```
+///
+/// The synchronous `run` is executed on a blocking thread, so it may perform blocking IO or heavy
+/// computations. Asynchronous logic should instead implement `async_run` and return true from `is_async`.
 #[async_trait]
 pub trait Complex {
     fn run(&self, input: Input, env: Arc<EnvVar>) -> Output;
@@ -62,6 +65,26 @@ pub trait Complex {
     fn is_async(&self) -> bool {
         false
     }
+
+    /// A key identifying what the action computes, used in the fingerprint of a cached task
+    /// in addition to its inputs, see [`CachePolicy`](crate::CachePolicy). It should change
+    /// whenever the action may produce a different output for the same inputs.
+    ///
+    /// It is awaited on the threads running the tasks, blocking work such as reading files
+    /// belongs on the blocking thread pool, see [`tokio::task::spawn_blocking`].
+    async fn cache_key(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether the results of a previous execution of the action are still valid, like the
+    /// targets of a makefile newer than their prerequisites. The task is then not executed and is
+    /// reported as [`TaskStatus::UpToDate`](crate::TaskStatus::UpToDate), its output is empty.
+    /// It is checked once the predecessors of the task are done, and can be overridden with
+    /// [`Dag::force_task`](crate::Dag::force_task). Like [`Complex::cache_key`], it should not
+    /// block.
+    async fn is_up_to_date(&self) -> bool {
+        false
+    }
 }
 
 /// Task specific behavior
@@ -79,23 +102,83 @@ pub enum Action {
 }
 
 impl Action {
-    fn inner_run(&self, input: Input, env: Arc<EnvVar>) -> Output {
+    /// The cache key of a [`Complex`] action, a closure has none.
+    pub(crate) async fn cache_key(&self) -> Option<String> {
+        match self {
+            Self::Closure(_) => None,
+            Self::Structure(structure) => structure.cache_key().await,
+        }
+    }
+
+    /// Whether a [`Complex`] action is up to date, a closure never is.
+    pub(crate) async fn is_up_to_date(&self) -> bool {
+        match self {
+            Self::Closure(_) => false,
+            Self::Structure(structure) => structure.is_up_to_date().await,
+        }
+    }
+
+    /// Run the execution logic on the current thread, an asynchronous [`Complex`] is driven by
+    /// `runtime` until it completes or `aborted` does, which drops it, for example to kill the
+    /// process of a [`CommandAction`](crate::CommandAction). A panic in the logic is propagated to
+    /// the caller.
+    pub(crate) fn run_on(
+        &self,
+        input: Input,
+        env: Arc<EnvVar>,
+        runtime: &tokio::runtime::Runtime,
+        aborted: impl Future,
+    ) -> Output {
         match self {
             Self::Closure(closure) => closure(input, env),
+            Self::Structure(structure) if structure.is_async() => runtime.block_on(async {
+                tokio::select! {
+                    output = structure.async_run(input, env) => output,
+                    _ = aborted => Output::error("The execution was aborted.".to_string()),
+                }
+            }),
             Self::Structure(structure) => structure.run(input, env),
         }
     }
 
-    pub async fn run(&self, input: Input, env: Arc<EnvVar>) -> Output {
+    /// Run the execution logic on the thread polling the returned future, even when it is
+    /// synchronous.
+    pub(crate) async fn run_inline(&self, input: Input, env: Arc<EnvVar>) -> Output {
         match self {
-            Self::Closure(_) => self.inner_run(input, env),
+            Self::Closure(closure) => closure(input, env),
+            Self::Structure(structure) if structure.is_async() => {
+                structure.async_run(input, env).await
+            }
+            Self::Structure(structure) => structure.run(input, env),
+        }
+    }
+
+    /// Run the execution logic.
+    ///
+    /// Synchronous logic, that is a closure or a [`Complex`] that is not async, may block its
+    /// thread for a long time, so it is executed on the blocking thread pool of tokio instead of
+    /// starving the threads running the asynchronous tasks. A panic in the logic is propagated
+    /// to the caller, and a cancelled execution returns an error output.
+    pub async fn run(&self, input: Input, env: Arc<EnvVar>) -> Output {
+        let blocking = match self {
+            Self::Closure(closure) => {
+                let closure = closure.clone();
+                tokio::task::spawn_blocking(move || closure(input, env))
+            }
             Self::Structure(structure) => {
                 if structure.is_async() {
-                    structure.async_run(input, env).await
-                } else {
-                    self.inner_run(input, env)
+                    return structure.async_run(input, env).await;
                 }
+                let structure = structure.clone();
+                tokio::task::spawn_blocking(move || structure.run(input, env))
             }
+        };
+        match blocking.await {
+            Ok(output) => output,
+            Err(err) => match err.try_into_panic() {
+                Ok(panic) => std::panic::resume_unwind(panic),
+                Err(err) => Output::error(format!("Blocking execution failed: {}", err)),
+            },
         }
     }
 }
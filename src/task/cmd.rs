@@ -1,12 +1,27 @@
 use crate::{Complex, EnvVar, Input, Output};
-use std::process::Command;
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::sync::Arc;
+use std::time::SystemTime;
 
+use crate::engine::StableHasher;
 use crate::task::Content;
 
 /// [`CommandAction`] is a specific implementation of [`Complex`], used to execute operating system commands.
+///
+/// When executed by a [`Dag`](crate::Dag), the command runs asynchronously, and the child process is
+/// killed if the task is aborted, for example because it timed out.
+///
+/// The files read and written by the command can be declared, like the prerequisites and targets
+/// of a makefile. The command is then not executed when all its output files are newer than all
+/// its input files, see [`Complex::is_up_to_date`].
 pub struct CommandAction {
     command: String,
+    /// Glob patterns of the files read by the command, see [`CommandAction::add_input_file`].
+    input_files: Vec<String>,
+    /// Glob patterns of the files written by the command, see [`CommandAction::add_output_file`].
+    output_files: Vec<String>,
 }
 
 impl CommandAction {
@@ -14,19 +29,35 @@ impl CommandAction {
     pub fn new(cmd: &str) -> Self {
         Self {
             command: cmd.to_owned(),
+            input_files: Vec::new(),
+            output_files: Vec::new(),
         }
     }
-}
 
-impl Complex for CommandAction {
-    fn run(&self, input: Input, _env: Arc<EnvVar>) -> Output {
+    /// Declare the files read by the command, as a path or a glob pattern such as `src/**/*.rs`.
+    /// When the task is cached, see [`CachePolicy`](crate::CachePolicy), it is executed again
+    /// whenever the content of one of its input files changed.
+    pub fn add_input_file(&mut self, path: impl AsRef<Path>) {
+        self.input_files
+            .push(path.as_ref().to_string_lossy().into_owned());
+    }
+
+    /// Declare the files written by the command, as a path or a glob pattern.
+    pub fn add_output_file(&mut self, path: impl AsRef<Path>) {
+        self.output_files
+            .push(path.as_ref().to_string_lossy().into_owned());
+    }
+
+    /// The shell used to execute the command and its arguments, the inputs of type `String`
+    /// are appended as extra arguments.
+    fn program_and_args<'a>(&'a self, input: &'a Input) -> (&'static str, Vec<&'a str>) {
         let mut args = Vec::new();
-        let mut cmd = if cfg!(target_os = "windows") {
+        let program = if cfg!(target_os = "windows") {
             args.push("-Command");
-            Command::new("powershell")
+            "powershell"
         } else {
             args.push("-c");
-            Command::new("sh")
+            "sh"
         };
         args.push(&self.command);
 
@@ -36,37 +67,195 @@ impl Complex for CommandAction {
             }
         });
 
-        log::debug!("cmd: {:?}, args: {:?}", cmd.get_program(), args);
-        let (code, out) = match cmd.args(args).output() {
-            Ok(o) => (0, o),
-            Err(e) => {
-                return Output::error_with_exit_code(
-                    e.raw_os_error(),
-                    Some(Content::new(e.to_string())),
-                )
-            }
-        };
-        let stdout: Vec<String> = {
-            let out = String::from_utf8(out.stdout).unwrap_or("".to_string());
-            if cfg!(target_os = "windows") {
-                out.rsplit_terminator("\r\n").map(str::to_string).collect()
-            } else {
-                out.split_terminator('\n').map(str::to_string).collect()
+        log::debug!("cmd: {:?}, args: {:?}", program, args);
+        (program, args)
+    }
+}
+
+/// Convert the result of a finished command into an [`Output`].
+fn command_output(out: std::io::Result<std::process::Output>) -> Output {
+    let out = match out {
+        Ok(o) => o,
+        Err(e) => {
+            return Output::error_with_exit_code(
+                e.raw_os_error(),
+                Some(command_content(e.to_string())),
+            )
+        }
+    };
+    let output = command_content((split_lines(out.stdout), split_lines(out.stderr)));
+    if out.status.success() {
+        Output::new(output)
+    } else {
+        Output::error_with_exit_code(out.status.code(), Some(output))
+    }
+}
+
+/// The contents of the outputs of commands are serializable with the `serde` feature.
+#[cfg(feature = "serde")]
+fn command_content<T: crate::Serializable>(val: T) -> Content {
+    Content::serializable(val)
+}
+
+#[cfg(not(feature = "serde"))]
+fn command_content<T: Send + Sync + 'static>(val: T) -> Content {
+    Content::new(val)
+}
+
+/// Kills the process group of a running command when dropped, unless the command finished.
+struct ProcessGroupGuard(Option<u32>);
+
+impl Drop for ProcessGroupGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pid) = self.0.and_then(|pid| libc::pid_t::try_from(pid).ok()) {
+            // SAFETY: `kill` has no memory safety requirements, the group id is the one of
+            // the child spawned by this action.
+            unsafe {
+                libc::kill(-pid, libc::SIGKILL);
             }
+        }
+    }
+}
+
+fn split_lines(out: Vec<u8>) -> Vec<String> {
+    let out = String::from_utf8(out).unwrap_or("".to_string());
+    if cfg!(target_os = "windows") {
+        out.rsplit_terminator("\r\n").map(str::to_string).collect()
+    } else {
+        out.split_terminator('\n').map(str::to_string).collect()
+    }
+}
+
+#[async_trait]
+impl Complex for CommandAction {
+    fn run(&self, input: Input, _env: Arc<EnvVar>) -> Output {
+        let (program, args) = self.program_and_args(&input);
+        command_output(Command::new(program).args(args).output())
+    }
+
+    async fn async_run(&self, input: Input, _env: Arc<EnvVar>) -> Output {
+        let (program, args) = self.program_and_args(&input);
+        let mut cmd = tokio::process::Command::new(program);
+        cmd.args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        // The shell may fork the actual command, so it runs in its own process group,
+        // which is killed as a whole when the action is aborted.
+        #[cfg(unix)]
+        cmd.process_group(0);
+        let child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(e) => return command_output(Err(e)),
         };
-        let stderr: Vec<String> = {
-            let out = String::from_utf8(out.stderr).unwrap_or("".to_string());
-            if cfg!(target_os = "windows") {
-                out.rsplit_terminator("\r\n").map(str::to_string).collect()
-            } else {
-                out.split_terminator('\n').map(str::to_string).collect()
+        let mut guard = ProcessGroupGuard(child.id());
+        let out = child.wait_with_output().await;
+        guard.0 = None;
+        command_output(out)
+    }
+
+    fn is_async(&self) -> bool {
+        true
+    }
+
+    /// The command and the contents of the input files.
+    async fn cache_key(&self) -> Option<String> {
+        let input_files = self.input_files.clone();
+        let key = read_files(move || Some(hash_files(&input_files)), None).await?;
+        Some(format!("{}\n{}", self.command, key))
+    }
+
+    /// The output files are up to date when each output pattern matches at least one file, each
+    /// input pattern too, and the oldest output file is not older than the newest input file.
+    async fn is_up_to_date(&self) -> bool {
+        if self.output_files.is_empty() {
+            return false;
+        }
+        let input_files = self.input_files.clone();
+        let output_files = self.output_files.clone();
+        read_files(
+            move || outputs_up_to_date(&input_files, &output_files),
+            false,
+        )
+        .await
+    }
+}
+
+/// Run `read`, which reads the declared files, on the blocking thread pool of tokio so that large
+/// files do not block the threads running the tasks. A panic is propagated to the caller, and
+/// `cancelled` is returned if the runtime shuts down meanwhile.
+async fn read_files<T: Send + 'static>(
+    read: impl FnOnce() -> T + Send + 'static,
+    cancelled: T,
+) -> T {
+    match tokio::task::spawn_blocking(read).await {
+        Ok(value) => value,
+        Err(err) => match err.try_into_panic() {
+            Ok(panic) => std::panic::resume_unwind(panic),
+            Err(_) => cancelled,
+        },
+    }
+}
+
+/// Hash the paths and the contents of the files matching the patterns.
+fn hash_files(patterns: &[String]) -> String {
+    let mut hasher = StableHasher::new();
+    for pattern in patterns {
+        hasher.write(pattern.as_bytes());
+        for path in matched_files(pattern) {
+            hasher.write(path.to_string_lossy().as_bytes());
+            match std::fs::read(path) {
+                Ok(content) => {
+                    hasher.write(b"+");
+                    hasher.write(&content);
+                }
+                Err(_) => hasher.write(b"-"),
             }
-        };
-        let output = Content::new((stdout, stderr));
-        if out.status.success() {
-            Output::new(output)
-        } else {
-            Output::error_with_exit_code(Some(code), Some(output))
         }
     }
+    hasher.finish()
+}
+
+/// The files matching a glob pattern, in alphabetical order.
+fn matched_files(pattern: &str) -> Vec<PathBuf> {
+    match glob::glob(pattern) {
+        Ok(paths) => paths.filter_map(Result::ok).collect(),
+        Err(err) => {
+            log::warn!("Invalid file pattern '{}': {}", pattern, err);
+            Vec::new()
+        }
+    }
+}
+
+/// Whether each pattern matches at least one file, and the oldest output file is not older than
+/// the newest input file.
+fn outputs_up_to_date(input_files: &[String], output_files: &[String]) -> bool {
+    let (Some(inputs), Some(outputs)) = (
+        modification_times(input_files),
+        modification_times(output_files),
+    ) else {
+        return false;
+    };
+    match (inputs.iter().max(), outputs.iter().min()) {
+        (Some(newest_input), Some(oldest_output)) => newest_input <= oldest_output,
+        (None, Some(_)) => true,
+        _ => false,
+    }
+}
+
+/// The modification times of the files matching the patterns, `None` if a pattern matches no file.
+fn modification_times(patterns: &[String]) -> Option<Vec<SystemTime>> {
+    let mut times = Vec::new();
+    for pattern in patterns {
+        let files = matched_files(pattern);
+        if files.is_empty() {
+            return None;
+        }
+        for file in files {
+            times.push(std::fs::metadata(file).and_then(|m| m.modified()).ok()?);
+        }
+    }
+    Some(times)
 }
@@ -0,0 +1,103 @@
+//! Conditional execution of a task
+//!
+//! By default a task is executed once all its predecessors succeeded. Three mechanisms make
+//! the execution conditional:
+//! - A predecessor may return [`Output::branch`](super::Output::branch) to select which of its
+//!   successors run, the others are bypassed.
+//! - A task may carry a [`Condition`] over its [`Input`] and the [`EnvVar`] of the dag, the task
+//!   is bypassed when it returns false.
+//! - The [`TriggerRule`] of a task decides whether it runs, given the final state of its
+//!   predecessors. This is how a join node runs after some branches were bypassed.
+//!
+//! A bypassed task is reported with the status [`TaskStatus::Bypassed`](crate::TaskStatus::Bypassed),
+//! it is not a failure. Unless the dag keeps going after errors, see [`Dag::keep_going`](crate::Dag::keep_going),
+//! a failure still stops the whole dag, whatever the trigger rules.
+//!
+//! # Example
+//!
+//! ```rust
+//! use dagrs::{Dag, DefaultTask, Output, TaskStatus, TriggerRule};
+//!
+//! let check = DefaultTask::with_closure("check", |_input, _env| Output::branch(["small"]));
+//! let mut small = DefaultTask::with_closure("small", |_input, _env| Output::new(1usize));
+//! small.set_predecessors(&[&check]);
+//! let mut large = DefaultTask::with_closure("large", |_input, _env| Output::new(1000usize));
+//! large.set_predecessors(&[&check]);
+//! let mut join = DefaultTask::with_closure("join", |input, _env| {
+//!     Output::new(input.get_iter().filter_map(|c| c.get::<usize>()).sum::<usize>())
+//! });
+//! join.set_predecessors(&[&small, &large]);
+//! join.set_trigger_rule(TriggerRule::NoneFailed);
+//!
+//! let mut dag = Dag::with_tasks(vec![check, small, large, join]);
+//! let report = dag.start_with_report().unwrap();
+//! assert!(report.is_success());
+//! assert_eq!(report.task_by_name("large").unwrap().status, TaskStatus::Bypassed);
+//! assert_eq!(*dag.get_result::<usize>().unwrap(), 1);
+//! ```
+
+use std::{fmt::Display, str::FromStr};
+
+use crate::{EnvVar, Input};
+
+/// The type of predicate deciding whether a task is executed, given its input and the
+/// environment of the dag.
+pub type Condition = dyn Fn(&Input, &EnvVar) -> bool + Send + Sync;
+
+/// Decides whether a task runs, given the final state of its predecessors.
+///
+/// A predecessor either succeeded, was bypassed (including when it did not select this task
+/// in its branch output), or failed (including when it was skipped after an upstream failure).
+/// When the rule is not met, the task is skipped if a predecessor failed, and bypassed otherwise.
+/// The outputs of the succeeded predecessors are the input of the task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TriggerRule {
+    /// All the predecessors succeeded.
+    #[default]
+    AllSuccess,
+    /// At least one predecessor succeeded.
+    AnySuccess,
+    /// All the predecessors are done, whatever their state.
+    AllDone,
+    /// No predecessor failed, some may have been bypassed.
+    NoneFailed,
+}
+
+impl TriggerRule {
+    /// Whether a task with this rule runs, given the numbers of its succeeded, bypassed and
+    /// failed predecessors.
+    pub(crate) fn is_met(&self, succeeded: usize, bypassed: usize, failed: usize) -> bool {
+        match self {
+            Self::AllSuccess => bypassed == 0 && failed == 0,
+            Self::AnySuccess => succeeded > 0 || succeeded + bypassed + failed == 0,
+            Self::AllDone => true,
+            Self::NoneFailed => failed == 0,
+        }
+    }
+}
+
+impl FromStr for TriggerRule {
+    type Err = String;
+
+    /// Parse the snake case name of a rule, such as `all_success`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all_success" => Ok(Self::AllSuccess),
+            "any_success" => Ok(Self::AnySuccess),
+            "all_done" => Ok(Self::AllDone),
+            "none_failed" => Ok(Self::NoneFailed),
+            _ => Err(format!("unknown trigger rule '{}'", s)),
+        }
+    }
+}
+
+impl Display for TriggerRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::AllSuccess => "all_success",
+            Self::AnySuccess => "any_success",
+            Self::AllDone => "all_done",
+            Self::NoneFailed => "none_failed",
+        })
+    }
+}
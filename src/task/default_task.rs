@@ -1,6 +1,6 @@
-use super::{Action, Complex, Task, ID_ALLOCATOR};
-use crate::{EnvVar, Input, Output};
-use std::sync::Arc;
+use super::{Action, Complex, Condition, RetryPolicy, Task, TriggerRule, ID_ALLOCATOR};
+use crate::{CachePolicy, EnvVar, Executor, Input, Output};
+use std::{sync::Arc, time::Duration};
 
 /// Common task types
 ///
@@ -68,6 +68,24 @@ pub struct DefaultTask {
     precursors: Vec<usize>,
     /// Perform specific actions.
     action: Action,
+    /// How the task is executed again after a failure.
+    retry_policy: Option<RetryPolicy>,
+    /// The maximum duration of one execution.
+    timeout: Option<Duration>,
+    /// Resources consumed while executing, as pairs of resource pool name and amount.
+    resources: Vec<(String, u32)>,
+    /// Decides whether the task is executed.
+    condition: Option<Arc<Condition>>,
+    /// Decides whether the task runs given the state of its predecessors.
+    trigger_rule: TriggerRule,
+    /// Allows the output of the task to be cached.
+    cache_policy: Option<CachePolicy>,
+    /// Tasks with a higher priority are started first.
+    priority: i32,
+    /// The expected duration of an execution.
+    estimated_duration: Option<Duration>,
+    /// Runs the action instead of the executor of the dag.
+    executor: Option<Arc<dyn Executor>>,
 }
 
 impl DefaultTask {
@@ -79,6 +97,15 @@ impl DefaultTask {
             action: Action::Closure(Arc::new(action)),
             name: name.to_owned(),
             precursors: Vec::new(),
+            retry_policy: None,
+            timeout: None,
+            resources: Vec::new(),
+            condition: None,
+            trigger_rule: TriggerRule::AllSuccess,
+            cache_policy: None,
+            priority: 0,
+            estimated_duration: None,
+            executor: None,
         }
     }
     /// Create a task, give the task name, and provide a specific type that implements the [`Complex`] trait as the specific
@@ -95,6 +122,15 @@ impl DefaultTask {
             action: Action::Structure(action),
             name: name.to_owned(),
             precursors: Vec::new(),
+            retry_policy: None,
+            timeout: None,
+            resources: Vec::new(),
+            condition: None,
+            trigger_rule: TriggerRule::AllSuccess,
+            cache_policy: None,
+            priority: 0,
+            estimated_duration: None,
+            executor: None,
         }
     }
 
@@ -116,6 +152,15 @@ impl DefaultTask {
             action: Action::Closure(action),
             name: name.to_owned(),
             precursors: Vec::new(),
+            retry_policy: None,
+            timeout: None,
+            resources: Vec::new(),
+            condition: None,
+            trigger_rule: TriggerRule::AllSuccess,
+            cache_policy: None,
+            priority: 0,
+            estimated_duration: None,
+            executor: None,
         }
     }
 
@@ -160,6 +205,71 @@ impl DefaultTask {
     pub fn set_action(&mut self, action: impl Complex + Send + Sync + 'static) {
         self.action = Action::Structure(Arc::new(action))
     }
+
+    /// Execute the task again according to the given policy when it fails.
+    ///
+    /// # Example
+    /// ```rust
+    /// use dagrs::{DefaultTask, Output, RetryPolicy};
+    /// let mut task = DefaultTask::with_closure("Task", |_input, _env| Output::empty());
+    /// task.set_retry_policy(RetryPolicy::new(3));
+    /// ```
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = Some(policy);
+    }
+
+    /// Abort an execution of the task taking longer than `timeout`, the task then fails.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    /// Declare that the task consumes `amount` of the resource pool `name` while executing.
+    pub fn add_resource(&mut self, name: &str, amount: u32) {
+        self.resources.push((name.to_string(), amount));
+    }
+
+    /// Only execute the task when `condition` holds for its input and the environment of the dag,
+    /// the task is bypassed otherwise.
+    ///
+    /// # Example
+    /// ```rust
+    /// use dagrs::{DefaultTask, Output};
+    /// let mut task = DefaultTask::with_closure("deploy", |_input, _env| Output::empty());
+    /// task.set_condition(|_input, env| env.get::<bool>("production") == Some(true));
+    /// ```
+    pub fn set_condition(
+        &mut self,
+        condition: impl Fn(&Input, &EnvVar) -> bool + Send + Sync + 'static,
+    ) {
+        self.condition = Some(Arc::new(condition));
+    }
+
+    /// Set the rule deciding whether the task runs given the state of its predecessors.
+    pub fn set_trigger_rule(&mut self, rule: TriggerRule) {
+        self.trigger_rule = rule;
+    }
+
+    /// Allow the output of the task to be cached, it is then only executed when its fingerprint
+    /// is not in the cache of the dag.
+    pub fn set_cache_policy(&mut self, policy: CachePolicy) {
+        self.cache_policy = Some(policy);
+    }
+
+    /// Set the priority of the task, the higher the sooner it is started when it competes with other
+    /// ready tasks. It is 0 by default.
+    pub fn set_priority(&mut self, priority: i32) {
+        self.priority = priority;
+    }
+
+    /// Set the expected duration of an execution of the task, used to find the critical path of the dag.
+    pub fn set_estimated_duration(&mut self, duration: Duration) {
+        self.estimated_duration = Some(duration);
+    }
+
+    /// Run the action of the task on `executor` rather than on the executor of the dag.
+    pub fn set_executor(&mut self, executor: Arc<dyn Executor>) {
+        self.executor = Some(executor);
+    }
 }
 
 impl Task for DefaultTask {
@@ -178,6 +288,42 @@ impl Task for DefaultTask {
     fn name(&self) -> &str {
         &self.name
     }
+
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        self.retry_policy.clone()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn resources(&self) -> &[(String, u32)] {
+        &self.resources
+    }
+
+    fn condition(&self) -> Option<Arc<Condition>> {
+        self.condition.clone()
+    }
+
+    fn trigger_rule(&self) -> TriggerRule {
+        self.trigger_rule
+    }
+
+    fn cache_policy(&self) -> Option<CachePolicy> {
+        self.cache_policy.clone()
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn estimated_duration(&self) -> Option<Duration> {
+        self.estimated_duration
+    }
+
+    fn executor(&self) -> Option<Arc<dyn Executor>> {
+        self.executor.clone()
+    }
 }
 
 impl Default for DefaultTask {
@@ -190,6 +336,15 @@ impl Default for DefaultTask {
             name,
             precursors: Vec::new(),
             action: Action::Closure(Arc::new(action)),
+            retry_policy: None,
+            timeout: None,
+            resources: Vec::new(),
+            condition: None,
+            trigger_rule: TriggerRule::AllSuccess,
+            cache_policy: None,
+            priority: 0,
+            estimated_duration: None,
+            executor: None,
         }
     }
 }
@@ -0,0 +1,84 @@
+//! Dynamic expansion of a task
+//!
+//! # [`Expansion`]
+//!
+//! The tasks of a dag are known before it runs, but a task may only find out at runtime how much
+//! work there is, for example one unit of work per file found. Such a task returns
+//! [`Output::expand`] with the child tasks to execute, which are inserted in the dag as it runs:
+//! - The children are executed in parallel, each receiving the input of the expanded task.
+//!   Their predecessors are ignored, and they may expand in turn.
+//! - The successors of the expanded task wait for all the children, and receive their outputs
+//!   as [`Input`](super::Input) instead of the output of the expanded task. Their trigger rule
+//!   applies to the children, see [`TriggerRule`](super::TriggerRule).
+//!
+//! The children are reported like the other tasks, right after the expanded task. They should be
+//! given distinct names.
+//!
+//! # Example
+//!
+//! ```rust
+//! use dagrs::{Dag, DefaultTask, Output};
+//!
+//! let list = DefaultTask::with_closure("list", |_input, _env| {
+//!     Output::expand((1..=3usize).map(|i| {
+//!         DefaultTask::with_closure(&format!("square {}", i), move |_input, _env| {
+//!             Output::new(i * i)
+//!         })
+//!     }))
+//! });
+//! let mut sum = DefaultTask::with_closure("sum", |input, _env| {
+//!     Output::new(input.get_iter().filter_map(|c| c.get::<usize>()).sum::<usize>())
+//! });
+//! sum.set_predecessors(&[&list]);
+//!
+//! let mut dag = Dag::with_tasks(vec![list, sum]);
+//! let report = dag.start_with_report().unwrap();
+//! assert!(report.is_success());
+//! assert_eq!(report.tasks.len(), 5);
+//! assert_eq!(*dag.get_result::<usize>().unwrap(), 14);
+//! ```
+
+use std::{fmt::Debug, sync::Arc};
+
+use super::{Output, Task};
+
+/// The child tasks a task expands into at runtime, see [`Output::expand`].
+#[derive(Clone)]
+pub struct Expansion {
+    tasks: Vec<Arc<dyn Task>>,
+}
+
+impl Expansion {
+    /// The number of child tasks.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    pub(crate) fn tasks(&self) -> &[Arc<dyn Task>] {
+        &self.tasks
+    }
+}
+
+impl Debug for Expansion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.tasks.iter().map(|task| task.name()))
+            .finish()
+    }
+}
+
+impl Output {
+    /// Construct an [`Output`] expanding the task into `tasks`, which are executed before its successors.
+    pub fn expand<T: Task + 'static>(tasks: impl IntoIterator<Item = T>) -> Self {
+        Self::Expand(Expansion {
+            tasks: tasks
+                .into_iter()
+                .map(|task| Arc::new(task) as Arc<dyn Task>)
+                .collect(),
+        })
+    }
+}
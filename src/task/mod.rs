@@ -12,6 +12,18 @@
 //! - `action`: type is [`Action`]. This field is used to store the specific execution logic of the task.
 //! - `precursors`: type is `Vec<usize>`. This field is used to store the predecessor task `id` of this task.
 //!
+//! A task can optionally provide a [`RetryPolicy`], which decides whether and when a failed
+//! execution is attempted again, a timeout bounding the duration of each execution, and the
+//! resources it consumes while executing. Its output can be cached across executions, see
+//! [`CachePolicy`]. Its priority and estimated duration decide which ready task is started first,
+//! see [`SchedulingPolicy`](crate::SchedulingPolicy). Its action can run on another executor than
+//! the one of the dag, see [`Executor`].
+//!
+//! A task can also be executed conditionally, see [`Condition`] and [`TriggerRule`].
+//! It can expand into child tasks at runtime, see [`Expansion`], and a whole dag can be executed
+//! as a single task, see [`SubDagTask`]. A [`TypedTask`] checks at compile time that it is
+//! connected to predecessors producing the type of its input.
+//!
 //! # [`Action`]: specific logical behavior
 //!
 //! Each task has an [`Action`] field inside, which stores the specific execution logic of the task.
@@ -24,20 +36,44 @@
 //! Each task may produce output and may require the output of its predecessor task as its input.
 //! [`Output`] is used to construct and store the output obtained by task execution. [`Input`] is used as a tool
 //! to provide users with the output of the predecessor task.
+//!
+//! With the `serde` feature, the contents of the outputs can be serialized, see [`Serializable`].
+use crate::{CachePolicy, Executor};
 use std::fmt::Debug;
 use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub use self::action::{Action, Complex, Simple};
 pub use self::cmd::CommandAction;
+pub use self::condition::{Condition, TriggerRule};
 pub use self::default_task::DefaultTask;
+pub use self::expand::Expansion;
+pub use self::retry::{Backoff, RetryPolicy, RetryPredicate};
+#[cfg(feature = "serde")]
+pub(crate) use self::serialize::{deserialize, DeserializeFn, SerializeVTable};
+#[cfg(feature = "serde")]
+pub use self::serialize::{ContentRegistry, Serializable};
 pub use self::state::Content;
 pub(crate) use self::state::ExecState;
 pub use self::state::{Input, Output, ToErrorMessage};
+#[cfg(feature = "yaml")]
+pub(crate) use self::sub_dag::SubDagAction;
+pub(crate) use self::sub_dag::SubDagOutput;
+pub use self::sub_dag::SubDagTask;
+pub use self::typed::{TypedOutput, TypedTask};
 
 mod action;
 mod cmd;
+mod condition;
 mod default_task;
+mod expand;
+mod retry;
+#[cfg(feature = "serde")]
+mod serialize;
 mod state;
+mod sub_dag;
+mod typed;
 /// The Task trait
 ///
 /// Tasks can have many attributes, among which `id`, `name`, `predecessor_tasks`, and
@@ -56,6 +92,52 @@ pub trait Task: Send + Sync {
     fn id(&self) -> usize;
     /// Get the name of this task.
     fn name(&self) -> &str;
+    /// Get the retry policy of this task. By default a task is executed only once.
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        None
+    }
+    /// Get the maximum duration of one execution of this task. A timed out execution is
+    /// aborted and handled as a failure. By default a task has no timeout.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+    /// Get the resources this task consumes while executing, as pairs of resource pool
+    /// name and amount. See [`Dag::add_resource_pool`](crate::Dag::add_resource_pool).
+    fn resources(&self) -> &[(String, u32)] {
+        &[]
+    }
+    /// Get the condition deciding whether this task is executed, once its trigger rule is met.
+    /// By default a task is always executed.
+    fn condition(&self) -> Option<Arc<Condition>> {
+        None
+    }
+    /// Get the rule deciding whether this task runs given the state of its predecessors.
+    /// By default all of them must succeed.
+    fn trigger_rule(&self) -> TriggerRule {
+        TriggerRule::AllSuccess
+    }
+    /// Get the policy allowing the output of this task to be cached, see [`CachePolicy`].
+    /// By default a task is always executed.
+    fn cache_policy(&self) -> Option<CachePolicy> {
+        None
+    }
+    /// Get the priority of this task. When tasks compete for the parallelism or the resources of
+    /// the dag, the ones with higher priority are started first, see
+    /// [`SchedulingPolicy`](crate::SchedulingPolicy). The default priority is 0.
+    fn priority(&self) -> i32 {
+        0
+    }
+    /// Get the estimated duration of an execution of this task, used by the critical-path
+    /// scheduling, see [`SchedulingPolicy::CriticalPath`](crate::SchedulingPolicy::CriticalPath).
+    /// By default the duration of the previous execution is used.
+    fn estimated_duration(&self) -> Option<Duration> {
+        None
+    }
+    /// Get the executor running the action of this task, see [`Executor`].
+    /// By default the executor of the dag is used.
+    fn executor(&self) -> Option<Arc<dyn Executor>> {
+        None
+    }
 }
 
 /// IDAllocator for DefaultTask
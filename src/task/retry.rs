@@ -0,0 +1,183 @@
+//! Retry policy of a task
+//!
+//! # [`RetryPolicy`]
+//!
+//! By default a task is executed exactly once, and an error output or a panic makes the
+//! task fail. A [`RetryPolicy`] allows a task to be executed again after a failure, which
+//! is useful for flaky tasks such as network-bound shell commands.
+//!
+//! A policy consists of:
+//! - `max_attempts`: the maximum number of executions, including the first one.
+//! - `backoff`: how long to wait between two attempts, see [`Backoff`].
+//! - `jitter`: whether the waiting time is randomized, so that many failing tasks do not
+//!   retry at the same moment.
+//! - `retry_on`: an optional predicate over the failed [`Output`]. When it is given, only
+//!   the outputs it accepts are retried. Panicked and timed out executions have no output
+//!   to accept, so they are only retried when there is no predicate.
+//!
+//! # Example
+//!
+//! ```rust
+//! use dagrs::{Backoff, DefaultTask, Output, RetryPolicy};
+//! use std::time::Duration;
+//!
+//! let mut task = DefaultTask::with_closure("flaky", |_input, _env| Output::empty());
+//! task.set_retry_policy(
+//!     RetryPolicy::new(3)
+//!         .with_backoff(Backoff::exponential(
+//!             Duration::from_millis(100),
+//!             Duration::from_secs(5),
+//!         ))
+//!         .with_jitter(true)
+//!         .retry_on_exit_codes([1, 255]),
+//! );
+//! ```
+
+use std::{
+    collections::hash_map::RandomState,
+    fmt::Debug,
+    hash::{BuildHasher, Hasher},
+    sync::Arc,
+    time::Duration,
+};
+
+use super::Output;
+
+/// The type of predicate deciding whether a failed [`Output`] should be retried.
+pub type RetryPredicate = dyn Fn(&Output) -> bool + Send + Sync;
+
+/// The waiting strategy between two attempts of a task.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backoff {
+    /// Always wait for the same duration.
+    Fixed(Duration),
+    /// Wait for `initial`, then double the waiting time after each attempt, never
+    /// waiting longer than `max`.
+    Exponential { initial: Duration, max: Duration },
+}
+
+/// Describes how many times and when a failed task is executed again.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// The maximum number of executions, including the first one.
+    max_attempts: u32,
+    /// The waiting strategy between two attempts.
+    backoff: Backoff,
+    /// Randomize each waiting time between half and the whole computed delay.
+    jitter: bool,
+    /// Only retry the outputs accepted by this predicate. Panics and timeouts are never
+    /// accepted by a predicate.
+    retry_on: Option<Arc<RetryPredicate>>,
+}
+
+impl Backoff {
+    /// Construct a [`Backoff`] that waits `initial`, `2 * initial`, `4 * initial`... at most `max`.
+    pub fn exponential(initial: Duration, max: Duration) -> Self {
+        Self::Exponential { initial, max }
+    }
+
+    /// The waiting time after the given failed attempt, attempts are counted from 1.
+    fn delay(&self, attempt: u32) -> Duration {
+        match *self {
+            Self::Fixed(delay) => delay,
+            Self::Exponential { initial, max } => {
+                let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+                initial.saturating_mul(factor).min(max)
+            }
+        }
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::Fixed(Duration::ZERO)
+    }
+}
+
+impl RetryPolicy {
+    /// Construct a policy executing a task at most `max_attempts` times without waiting
+    /// between attempts. `max_attempts` is at least 1.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            backoff: Backoff::default(),
+            jitter: false,
+            retry_on: None,
+        }
+    }
+
+    /// Set the waiting strategy between two attempts.
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Randomize the waiting time between two attempts.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Only retry the failed outputs accepted by `predicate`. Panicked and timed out
+    /// executions are no longer retried.
+    pub fn retry_if(mut self, predicate: impl Fn(&Output) -> bool + Send + Sync + 'static) -> Self {
+        self.retry_on = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Only retry the failed outputs carrying one of the given exit codes, for example
+    /// the outputs of a failed [`CommandAction`](crate::CommandAction).
+    pub fn retry_on_exit_codes(self, codes: impl IntoIterator<Item = i32>) -> Self {
+        let codes: Vec<i32> = codes.into_iter().collect();
+        self.retry_if(move |output| match output {
+            Output::ErrWithExitCode(Some(code), _) => codes.contains(code),
+            _ => false,
+        })
+    }
+
+    /// The maximum number of executions, including the first one.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// The waiting strategy between two attempts.
+    pub fn backoff(&self) -> Backoff {
+        self.backoff
+    }
+
+    /// Determine whether the task should run again after its `attempt`-th execution failed.
+    /// `output` is `None` when the execution panicked or timed out.
+    pub(crate) fn should_retry(&self, attempt: u32, output: Option<&Output>) -> bool {
+        if attempt >= self.max_attempts {
+            return false;
+        }
+        match (output, &self.retry_on) {
+            (_, None) => true,
+            (Some(output), Some(predicate)) => predicate(output),
+            (None, Some(_)) => false,
+        }
+    }
+
+    /// The waiting time before the next attempt, after the `attempt`-th execution failed.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.backoff.delay(attempt);
+        if self.jitter {
+            // A fresh `RandomState` is randomly seeded, which is enough for spreading retries.
+            let random = RandomState::new().build_hasher().finish();
+            delay.mul_f64(0.5 + (random % 1000) as f64 / 2000.0)
+        } else {
+            delay
+        }
+    }
+}
+
+impl Debug for RetryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("backoff", &self.backoff)
+            .field("jitter", &self.jitter)
+            .field("retry_on", &self.retry_on.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,235 @@
+//! Serialization of the contents of outputs
+//!
+//! # [`Serializable`]
+//!
+//! A [`Content`] only knows the type of its value at runtime, so it cannot be serialized in
+//! general. With the `serde` feature, a content created by [`Content::serializable`] remembers
+//! the tag of the type of its value, and implements [`serde::Serialize`] as the pair
+//! `(tag, value)` in any serde format, like JSON or bincode.
+//!
+//! A [`Serializable`] type is a [`Checkpointable`] type which also implements the serde traits:
+//! its tag is [`Checkpointable::TYPE_TAG`], and its values can be stored in checkpoints too.
+//!
+//! A content is decoded back with a [`ContentRegistry`], which maps the tags to the types to
+//! decode, like the registry of the types stored in checkpoints. The strings, numbers, booleans,
+//! `Vec<String>` and the `(stdout, stderr)` lines of a [`CommandAction`](crate::CommandAction) are
+//! registered by default, other types are added with [`ContentRegistry::register`].
+//!
+//! A content wrapping another serializable content, like the output of a
+//! [`CommandAction`](crate::CommandAction), is serializable too: the tag of the inner type is
+//! wrapped in `Content<...>`.
+//!
+//! # Example
+//!
+//! ```rust
+//! use dagrs::{task::Content, ContentRegistry};
+//! use serde::de::DeserializeSeed;
+//!
+//! let content = Content::serializable(42usize);
+//! let json = serde_json::to_string(&content).unwrap();
+//! assert_eq!(json, r#"["usize",42]"#);
+//!
+//! let registry = ContentRegistry::default();
+//! let mut deserializer = serde_json::Deserializer::from_str(&json);
+//! let decoded = registry.deserialize(&mut deserializer).unwrap();
+//! assert_eq!(decoded.get::<usize>(), Some(&42));
+//! ```
+
+use std::fmt;
+
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, SeqAccess, Visitor},
+    ser::{self, SerializeTuple},
+    Deserializer, Serialize, Serializer,
+};
+
+use super::{Content, Output};
+use crate::{
+    engine::{wrapped_tag, wrapping_tag, Codecs},
+    Checkpointable,
+};
+
+/// A type whose values can be serialized in a [`Content`]. It is identified by its
+/// [`Checkpointable::TYPE_TAG`], which must be unique among the types of a [`ContentRegistry`].
+pub trait Serializable: Checkpointable + Serialize + DeserializeOwned {}
+
+macro_rules! impl_serializable {
+    ($($ty:ty),*) => {
+        $(
+            impl Serializable for $ty {}
+        )*
+    };
+}
+
+impl_serializable!(String, bool, i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+
+impl Serializable for Vec<String> {}
+
+/// The `(stdout, stderr)` lines of a command.
+impl Serializable for (Vec<String>, Vec<String>) {}
+
+/// How the value of a serializable [`Content`] is serialized.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SerializeVTable {
+    type_tag: &'static str,
+    erase: fn(&Content) -> &dyn erased_serde::Serialize,
+}
+
+fn erase<T: Serializable>(content: &Content) -> &dyn erased_serde::Serialize {
+    content
+        .get::<T>()
+        .expect("the vtable matches the type of the content")
+}
+
+impl SerializeVTable {
+    pub(crate) fn of<T: Serializable>() -> Self {
+        Self {
+            type_tag: T::TYPE_TAG,
+            erase: erase::<T>,
+        }
+    }
+}
+
+impl Content {
+    /// Construct a [`Content`] which can be serialized, and decoded back by a [`ContentRegistry`].
+    pub fn serializable<H: Serializable>(val: H) -> Self {
+        let mut content = Self::new(val);
+        content.serialize = Some(SerializeVTable::of::<H>());
+        content
+    }
+
+    /// Whether the content can be serialized.
+    pub fn is_serializable(&self) -> bool {
+        self.serialized_parts().is_some()
+    }
+
+    /// The tag of the type of the value, and the value to serialize.
+    fn serialized_parts(&self) -> Option<(String, &dyn erased_serde::Serialize)> {
+        if let Some(vtable) = self.serialize {
+            return Some((vtable.type_tag.to_string(), (vtable.erase)(self)));
+        }
+        let (type_tag, value) = self.get::<Content>()?.serialized_parts()?;
+        Some((wrapping_tag(&type_tag), value))
+    }
+}
+
+impl Output {
+    /// Construct an [`Output`] whose content can be serialized, see [`Content::serializable`].
+    pub fn serializable<H: Serializable>(val: H) -> Self {
+        Self::Out(Some(Content::serializable(val)))
+    }
+}
+
+/// A content is serialized as the pair `(tag, value)`, it fails when the content was not created
+/// by [`Content::serializable`].
+impl Serialize for Content {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (type_tag, value) = self
+            .serialized_parts()
+            .ok_or_else(|| ser::Error::custom("the content is not serializable"))?;
+        let mut tuple = serializer.serialize_tuple(2)?;
+        tuple.serialize_element(&type_tag)?;
+        tuple.serialize_element(value)?;
+        tuple.end()
+    }
+}
+
+pub(crate) type DeserializeFn =
+    for<'de> fn(&mut dyn erased_serde::Deserializer<'de>) -> Result<Content, erased_serde::Error>;
+
+pub(crate) fn deserialize<T: Serializable>(
+    deserializer: &mut dyn erased_serde::Deserializer<'_>,
+) -> Result<Content, erased_serde::Error> {
+    erased_serde::deserialize::<T>(deserializer).map(Content::serializable)
+}
+
+/// The types the serialized contents can be decoded into, by their tags. It is built on the
+/// registry of the types stored in checkpoints, see [`Dag::register_checkpoint_type`](crate::Dag::register_checkpoint_type).
+#[derive(Clone, Default)]
+pub struct ContentRegistry {
+    codecs: Codecs,
+}
+
+impl ContentRegistry {
+    /// A registry without any type.
+    pub fn empty() -> Self {
+        Self {
+            codecs: Codecs::empty(),
+        }
+    }
+
+    /// Allow the contents of type `T` to be decoded, replacing the type registered with the same tag.
+    pub fn register<T: Serializable>(&mut self) {
+        self.codecs.register_serializable::<T>();
+    }
+
+    /// Whether the contents tagged `type_tag` can be decoded.
+    pub fn contains(&self, type_tag: &str) -> bool {
+        match wrapped_tag(type_tag) {
+            Some(inner) => self.contains(inner),
+            None => self.codecs.deserializer(type_tag).is_some(),
+        }
+    }
+
+    fn deserialize_tagged<'de, D: Deserializer<'de>>(
+        &self,
+        type_tag: &str,
+        deserializer: D,
+    ) -> Result<Content, D::Error> {
+        if let Some(inner) = wrapped_tag(type_tag) {
+            return self
+                .deserialize_tagged(inner, deserializer)
+                .map(Content::new);
+        }
+        let deserialize = self.codecs.deserializer(type_tag).ok_or_else(|| {
+            de::Error::custom(format!("unregistered content type `{}`", type_tag))
+        })?;
+        let mut erased = <dyn erased_serde::Deserializer>::erase(deserializer);
+        deserialize(&mut erased).map_err(de::Error::custom)
+    }
+}
+
+/// Decodes a content serialized as the pair `(tag, value)`.
+impl<'de> DeserializeSeed<'de> for &ContentRegistry {
+    type Value = Content;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Content, D::Error> {
+        deserializer.deserialize_tuple(2, ContentVisitor(self))
+    }
+}
+
+struct ContentVisitor<'a>(&'a ContentRegistry);
+
+impl<'de> Visitor<'de> for ContentVisitor<'_> {
+    type Value = Content;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a pair of a type tag and a value")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Content, A::Error> {
+        let type_tag: String = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        seq.next_element_seed(TaggedValue {
+            registry: self.0,
+            type_tag: &type_tag,
+        })?
+        .ok_or_else(|| de::Error::invalid_length(1, &self))
+    }
+}
+
+/// The value of a serialized content, whose type is known from its tag.
+struct TaggedValue<'a> {
+    registry: &'a ContentRegistry,
+    type_tag: &'a str,
+}
+
+impl<'de> DeserializeSeed<'de> for TaggedValue<'_> {
+    type Value = Content;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Content, D::Error> {
+        self.registry
+            .deserialize_tagged(self.type_tag, deserializer)
+    }
+}
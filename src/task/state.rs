@@ -23,32 +23,58 @@
 //! ```rust
 //! use dagrs::Output;
 //! use dagrs::task::Content;
-//! let err_out = Output::Err("some error messages!".to_string());
+//! let err_out = Output::error("some error messages!".to_string());
 //! ```
 //!
 //! # [`Input`]
 //!
 //! [`Input`] represents the input required by the task. The input comes from the output
 //! generated by multiple predecessor tasks of the task. If a predecessor task does not produce
-//! output, the output will not be stored in [`Input`].
+//! output, the output will not be returned by [`Input::get_iter`].
 //! [`Input`] will be used directly by the user without user construction. [`Input`] is actually
 //! constructed by cloning multiple [`Output`]. Users can obtain the content stored in [`Input`]
 //! to implement the logic of the program.
+//!
+//! The outputs are also keyed by the predecessor that produced them, so that a task with several
+//! predecessors can tell which value came from which, and whether a predecessor produced no output.
+//!
+//! ```rust
+//! use dagrs::{Dag, DefaultTask, Output};
+//!
+//! let fetch = DefaultTask::with_closure("fetch", |_input, _env| Output::new(2usize));
+//! let check = DefaultTask::with_closure("check", |_input, _env| Output::empty());
+//! let mut merge = DefaultTask::with_closure("merge", |input, _env| {
+//!     assert!(input.output_of("check").unwrap().is_none());
+//!     Output::new(*input.get_from::<usize>("fetch").unwrap() * 10)
+//! });
+//! merge.set_predecessors(&[&fetch, &check]);
+//! let mut dag = Dag::with_tasks(vec![fetch, check, merge]);
+//! assert!(dag.start().unwrap());
+//! assert_eq!(*dag.get_result::<usize>().unwrap(), 20);
+//! ```
 
 use std::{
-    any::Any, fmt::Debug, slice::Iter,
+    any::Any,
+    fmt::Debug,
+    slice::Iter,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc, Mutex,
+        Arc, Mutex, MutexGuard,
     },
 };
 
 use tokio::sync::Semaphore;
 
+use super::Expansion;
+use crate::engine::TaskRecord;
+
 /// Container type to store task output.
 #[derive(Debug, Clone)]
 pub struct Content {
     content: Arc<dyn Any + Send + Sync>,
+    /// How the content is serialized, when it was created by `Content::serializable`.
+    #[cfg(feature = "serde")]
+    pub(super) serialize: Option<super::SerializeVTable>,
 }
 
 impl Content {
@@ -56,11 +82,17 @@ impl Content {
     pub fn new<H: Send + Sync + 'static>(val: H) -> Self {
         Self {
             content: Arc::new(val),
+            #[cfg(feature = "serde")]
+            serialize: None,
         }
     }
 
     pub fn from_arc<H: Send + Sync + 'static>(val: Arc<H>) -> Self {
-        Self { content: val }
+        Self {
+            content: val,
+            #[cfg(feature = "serde")]
+            serialize: None,
+        }
     }
 
     pub fn get<H: 'static>(&self) -> Option<&H> {
@@ -81,6 +113,10 @@ impl Content {
 /// the output of the predecessor task as the input of this task.
 #[derive(Debug)]
 pub(crate) struct ExecState {
+    /// The id of the task.
+    id: usize,
+    /// The name of the task, the outputs are keyed by it in the inputs of its successors.
+    name: String,
     /// The execution succeed or not.
     success: AtomicBool,
     /// Output produced by a task.
@@ -93,6 +129,10 @@ pub(crate) struct ExecState {
     /// The task will obtain a permits synchronously (the permit will not be returned), which means
     /// that the subsequent task has obtained the execution result of this task.
     semaphore: Semaphore,
+    /// What happened to the task, used to build the report of the dag.
+    record: Mutex<TaskRecord>,
+    /// The states of the tasks this task expanded into at runtime, see [`Output::expand`].
+    expanded: Mutex<Option<Vec<Arc<ExecState>>>>,
 }
 
 /// Output produced by a task.
@@ -102,11 +142,20 @@ pub enum Output {
     Err(Option<Content>),
     ErrWithExitCode(Option<i32>, Option<Content>),
     Termination,
+    /// A normal output, which also selects the successors to run by name, the others are bypassed.
+    Branch(Option<Content>, Vec<String>),
+    /// A normal output, which expands the task into child tasks executed at runtime.
+    Expand(Expansion),
 }
 
 /// Task's input value.
-#[derive(Debug)]
-pub struct Input(Vec<Content>);
+#[derive(Debug, Clone, Default)]
+pub struct Input {
+    /// The non-empty outputs of the predecessors, in the order of the predecessors.
+    contents: Vec<Content>,
+    /// The outputs of the predecessors, keyed by their id and name. An empty output is `None`.
+    sources: Vec<(usize, String, Option<Content>)>,
+}
 
 pub trait ToErrorMessage {
     fn to_error_message(&self) -> String;
@@ -121,12 +170,16 @@ impl ToErrorMessage for String {
 
 impl ExecState {
     /// Construct a new [`ExeState`].
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(id: usize, name: &str) -> Self {
         // initialize the task to failure without output.
         Self {
+            id,
+            name: name.to_string(),
             success: AtomicBool::new(false),
             output: Arc::new(Mutex::new(Output::empty())),
             semaphore: Semaphore::new(0),
+            record: Mutex::new(TaskRecord::new()),
+            expanded: Mutex::new(None),
         }
     }
 
@@ -148,22 +201,6 @@ impl ExecState {
         self.success.load(Ordering::Relaxed)
     }
 
-    pub(crate) fn exe_success(&self) {
-        self.success.store(true, Ordering::Relaxed)
-    }
-
-    pub(crate) fn exe_fail(&self) {
-        self.success.store(false, Ordering::Relaxed)
-    }
-
-    /// A utility function to set the successful output and propagate down the line
-    /// by adding permits.
-    pub(crate) fn set_and_propagate_success(&self, output: Output, n_permits_to_add: usize) {
-        self.set_output(output);
-        self.exe_success();
-        self.semaphore().add_permits(n_permits_to_add);
-    }
-
     /// The semaphore is used to control the synchronous acquisition of task output results.
     /// Under normal circumstances, first use the semaphore to obtain a permit, and then call
     /// the `get_output` function to obtain the output. If the current task is not completed
@@ -172,6 +209,33 @@ impl ExecState {
     pub(crate) fn semaphore(&self) -> &Semaphore {
         &self.semaphore
     }
+
+    /// The execution record of the task.
+    pub(crate) fn record(&self) -> MutexGuard<'_, TaskRecord> {
+        self.record.lock().unwrap()
+    }
+
+    pub(crate) fn id(&self) -> usize {
+        self.id
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Record the states of the tasks this task expanded into, before its successors are woken up.
+    pub(crate) fn set_expanded(&self, states: Vec<Arc<ExecState>>) {
+        *self.expanded.lock().unwrap() = Some(states);
+    }
+
+    /// The states whose outputs are given to the successors of this task: those of the tasks it
+    /// expanded into, recursively, or its own.
+    pub(crate) fn gathered(self: &Arc<Self>) -> Vec<Arc<ExecState>> {
+        match self.expanded.lock().unwrap().as_ref() {
+            Some(states) => states.iter().flat_map(|state| state.gathered()).collect(),
+            None => vec![self.clone()],
+        }
+    }
 }
 
 impl Output {
@@ -192,6 +256,24 @@ impl Output {
         Self::Termination
     }
 
+    /// Construct an empty [`Output`] selecting the successors to run, given by name.
+    /// The other successors are bypassed, see the `condition` module.
+    pub fn branch<S: Into<String>>(successors: impl IntoIterator<Item = S>) -> Self {
+        Self::Branch(None, successors.into_iter().map(Into::into).collect())
+    }
+
+    /// Construct an [`Output`] selecting the successors to run, given by name.
+    /// The selected successors receive `val` as input.
+    pub fn branch_with<H: Send + Sync + 'static, S: Into<String>>(
+        val: H,
+        successors: impl IntoIterator<Item = S>,
+    ) -> Self {
+        Self::Branch(
+            Some(Content::new(val)),
+            successors.into_iter().map(Into::into).collect(),
+        )
+    }
+
     /// Construct an [`Output`]` with an error message.
     pub fn error<H: Send + Sync + Debug + ToErrorMessage + 'static>(msg: H) -> Self {
         Self::Err(Some(Content::new(msg)))
@@ -206,7 +288,7 @@ impl Output {
     pub(crate) fn is_err(&self) -> bool {
         match self {
             Self::Err(_) | Self::ErrWithExitCode(_, _) => true,
-            Self::Out(_) | Self::Termination => false,
+            Self::Out(_) | Self::Termination | Self::Branch(_, _) | Self::Expand(_) => false,
         }
     }
 
@@ -214,51 +296,118 @@ impl Output {
         matches!(self, Self::Termination)
     }
 
+    /// Whether the successor named `name` is selected by this output, all the successors
+    /// are selected unless it is a branch.
+    pub(crate) fn selects(&self, name: &str) -> bool {
+        match self {
+            Self::Branch(_, successors) => successors.iter().any(|s| s == name),
+            _ => true,
+        }
+    }
+
     /// Get the contents of [`Output`].
     pub(crate) fn get_out(&self) -> Option<Content> {
         match self {
             Self::Out(ref out) => out.clone(),
             Self::Err(ref out) => out.clone(),
+            Self::Branch(ref out, _) => out.clone(),
+            Self::Expand(_) => None,
             Self::ErrWithExitCode(_, _) | Self::Termination => None,
         }
     }
 
     /// Get error information stored in [`Output`].
+    ///
+    /// The message can be extracted from a `String` or a `&str`, and from the standard error
+    /// lines of a failed [`CommandAction`](crate::CommandAction).
     pub(crate) fn get_err(&self) -> Option<String> {
         match self {
-            Self::Out(_) | Self::Termination => None,
-            Self::Err(err) => {
-                err
-                    .as_ref()
-                    .map(|content| {
-                        // TODO: this `get` here always returns None
-                        content
-                            .get::<Box<dyn ToErrorMessage>>()
-                            .map(|m| m.to_error_message())
-                    })
-                    .flatten()
-
-            }
-            Self::ErrWithExitCode(_, err) => {
-                if let Some(e) = err {
-                    Some(e.get::<String>()?.to_string())
-                } else {
-                    None
-                }
+            Self::Out(_) | Self::Termination | Self::Branch(_, _) | Self::Expand(_) => None,
+            Self::Err(err) | Self::ErrWithExitCode(_, err) => {
+                err.as_ref().and_then(Self::error_message)
             }
         }
     }
+
+    fn error_message(content: &Content) -> Option<String> {
+        if let Some(msg) = content.get::<String>() {
+            Some(msg.clone())
+        } else if let Some(msg) = content.get::<&str>() {
+            Some(msg.to_string())
+        } else {
+            content
+                .get::<(Vec<String>, Vec<String>)>()
+                .map(|(_, stderr)| stderr.join("\n"))
+        }
+    }
 }
 
 impl Input {
     /// Constructs input using output produced by a non-empty predecessor task.
+    /// The contents are not keyed by any predecessor.
     pub fn new(input: Vec<Content>) -> Self {
-        Self(input)
+        Self {
+            contents: input,
+            sources: Vec::new(),
+        }
+    }
+
+    /// Add the output of the predecessor with the given id and name.
+    pub(crate) fn push(&mut self, id: usize, name: &str, content: Option<Content>) {
+        if let Some(content) = &content {
+            self.contents.push(content.clone());
+        }
+        self.sources.push((id, name.to_string(), content));
+    }
+
+    /// The contents not keyed by any predecessor, given to [`Input::new`].
+    #[cfg(feature = "serde")]
+    pub(crate) fn unkeyed(&self) -> &[Content] {
+        // The keyed contents are pushed after the unkeyed ones.
+        let keyed = self
+            .sources
+            .iter()
+            .filter(|(_, _, content)| content.is_some())
+            .count();
+        &self.contents[..self.contents.len() - keyed]
     }
 
     /// Since [`Input`] can contain multi-input values, and it's implemented
     /// by [`Vec`] actually, of course it can be turned into a iterator.
-    pub fn get_iter(&self) -> Iter<Content> {
-        self.0.iter()
+    /// It only yields the non-empty outputs.
+    pub fn get_iter(&self) -> Iter<'_, Content> {
+        self.contents.iter()
+    }
+
+    /// Get the output of the predecessor named `name`, if it is of type `H`.
+    pub fn get_from<H: 'static>(&self, name: &str) -> Option<&H> {
+        self.output_of(name).flatten()?.get()
+    }
+
+    /// Get the output of the predecessor with the given id, if it is of type `H`.
+    pub fn get_from_id<H: 'static>(&self, id: usize) -> Option<&H> {
+        self.sources
+            .iter()
+            .find(|(source, _, _)| *source == id)?
+            .2
+            .as_ref()?
+            .get()
+    }
+
+    /// Get the output of the predecessor named `name`. It is `None` if there is no such predecessor
+    /// in the input, and `Some(None)` if the predecessor returned an empty output.
+    pub fn output_of(&self, name: &str) -> Option<Option<&Content>> {
+        self.sources
+            .iter()
+            .find(|(_, source, _)| source == name)
+            .map(|(_, _, content)| content.as_ref())
+    }
+
+    /// Iterate over the outputs of the predecessors, with the id and name of the predecessor
+    /// that produced them, an empty output is `None`.
+    pub fn sources(&self) -> impl Iterator<Item = (usize, &str, Option<&Content>)> {
+        self.sources
+            .iter()
+            .map(|(id, name, content)| (*id, name.as_str(), content.as_ref()))
     }
 }
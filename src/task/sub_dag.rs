@@ -0,0 +1,172 @@
+//! A dag executed as a single task
+//!
+//! # [`SubDagTask`]
+//!
+//! Pipelines are often composed of reusable pieces. A [`SubDagTask`] runs an inner [`Dag`] as one
+//! task of an outer dag:
+//! - The input of the task is given to the tasks of the inner dag without predecessors.
+//! - The inner dag runs with the environment of the outer dag, and is cancelled with it.
+//! - The output of the exit task of the inner dag, the last one of its execution sequence, is the
+//!   output of the task. The task fails when the inner dag does not succeed.
+//! - The report of the inner dag is attached to the report of the task, see
+//!   [`TaskReport::sub_dag`](crate::TaskReport::sub_dag).
+//!
+//! The inner dag is reset before each execution of the task.
+//!
+//! # Example
+//!
+//! ```rust
+//! use dagrs::{Dag, DefaultTask, Output, SubDagTask, Task};
+//!
+//! let double = DefaultTask::with_closure("double", |input, _env| {
+//!     Output::new(input.get_iter().next().unwrap().get::<usize>().unwrap() * 2)
+//! });
+//! let mut increment = DefaultTask::with_closure("increment", |input, _env| {
+//!     Output::new(input.get_iter().next().unwrap().get::<usize>().unwrap() + 1)
+//! });
+//! increment.set_predecessors(&[&double]);
+//!
+//! let source = DefaultTask::with_closure("source", |_input, _env| Output::new(20usize));
+//! let mut sub = SubDagTask::new("double then increment", Dag::with_tasks(vec![double, increment]));
+//! sub.set_predecessors(&[&source]);
+//!
+//! let mut dag = Dag::with_tasks_dyn(vec![Box::new(source), Box::new(sub)]);
+//! let report = dag.start_with_report().unwrap();
+//! assert!(report.is_success());
+//! assert_eq!(*dag.get_result::<usize>().unwrap(), 41);
+//! let inner = report.tasks[1].sub_dag.as_ref().unwrap();
+//! assert_eq!(inner.tasks.len(), 2);
+//! ```
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+use super::{Action, Complex, Input, Output, Task, ID_ALLOCATOR};
+use crate::{Dag, DagReport, EnvVar};
+
+/// A task executing an inner [`Dag`].
+pub struct SubDagTask {
+    id: usize,
+    name: String,
+    precursors: Vec<usize>,
+    action: Action,
+}
+
+impl SubDagTask {
+    /// Create a task executing `dag`.
+    pub fn new(name: &str, dag: Dag<'static>) -> Self {
+        Self {
+            id: ID_ALLOCATOR.alloc(),
+            name: name.to_owned(),
+            precursors: Vec::new(),
+            action: SubDagAction::action(dag),
+        }
+    }
+
+    /// Set the tasks executed before this one.
+    pub fn set_predecessors<'a, T: Task + 'a>(
+        &mut self,
+        predecessors: impl IntoIterator<Item = &'a &'a T>,
+    ) {
+        self.precursors
+            .extend(predecessors.into_iter().map(|t| t.id()))
+    }
+
+    /// The same as `set_predecessors`, but input are tasks' ids.
+    pub fn set_predecessors_by_id(&mut self, predecessors_id: impl IntoIterator<Item = usize>) {
+        self.precursors.extend(predecessors_id)
+    }
+}
+
+impl Task for SubDagTask {
+    fn action(&self) -> Action {
+        self.action.clone()
+    }
+    fn precursors(&self) -> &[usize] {
+        &self.precursors
+    }
+    fn id(&self) -> usize {
+        self.id
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// The action executing the inner dag of a [`SubDagTask`], it is also used by the tasks of the
+/// yaml configuration files defining a sub-dag.
+pub(crate) struct SubDagAction {
+    dag: Mutex<Dag<'static>>,
+}
+
+impl SubDagAction {
+    pub(crate) fn action(dag: Dag<'static>) -> Action {
+        Action::Structure(Arc::new(Self {
+            dag: Mutex::new(dag),
+        }))
+    }
+}
+
+#[async_trait]
+impl Complex for SubDagAction {
+    fn run(&self, _input: Input, _env: Arc<EnvVar>) -> Output {
+        unreachable!("the inner dag is executed asynchronously")
+    }
+
+    async fn async_run(&self, input: Input, env: Arc<EnvVar>) -> Output {
+        let mut dag = self.dag.lock().await;
+        dag.reset();
+        dag.set_env(env.as_ref().clone());
+        dag.set_input(input);
+        // The inner dag has its own cancellation handle, it follows the one of the outer dag.
+        let inner = dag.cancellation_handle();
+        let outer = env.cancellation().clone();
+        let forward = tokio::spawn(async move {
+            outer.cancelled().await;
+            inner.cancel();
+        });
+        let result = dag.async_start_with_report().await;
+        forward.abort();
+        let report = match result {
+            Ok(report) => report,
+            Err(err) => return Output::error(format!("Cannot execute the inner dag: {}", err)),
+        };
+        let output = if report.is_success() {
+            Output::Out(dag.exit_output().and_then(|output| output.get_out()))
+        } else {
+            let failed: Vec<&str> = report.failed_tasks().map(|t| t.name.as_str()).collect();
+            Output::error(format!(
+                "The inner dag did not succeed ({:?}), failed tasks: [{}]",
+                report.outcome,
+                failed.join(", ")
+            ))
+        };
+        Output::new(SubDagOutput { output, report })
+    }
+
+    fn is_async(&self) -> bool {
+        true
+    }
+}
+
+/// The output of a [`SubDagAction`], the engine unwraps it and records the report of the inner dag.
+pub(crate) struct SubDagOutput {
+    output: Output,
+    report: DagReport,
+}
+
+impl SubDagOutput {
+    /// Split the output of a [`SubDagAction`] into the output of the task and the report of the
+    /// inner dag, other outputs are returned as is.
+    pub(crate) fn unwrap(output: Output) -> (Output, Option<DagReport>) {
+        match &output {
+            Output::Out(Some(content)) => match content.clone().into_inner::<SubDagOutput>() {
+                Some(sub) => (sub.output.clone(), Some(sub.report.clone())),
+                None => (output, None),
+            },
+            _ => (output, None),
+        }
+    }
+}
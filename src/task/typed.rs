@@ -0,0 +1,245 @@
+//! Tasks with typed inputs and outputs
+//!
+//! # [`TypedTask`]
+//!
+//! The outputs of the tasks are stored as [`Content`](super::Content), whose type is only checked
+//! at runtime when a successor reads it. A [`TypedTask<I, O>`] is a task taking an input of type `I`
+//! and producing an output of type `O`. It can only be connected to predecessors producing the
+//! type it takes, see [`TypedOutput`], so a mismatch is found by the compiler.
+//!
+//! The input of a typed task is either the output of one predecessor, a [`Vec`] gathering the
+//! outputs of several predecessors of the same type, or a pair of outputs of two predecessors.
+//! A task without predecessors is created with [`TypedTask::source`].
+//!
+//! A typed task is an ordinary [`Task`], so typed and untyped tasks can be mixed in one dag: an
+//! untyped task reads the output of a typed task like any other output.
+//!
+//! # Example
+//!
+//! ```rust
+//! use dagrs::{Dag, DefaultTask, Output, Task, TypedTask};
+//!
+//! let numbers = TypedTask::source("numbers", |_env| vec![1usize, 2, 3]);
+//! let mut sum = TypedTask::new("sum", |numbers: Vec<usize>, _env| numbers.iter().sum::<usize>());
+//! sum.set_predecessor(&numbers);
+//! let mut len = TypedTask::new("len", |numbers: Vec<usize>, _env| numbers.len());
+//! len.set_predecessor(&numbers);
+//! let mut mean = TypedTask::new("mean", |(sum, len): (usize, usize), _env| {
+//!     sum as f64 / len as f64
+//! });
+//! mean.set_predecessors(&sum, &len);
+//! let mut print = DefaultTask::with_closure("print", |input, _env| {
+//!     Output::new(format!("{:.1}", input.get_from::<f64>("mean").unwrap()))
+//! });
+//! print.set_predecessors_by_id([mean.id()]);
+//!
+//! let mut dag = Dag::with_tasks_dyn(vec![
+//!     Box::new(numbers),
+//!     Box::new(sum),
+//!     Box::new(len),
+//!     Box::new(mean),
+//!     Box::new(print),
+//! ]);
+//! assert!(dag.start().unwrap());
+//! assert_eq!(dag.get_result::<String>().unwrap().as_str(), "2.0");
+//! ```
+//!
+//! Connecting tasks of mismatched types does not compile:
+//!
+//! ```rust,compile_fail
+//! use dagrs::TypedTask;
+//!
+//! let numbers = TypedTask::source("numbers", |_env| vec![1usize, 2, 3]);
+//! let mut len = TypedTask::new("len", |text: String, _env| text.len());
+//! len.set_predecessor(&numbers);
+//! ```
+
+use std::{fmt::Debug, marker::PhantomData, sync::Arc, time::Duration};
+
+use super::{Action, DefaultTask, Input, Output, RetryPolicy, Task, ToErrorMessage};
+use crate::{CachePolicy, EnvVar, Executor};
+
+/// A task whose output is of type `O`, it can be the predecessor of the typed tasks taking `O`
+/// as input.
+///
+/// It is implemented by [`TypedTask`], and can be implemented by other tasks producing outputs
+/// of type `O`.
+pub trait TypedOutput<O>: Task {}
+
+/// A task taking an input of type `I` and producing an output of type `O`.
+pub struct TypedTask<I, O> {
+    /// The untyped task the typed task lowers onto, its action is set when the task is connected.
+    inner: DefaultTask,
+    precursors: Vec<usize>,
+    run: Arc<dyn Fn(I, Arc<EnvVar>) -> Output + Send + Sync>,
+    output: PhantomData<fn() -> O>,
+}
+
+impl<I: 'static, O: Send + Sync + 'static> TypedTask<I, O> {
+    /// Create a task computing its output from its input. Its predecessors must be set with
+    /// `set_predecessor` or `set_predecessors`.
+    pub fn new(name: &str, action: impl Fn(I, Arc<EnvVar>) -> O + Send + Sync + 'static) -> Self {
+        Self::with_output(name, move |input, env| Output::new(action(input, env)))
+    }
+
+    /// Create a task computing its output from its input, or failing with an error message.
+    pub fn fallible<E: Send + Sync + Debug + ToErrorMessage + 'static>(
+        name: &str,
+        action: impl Fn(I, Arc<EnvVar>) -> Result<O, E> + Send + Sync + 'static,
+    ) -> Self {
+        Self::with_output(name, move |input, env| match action(input, env) {
+            Ok(output) => Output::new(output),
+            Err(err) => Output::error(err),
+        })
+    }
+
+    fn with_output(
+        name: &str,
+        run: impl Fn(I, Arc<EnvVar>) -> Output + Send + Sync + 'static,
+    ) -> Self {
+        let mut task = Self {
+            inner: DefaultTask::new(name),
+            precursors: Vec::new(),
+            run: Arc::new(run),
+            output: PhantomData,
+        };
+        task.connect(Vec::new(), |_| None);
+        task
+    }
+
+    /// Set the predecessors of the task, and how its typed input is taken from their outputs.
+    fn connect(
+        &mut self,
+        precursors: Vec<usize>,
+        extract: impl Fn(&Input) -> Option<I> + Send + Sync + 'static,
+    ) {
+        self.precursors = precursors;
+        let run = self.run.clone();
+        let name = self.inner.name().to_string();
+        self.inner
+            .set_closure(move |input, env| match extract(&input) {
+                Some(input) => run(input, env),
+                None => Output::error(format!("The typed input of task {} is missing.", name)),
+            });
+    }
+
+    /// Set the policy used to execute the task again after a failure.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.inner.set_retry_policy(policy);
+    }
+
+    /// Set the maximum duration of one execution of the task.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.inner.set_timeout(timeout);
+    }
+
+    /// Declare that the task consumes `amount` of the resource pool `name` while executing.
+    pub fn add_resource(&mut self, name: &str, amount: u32) {
+        self.inner.add_resource(name, amount);
+    }
+
+    /// Allow the output of the task to be cached, see [`CachePolicy`].
+    pub fn set_cache_policy(&mut self, policy: CachePolicy) {
+        self.inner.set_cache_policy(policy);
+    }
+
+    /// Set the priority of the task, see [`DefaultTask::set_priority`].
+    pub fn set_priority(&mut self, priority: i32) {
+        self.inner.set_priority(priority);
+    }
+
+    /// Set the expected duration of an execution of the task.
+    pub fn set_estimated_duration(&mut self, duration: Duration) {
+        self.inner.set_estimated_duration(duration);
+    }
+
+    /// Run the action of the task on `executor` rather than on the executor of the dag.
+    pub fn set_executor(&mut self, executor: Arc<dyn Executor>) {
+        self.inner.set_executor(executor);
+    }
+}
+
+impl<O: Send + Sync + 'static> TypedTask<(), O> {
+    /// Create a task without predecessors.
+    pub fn source(name: &str, action: impl Fn(Arc<EnvVar>) -> O + Send + Sync + 'static) -> Self {
+        let mut task = Self::new(name, move |_, env| action(env));
+        task.connect(Vec::new(), |_| Some(()));
+        task
+    }
+}
+
+impl<I: Clone + Send + Sync + 'static, O: Send + Sync + 'static> TypedTask<I, O> {
+    /// Set the predecessor whose output is the input of the task.
+    pub fn set_predecessor(&mut self, predecessor: &impl TypedOutput<I>) {
+        let id = predecessor.id();
+        self.connect(vec![id], move |input| input.get_from_id::<I>(id).cloned());
+    }
+}
+
+impl<A: Clone + Send + Sync + 'static, O: Send + Sync + 'static> TypedTask<Vec<A>, O> {
+    /// Set the predecessors whose outputs, in the given order, are the input of the task.
+    pub fn set_predecessors(&mut self, predecessors: &[&dyn TypedOutput<A>]) {
+        let ids: Vec<usize> = predecessors.iter().map(|task| task.id()).collect();
+        self.connect(ids.clone(), move |input| {
+            ids.iter()
+                .map(|id| input.get_from_id::<A>(*id).cloned())
+                .collect()
+        });
+    }
+}
+
+impl<A, B, O> TypedTask<(A, B), O>
+where
+    A: Clone + Send + Sync + 'static,
+    B: Clone + Send + Sync + 'static,
+    O: Send + Sync + 'static,
+{
+    /// Set the two predecessors whose outputs are the input of the task.
+    pub fn set_predecessors(&mut self, first: &impl TypedOutput<A>, second: &impl TypedOutput<B>) {
+        let (a, b) = (first.id(), second.id());
+        self.connect(vec![a, b], move |input| {
+            Some((
+                input.get_from_id::<A>(a)?.clone(),
+                input.get_from_id::<B>(b)?.clone(),
+            ))
+        });
+    }
+}
+
+impl<I, O> TypedOutput<O> for TypedTask<I, O> {}
+
+impl<I, O> Task for TypedTask<I, O> {
+    fn action(&self) -> Action {
+        self.inner.action()
+    }
+    fn precursors(&self) -> &[usize] {
+        &self.precursors
+    }
+    fn id(&self) -> usize {
+        self.inner.id()
+    }
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        self.inner.retry_policy()
+    }
+    fn timeout(&self) -> Option<Duration> {
+        self.inner.timeout()
+    }
+    fn resources(&self) -> &[(String, u32)] {
+        self.inner.resources()
+    }
+    fn cache_policy(&self) -> Option<CachePolicy> {
+        self.inner.cache_policy()
+    }
+    fn priority(&self) -> i32 {
+        self.inner.priority()
+    }
+    fn estimated_duration(&self) -> Option<Duration> {
+        self.inner.estimated_duration()
+    }
+    fn executor(&self) -> Option<Arc<dyn Executor>> {
+        self.inner.executor()
+    }
+}
@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+/// Parse a duration such as `500ms`, `30s`, `5m`, `1h` or `1d`. A bare integer is a number of
+/// seconds. `None` if it is not a duration or it overflows.
+///
+/// # Example
+/// ```rust
+/// use dagrs::utils::parse_duration;
+/// use std::time::Duration;
+///
+/// assert_eq!(parse_duration("15m"), Some(Duration::from_secs(900)));
+/// assert_eq!(parse_duration("500 ms"), Some(Duration::from_millis(500)));
+/// assert_eq!(parse_duration("10"), Some(Duration::from_secs(10)));
+/// assert_eq!(parse_duration("3w"), None);
+/// assert_eq!(parse_duration("999999999999999999d"), None);
+/// ```
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().ok()?;
+    match unit.trim() {
+        "ms" => Some(Duration::from_millis(number)),
+        "" | "s" => Some(Duration::from_secs(number)),
+        "m" => Some(Duration::from_secs(number.checked_mul(60)?)),
+        "h" => Some(Duration::from_secs(number.checked_mul(3600)?)),
+        "d" => Some(Duration::from_secs(number.checked_mul(86400)?)),
+        _ => None,
+    }
+}
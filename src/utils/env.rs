@@ -1,4 +1,4 @@
-use crate::task::Content;
+use crate::{task::Content, CancellationHandle};
 
 use std::collections::HashMap;
 
@@ -11,9 +11,13 @@ pub type Variable = Content;
 /// Before all tasks run, the user builds a [`EnvVar`] and sets all the environment
 /// variables. One [`EnvVar`] corresponds to one dag. All tasks in a job can
 /// be shared and immutable at runtime. environment variables.
-#[derive(Debug, Default)]
+///
+/// When the dag runs, its [`EnvVar`] also carries the [`CancellationHandle`] of the dag, so that
+/// actions can observe a cancellation.
+#[derive(Debug, Default, Clone)]
 pub struct EnvVar {
     variables: HashMap<String, Variable>,
+    cancellation: CancellationHandle,
 }
 
 impl EnvVar {
@@ -21,6 +25,7 @@ impl EnvVar {
     pub fn new() -> Self {
         Self {
             variables: HashMap::new(),
+            cancellation: CancellationHandle::new(),
         }
     }
 
@@ -37,6 +42,14 @@ impl EnvVar {
         self.variables.insert(name.to_owned(), v);
     }
 
+    /// Set a global variable which can be serialized, so that it is also available to the
+    /// actions executed in a [`WorkerPool`](crate::WorkerPool).
+    #[cfg(feature = "serde")]
+    pub fn set_serializable<H: crate::Serializable>(&mut self, name: &str, var: H) {
+        self.variables
+            .insert(name.to_owned(), Variable::serializable(var));
+    }
+
     /// Get environment variables through keys of type &str.
     ///
     /// Note: This method will clone the value. To avoid cloning, use [`get_ref`].
@@ -52,4 +65,59 @@ impl EnvVar {
             None
         }
     }
+
+    /// Get the raw content of an environment variable.
+    pub(crate) fn get_content(&self, name: &str) -> Option<&Variable> {
+        self.variables.get(name)
+    }
+
+    /// Iterate over the variables, by name.
+    #[cfg(feature = "serde")]
+    pub(crate) fn variables(&self) -> impl Iterator<Item = (&str, &Variable)> {
+        self.variables
+            .iter()
+            .map(|(name, variable)| (name.as_str(), variable))
+    }
+
+    /// Set the raw content of an environment variable.
+    pub(crate) fn set_content(&mut self, name: &str, variable: Variable) {
+        self.variables.insert(name.to_owned(), variable);
+    }
+
+    /// Get the cancellation handle of the dag this environment belongs to.
+    ///
+    /// # Example
+    /// ```rust
+    /// use dagrs::{Complex, EnvVar, Input, Output};
+    /// use std::sync::Arc;
+    ///
+    /// struct Poll;
+    ///
+    /// #[async_trait::async_trait]
+    /// impl Complex for Poll {
+    ///     fn run(&self, _input: Input, _env: Arc<EnvVar>) -> Output {
+    ///         Output::empty()
+    ///     }
+    ///
+    ///     async fn async_run(&self, _input: Input, env: Arc<EnvVar>) -> Output {
+    ///         let cancellation = env.cancellation();
+    ///         while !cancellation.is_cancelled() {
+    ///             // poll something...
+    /// #           break;
+    ///         }
+    ///         Output::empty()
+    ///     }
+    ///
+    ///     fn is_async(&self) -> bool {
+    ///         true
+    ///     }
+    /// }
+    /// ```
+    pub fn cancellation(&self) -> &CancellationHandle {
+        &self.cancellation
+    }
+
+    pub(crate) fn set_cancellation(&mut self, cancellation: CancellationHandle) {
+        self.cancellation = cancellation;
+    }
 }
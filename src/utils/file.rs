@@ -1,8 +1,9 @@
 use std::fs::File;
 use std::io::{Error, Read};
+use std::path::Path;
 
 /// Given file path, and load configuration file.
-pub fn load_file(file: &str) -> Result<String, Error> {
+pub fn load_file(file: impl AsRef<Path>) -> Result<String, Error> {
     let mut content = String::new();
     let mut fh = File::open(file)?;
     fh.read_to_string(&mut content)?;
@@ -3,9 +3,11 @@
 //! This module contains common tools for the program, such as: environment
 //! variables, task generation macros.
 
+mod duration;
 mod env;
 pub mod file;
 mod parser;
 
+pub use self::duration::parse_duration;
 pub use self::env::EnvVar;
 pub use self::parser::{ParseError, Parser};
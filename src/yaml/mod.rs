@@ -42,6 +42,75 @@
 //!     cmd: echo h
 //! ```
 //!
+//! A task can be executed again when it fails by giving it a `retry` attribute. It is either the
+//! maximum number of attempts, or a map describing the whole retry policy:
+//!
+//! ```yaml
+//! dagrs:
+//!   a:
+//!     name: "Download"
+//!     cmd: curl -f https://example.com
+//!     retry:
+//!       attempts: 5
+//!       backoff: exponential
+//!       delay: 500ms
+//!       max_delay: 30s
+//!       jitter: true
+//!       on_exit_codes: [ 6, 7 ]
+//!   b:
+//!     name: "Echo"
+//!     after: [ a ]
+//!     cmd: echo b
+//!     retry: 3
+//! ```
+//!
+//! `backoff` is `fixed` (the default) or `exponential`.
+//!
+//! The duration of each execution of a task can be bounded by a `timeout` attribute, such as
+//! `timeout: 30s`. A timed out command is killed and the task fails.
+//!
+//! A task can declare the resources it consumes while executing, as a map of resource pool names
+//! to amounts, such as `resources: { gpu-slot: 1, db: 2 }`. The resource pools are defined on the
+//! dag, see `Dag::add_resource_pool`, or with the `--resource` option of the `dagrs` command.
+//!
+//! By default a task runs once all its predecessors succeeded. A `trigger_rule` attribute among
+//! `all_success`, `any_success`, `all_done` and `none_failed` changes that, for example a cleanup
+//! task with `trigger_rule: all_done` runs even when its predecessors failed, provided the dag
+//! keeps going after errors.
+//!
+//! Instead of a `cmd`, a task may run a whole sub-dag as a single task, defined either in another
+//! configuration file with `dag: path/to/other.yaml`, or inline with a `tasks` attribute mapping
+//! ids to tasks, in the same format as the content of `dagrs`. The path is relative to the
+//! directory of the including file, and a file cannot include itself, even indirectly. The input
+//! of the task is given to the tasks of the sub-dag without predecessors, and the output of its
+//! last task is the output of the task.
+//!
+//! Like the rules of a makefile, a command can declare the files it reads and writes with the
+//! `inputs` and `outputs` attributes, as lists of paths or glob patterns:
+//!
+//! ```yaml
+//! dagrs:
+//!   compile:
+//!     name: "Compile"
+//!     cmd: cc -o build/app src/*.c
+//!     inputs: [ "src/*.c", "src/*.h" ]
+//!     outputs: [ build/app ]
+//! ```
+//!
+//! The command is not executed when all its outputs exist and are newer than all its inputs,
+//! the task is then reported as up to date. The `--force` option of the `dagrs` command, or
+//! `Dag::force_task`, executes a task anyway. With `cache: true`, a task is also not executed
+//! when the contents of its inputs did not change since an execution whose output was cached,
+//! see `Dag::set_cache_store` and the `--cache` option of the `dagrs` command.
+//!
+//! When the tasks ready to execute compete for the parallelism or the resources of the dag, an
+//! integer `priority` attribute decides which ones start first, the higher the sooner. With the
+//! critical-path scheduling, see `Dag::set_scheduling_policy` and the `--scheduling` option of the
+//! `dagrs` command, the tasks on the longest chain start first, given the durations of their last
+//! execution or the durations estimated by their `estimate` attribute, such as `estimate: 2m`.
+//!
+//! Durations are written as an integer followed by a unit among `ms`, `s`, `m`, `h` and `d`.
+//!
 //! Users can read the yaml configuration file programmatically or by using the compiled `dagrs`
 //! command line tool. Either way, you need to enable the `yaml` feature.
 //!
@@ -77,6 +146,33 @@ pub enum YamlTaskError {
     /// `script` is not defined.
     #[error("The 'script' attribute is not defined. [{0}]")]
     NoScriptAttr(String),
+    /// The `retry` attribute is malformed.
+    #[error("Illegal 'retry' attribute: {1}. [{0}]")]
+    IllegalRetryAttr(String, String),
+    /// The `timeout` attribute is not a duration.
+    #[error("Illegal 'timeout' attribute. [{0}]")]
+    IllegalTimeoutAttr(String),
+    /// The `resources` attribute is not a map of resource names to amounts.
+    #[error("Illegal 'resources' attribute. [{0}]")]
+    IllegalResourcesAttr(String),
+    /// The `trigger_rule` attribute is not the name of a trigger rule.
+    #[error("Illegal 'trigger_rule' attribute. [{0}]")]
+    IllegalTriggerRuleAttr(String),
+    /// The sub-dag defined by the `dag` or `tasks` attribute cannot be parsed.
+    #[error("Illegal sub-dag: {1}. [{0}]")]
+    IllegalSubDagAttr(String, String),
+    /// The `inputs` or `outputs` attribute is not a list of paths, or the task is not a command.
+    #[error("Illegal 'inputs' or 'outputs' attribute: {1}. [{0}]")]
+    IllegalFilesAttr(String, String),
+    /// The `cache` attribute is not a boolean.
+    #[error("Illegal 'cache' attribute. [{0}]")]
+    IllegalCacheAttr(String),
+    /// The `priority` attribute is not an integer.
+    #[error("Illegal 'priority' attribute. [{0}]")]
+    IllegalPriorityAttr(String),
+    /// The `estimate` attribute is not a duration.
+    #[error("Illegal 'estimate' attribute. [{0}]")]
+    IllegalEstimateAttr(String),
 }
 
 /// Error about file information.
@@ -1,9 +1,17 @@
 //! Default yaml configuration file parser.
 
 use super::{FileContentError, YamlTask, YamlTaskError};
-use crate::{utils::file::load_file, utils::ParseError, Action, CommandAction, Parser, Task};
-use std::{collections::HashMap, sync::Arc};
-use yaml_rust::{Yaml, YamlLoader};
+use crate::{
+    task::SubDagAction, utils::file::load_file, utils::ParseError, Action, Backoff, CachePolicy,
+    CommandAction, Dag, Parser, RetryPolicy, Task,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use yaml_rust::{yaml::Hash, Yaml, YamlLoader};
 
 /// An implementation of [`Parser`]. It is the default yaml configuration file parser.
 pub struct YamlParser;
@@ -16,12 +24,39 @@ impl YamlParser {
     ///    name: "Task 1"
     ///    after: [b, c]
     ///    cmd: echo a
+    ///    timeout: 30s
+    ///    resources: { db: 1 }
+    ///    trigger_rule: all_done
+    ///    inputs: [src/*.c]
+    ///    outputs: [build/app]
+    ///    cache: true
+    ///    priority: 10
+    ///    estimate: 2m
+    /// ```
+    ///
+    /// Instead of `cmd`, an item may define a sub-dag executed as a single task, either with the
+    /// path of another configuration file, relative to the directory of the including file:
+    ///
+    /// ```yaml
+    ///    name: "Task 2"
+    ///    dag: path/to/other.yaml
+    /// ```
+    ///
+    /// or with its tasks inline:
+    ///
+    /// ```yaml
+    ///    name: "Task 2"
+    ///    tasks:
+    ///      x:
+    ///        name: "Task 2.1"
+    ///        cmd: echo x
     /// ```
     fn parse_one(
         &self,
         id: &str,
         item: &Yaml,
         specific_action: Option<Action>,
+        includes: &[PathBuf],
     ) -> Result<YamlTask, YamlTaskError> {
         // Get name first
         let name = item["name"]
@@ -36,36 +71,148 @@ impl YamlParser {
                 .for_each(|task_id| precursors.push(task_id.as_str().unwrap().to_owned()));
         }
 
-        if let Some(action) = specific_action {
-            Ok(YamlTask::new(id, precursors, name, action))
+        let inputs = parse_files(id, item, "inputs")?;
+        let outputs = parse_files(id, item, "outputs")?;
+        let is_command =
+            specific_action.is_none() && item["dag"].is_badvalue() && item["tasks"].is_badvalue();
+        // Only the commands of the configuration file read and write the declared files.
+        if !is_command && (!inputs.is_empty() || !outputs.is_empty()) {
+            return Err(YamlTaskError::IllegalFilesAttr(
+                id.to_owned(),
+                "only commands can declare files".to_string(),
+            ));
+        }
+        let mut task = if let Some(action) = specific_action {
+            YamlTask::new(id, precursors, name, action)
+        } else if let Some(dag) = self.parse_sub_dag(id, item, includes)? {
+            YamlTask::new(id, precursors, name, SubDagAction::action(dag))
         } else {
             let cmd = item["cmd"]
                 .as_str()
                 .ok_or(YamlTaskError::NoScriptAttr(name.clone()))?;
-            Ok(YamlTask::new(
-                id,
-                precursors,
-                name,
-                Action::Structure(Arc::new(CommandAction::new(cmd))),
-            ))
+            let mut action = CommandAction::new(cmd);
+            inputs.iter().for_each(|file| action.add_input_file(file));
+            outputs.iter().for_each(|file| action.add_output_file(file));
+            YamlTask::new(id, precursors, name, Action::Structure(Arc::new(action)))
+        };
+        if let Some(policy) = self.parse_retry(id, &item["retry"])? {
+            task.set_retry_policy(policy);
+        }
+        match &item["timeout"] {
+            Yaml::BadValue => {}
+            timeout => task.set_timeout(
+                parse_duration(timeout).ok_or(YamlTaskError::IllegalTimeoutAttr(id.to_owned()))?,
+            ),
+        }
+        match &item["resources"] {
+            Yaml::BadValue => {}
+            Yaml::Hash(resources) => {
+                for (name, amount) in resources {
+                    let (Some(name), Some(amount)) = (
+                        name.as_str(),
+                        amount
+                            .as_i64()
+                            .and_then(|amount| u32::try_from(amount).ok()),
+                    ) else {
+                        return Err(YamlTaskError::IllegalResourcesAttr(id.to_owned()));
+                    };
+                    task.add_resource(name, amount);
+                }
+            }
+            _ => return Err(YamlTaskError::IllegalResourcesAttr(id.to_owned())),
+        }
+        match &item["trigger_rule"] {
+            Yaml::BadValue => {}
+            rule => task.set_trigger_rule(
+                rule.as_str()
+                    .and_then(|rule| rule.parse().ok())
+                    .ok_or(YamlTaskError::IllegalTriggerRuleAttr(id.to_owned()))?,
+            ),
+        }
+        match &item["cache"] {
+            Yaml::BadValue | Yaml::Boolean(false) => {}
+            Yaml::Boolean(true) => task.set_cache_policy(CachePolicy::new()),
+            _ => return Err(YamlTaskError::IllegalCacheAttr(id.to_owned())),
+        }
+        match &item["priority"] {
+            Yaml::BadValue => {}
+            priority => task.set_priority(
+                priority
+                    .as_i64()
+                    .and_then(|priority| i32::try_from(priority).ok())
+                    .ok_or(YamlTaskError::IllegalPriorityAttr(id.to_owned()))?,
+            ),
+        }
+        match &item["estimate"] {
+            Yaml::BadValue => {}
+            estimate => task.set_estimated_duration(
+                parse_duration(estimate)
+                    .ok_or(YamlTaskError::IllegalEstimateAttr(id.to_owned()))?,
+            ),
         }
+        Ok(task)
     }
-}
 
-impl Parser for YamlParser {
-    fn parse_tasks(
+    /// Parses the sub-dag defined by the `dag` or `tasks` attribute of a task, if any.
+    ///
+    /// `includes` is the chain of configuration files being parsed, the last one defines the task.
+    /// The inner dag is checked for loops here, rather than when the outer dag runs.
+    fn parse_sub_dag(
         &self,
-        file: &str,
+        id: &str,
+        item: &Yaml,
+        includes: &[PathBuf],
+    ) -> Result<Option<Dag<'static>>, YamlTaskError> {
+        let illegal = |err: ParseError| YamlTaskError::IllegalSubDagAttr(id.to_owned(), err.0);
+        let tasks = match (&item["dag"], &item["tasks"]) {
+            (Yaml::BadValue, Yaml::BadValue) => return Ok(None),
+            (Yaml::String(file), Yaml::BadValue) => {
+                let file = match includes.last().and_then(|parent| parent.parent()) {
+                    Some(dir) => dir.join(file),
+                    None => PathBuf::from(file),
+                };
+                self.parse_file(&file, HashMap::new(), includes)
+                    .map_err(illegal)?
+            }
+            (Yaml::BadValue, Yaml::Hash(tasks)) => self
+                .parse_hash(tasks, &mut HashMap::new(), includes)
+                .map_err(illegal)?,
+            _ => {
+                return Err(illegal(ParseError(
+                    "expected either a 'dag' file or a map of 'tasks'".to_string(),
+                )))
+            }
+        };
+        let mut dag = Dag::with_tasks_dyn(tasks);
+        dag.init()
+            .map_err(|err| YamlTaskError::IllegalSubDagAttr(id.to_owned(), err.to_string()))?;
+        Ok(Some(dag))
+    }
+
+    /// Parses a configuration file included by the chain of files `includes`, and fails if the
+    /// file is already part of the chain.
+    fn parse_file(
+        &self,
+        file: &Path,
         specific_actions: HashMap<String, Action>,
+        includes: &[PathBuf],
     ) -> Result<Vec<Box<dyn Task>>, ParseError> {
         let content = load_file(file)?;
-        self.parse_tasks_from_str(&content, specific_actions)
+        let path = file.canonicalize()?;
+        if includes.contains(&path) {
+            return Err(ParseError(format!("{} includes itself", file.display())));
+        }
+        let mut includes = includes.to_vec();
+        includes.push(path);
+        self.parse_content(&content, specific_actions, &includes)
     }
 
-    fn parse_tasks_from_str(
+    /// Parses the content of a configuration file, see [`YamlParser::parse_file`].
+    fn parse_content(
         &self,
         content: &str,
         mut specific_actions: HashMap<String, Action>,
+        includes: &[PathBuf],
     ) -> Result<Vec<Box<dyn Task>>, ParseError> {
         // Parse Yaml
         let yaml_tasks =
@@ -76,7 +223,16 @@ impl Parser for YamlParser {
         let yaml_tasks = yaml_tasks[0]["dagrs"]
             .as_hash()
             .ok_or(YamlTaskError::StartWordError)?;
+        self.parse_hash(yaml_tasks, &mut specific_actions, includes)
+    }
 
+    /// Parses a map of tasks, such as the content of the `dagrs` key.
+    fn parse_hash(
+        &self,
+        yaml_tasks: &Hash,
+        specific_actions: &mut HashMap<String, Action>,
+        includes: &[PathBuf],
+    ) -> Result<Vec<Box<dyn Task>>, ParseError> {
         let mut tasks = Vec::with_capacity(yaml_tasks.len());
         let mut map = HashMap::with_capacity(yaml_tasks.len());
         // Read tasks
@@ -85,8 +241,8 @@ impl Parser for YamlParser {
                 .as_str()
                 .ok_or(ParseError("Invalid YAML Node Type".to_string()))?;
             let task = specific_actions.remove(id).map_or_else(
-                || self.parse_one(id, w, None),
-                |action| self.parse_one(id, w, Some(action)),
+                || self.parse_one(id, w, None, includes),
+                |action| self.parse_one(id, w, Some(action), includes),
             )?;
             map.insert(id, task.id());
             tasks.push(task);
@@ -109,4 +265,111 @@ impl Parser for YamlParser {
             .map(|task| Box::new(task) as Box<dyn Task>)
             .collect())
     }
+
+    /// Parses the `retry` attribute of a task. It is either the number of attempts:
+    ///
+    /// ```yaml
+    ///    retry: 3
+    /// ```
+    ///
+    /// or a map describing the policy:
+    ///
+    /// ```yaml
+    ///    retry:
+    ///      attempts: 3
+    ///      backoff: exponential
+    ///      delay: 1s
+    ///      max_delay: 30s
+    ///      jitter: true
+    ///      on_exit_codes: [ 1 ]
+    /// ```
+    fn parse_retry(&self, id: &str, item: &Yaml) -> Result<Option<RetryPolicy>, YamlTaskError> {
+        let illegal = |msg: &str| YamlTaskError::IllegalRetryAttr(id.to_owned(), msg.to_owned());
+        match item {
+            Yaml::BadValue => Ok(None),
+            Yaml::Integer(attempts) => Ok(Some(RetryPolicy::new(
+                u32::try_from(*attempts)
+                    .ok()
+                    .filter(|&attempts| attempts > 0)
+                    .ok_or(illegal("attempts must be a positive integer"))?,
+            ))),
+            Yaml::Hash(_) => {
+                let attempts = item["attempts"]
+                    .as_i64()
+                    .and_then(|attempts| u32::try_from(attempts).ok())
+                    .filter(|&attempts| attempts > 0)
+                    .ok_or(illegal("'attempts' must be a positive integer"))?;
+                let delay = match &item["delay"] {
+                    Yaml::BadValue => Duration::ZERO,
+                    delay => parse_duration(delay).ok_or(illegal("invalid 'delay'"))?,
+                };
+                let backoff = match item["backoff"].as_str() {
+                    None | Some("fixed") => Backoff::Fixed(delay),
+                    Some("exponential") => {
+                        let max = match &item["max_delay"] {
+                            Yaml::BadValue => Duration::MAX,
+                            max => parse_duration(max).ok_or(illegal("invalid 'max_delay'"))?,
+                        };
+                        Backoff::exponential(delay, max)
+                    }
+                    Some(_) => return Err(illegal("unknown 'backoff'")),
+                };
+                let mut policy = RetryPolicy::new(attempts)
+                    .with_backoff(backoff)
+                    .with_jitter(item["jitter"].as_bool().unwrap_or(false));
+                if let Some(codes) = item["on_exit_codes"].as_vec() {
+                    let codes = codes
+                        .iter()
+                        .map(|code| code.as_i64().and_then(|code| i32::try_from(code).ok()))
+                        .collect::<Option<Vec<i32>>>()
+                        .ok_or(illegal("invalid 'on_exit_codes'"))?;
+                    policy = policy.retry_on_exit_codes(codes);
+                }
+                Ok(Some(policy))
+            }
+            _ => Err(illegal("expected an integer or a map")),
+        }
+    }
+}
+
+/// Parses the files declared by the `inputs` or `outputs` attribute of a task, as a list of paths
+/// or glob patterns.
+fn parse_files<'a>(id: &str, item: &'a Yaml, attr: &str) -> Result<Vec<&'a str>, YamlTaskError> {
+    let illegal = || {
+        YamlTaskError::IllegalFilesAttr(id.to_owned(), format!("'{}' is not a list of paths", attr))
+    };
+    match &item[attr] {
+        Yaml::BadValue => Ok(Vec::new()),
+        Yaml::Array(files) => files
+            .iter()
+            .map(|file| file.as_str().ok_or_else(illegal))
+            .collect(),
+        _ => Err(illegal()),
+    }
+}
+
+/// Parses a duration, see [`crate::utils::parse_duration`]. A bare integer is a number of seconds.
+fn parse_duration(item: &Yaml) -> Option<Duration> {
+    if let Some(secs) = item.as_i64() {
+        return u64::try_from(secs).ok().map(Duration::from_secs);
+    }
+    crate::utils::parse_duration(item.as_str()?)
+}
+
+impl Parser for YamlParser {
+    fn parse_tasks(
+        &self,
+        file: &str,
+        specific_actions: HashMap<String, Action>,
+    ) -> Result<Vec<Box<dyn Task>>, ParseError> {
+        self.parse_file(Path::new(file), specific_actions, &[])
+    }
+
+    fn parse_tasks_from_str(
+        &self,
+        content: &str,
+        specific_actions: HashMap<String, Action>,
+    ) -> Result<Vec<Box<dyn Task>>, ParseError> {
+        self.parse_content(content, specific_actions, &[])
+    }
 }
@@ -7,7 +7,8 @@
 //! It is different from `DefaultTask`, in addition to the four mandatory attributes of the
 //! task type, he has several additional attributes.
 
-use crate::{alloc_id, Action, Task};
+use crate::{alloc_id, Action, CachePolicy, RetryPolicy, Task, TriggerRule};
+use std::time::Duration;
 
 /// Task struct for yaml file.
 pub struct YamlTask {
@@ -19,6 +20,20 @@ pub struct YamlTask {
     precursors: Vec<String>,
     precursors_id: Vec<usize>,
     action: Action,
+    /// Retry policy defined by the `retry` attribute in yaml.
+    retry_policy: Option<RetryPolicy>,
+    /// Timeout defined by the `timeout` attribute in yaml.
+    timeout: Option<Duration>,
+    /// Resources defined by the `resources` attribute in yaml.
+    resources: Vec<(String, u32)>,
+    /// Trigger rule defined by the `trigger_rule` attribute in yaml.
+    trigger_rule: TriggerRule,
+    /// Cache policy enabled by the `cache` attribute in yaml.
+    cache_policy: Option<CachePolicy>,
+    /// Priority defined by the `priority` attribute in yaml.
+    priority: i32,
+    /// Estimated duration defined by the `estimate` attribute in yaml.
+    estimated_duration: Option<Duration>,
 }
 
 impl YamlTask {
@@ -31,8 +46,51 @@ impl YamlTask {
             precursors,
             precursors_id: Vec::new(),
             action,
+            retry_policy: None,
+            timeout: None,
+            resources: Vec::new(),
+            trigger_rule: TriggerRule::AllSuccess,
+            cache_policy: None,
+            priority: 0,
+            estimated_duration: None,
         }
     }
+
+    /// Set the policy used to execute the task again after a failure.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = Some(policy);
+    }
+
+    /// Set the maximum duration of one execution of the task.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    /// Declare that the task consumes `amount` of the resource pool `name` while executing.
+    pub fn add_resource(&mut self, name: &str, amount: u32) {
+        self.resources.push((name.to_string(), amount));
+    }
+
+    /// Set the rule deciding whether the task runs given the state of its predecessors.
+    pub fn set_trigger_rule(&mut self, rule: TriggerRule) {
+        self.trigger_rule = rule;
+    }
+
+    /// Allow the output of the task to be cached, see [`CachePolicy`].
+    pub fn set_cache_policy(&mut self, policy: CachePolicy) {
+        self.cache_policy = Some(policy);
+    }
+
+    /// Set the priority of the task, the higher the sooner it is started.
+    pub fn set_priority(&mut self, priority: i32) {
+        self.priority = priority;
+    }
+
+    /// Set the expected duration of an execution of the task.
+    pub fn set_estimated_duration(&mut self, duration: Duration) {
+        self.estimated_duration = Some(duration);
+    }
+
     /// After the configuration file is parsed, the id of each task has been assigned.
     /// At this time, the `precursors_id` of this task will be initialized according to
     /// the id of the predecessor task of each task.
@@ -66,4 +124,25 @@ impl Task for YamlTask {
     fn name(&self) -> &str {
         &self.name
     }
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        self.retry_policy.clone()
+    }
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+    fn resources(&self) -> &[(String, u32)] {
+        &self.resources
+    }
+    fn trigger_rule(&self) -> TriggerRule {
+        self.trigger_rule
+    }
+    fn cache_policy(&self) -> Option<CachePolicy> {
+        self.cache_policy.clone()
+    }
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+    fn estimated_duration(&self) -> Option<Duration> {
+        self.estimated_duration
+    }
 }
@@ -1,8 +1,23 @@
 //! Some tests of the dag engine.
 
-use std::{collections::HashMap, env::set_var, sync::Arc};
+use std::{
+    collections::HashMap,
+    env::set_var,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
-use dagrs::{Complex, Dag, DagError, DefaultTask, EnvVar, Input, Output};
+use dagrs::{
+    task::Content, Backoff, CachePolicy, CacheStore, CheckpointStore, Checkpointable,
+    CommandAction, Complex, Dag, DagError, DagMetadata, DagObserver, DagOutcome, DagReport,
+    DefaultTask, Engine, EnvVar, Executor, FileCacheStore, FileCheckpointStore, Input, ManualClock,
+    MemoryCacheStore, MissedRuns, Output, OutputMessage, RetryPolicy, Schedule, SchedulingPolicy,
+    SerialExecutor, StoredOutput, SubDagTask, Task, TaskCheckpoint, TaskReport, TaskStatus,
+    TokioExecutor, TriggerRule, TypedOutput, TypedTask,
+};
 
 #[test]
 fn yaml_task_correct_execute() {
@@ -68,11 +83,11 @@ impl Complex for FailedActionC {
     }
 }
 
-struct FailedActionD(usize);
+struct FailedActionD;
 
 impl Complex for FailedActionD {
     fn run(&self, _input: Input, _env: Arc<EnvVar>) -> Output {
-        Output::Err("error".to_string())
+        Output::error("error".to_string())
     }
 }
 
@@ -98,7 +113,7 @@ fn test_dag(keep_going: bool, num_some_output: Option<usize>) {
     let a = generate_task!(A(1), "Compute A");
     let mut b = generate_task!(B(2), "Compute B");
     let mut c = DefaultTask::with_action("Compute C", FailedActionC(0));
-    let mut d = DefaultTask::with_action("Compute D", FailedActionD(1));
+    let mut d = DefaultTask::with_action("Compute D", FailedActionD);
     let mut e: DefaultTask = generate_task!(E(16), "Compute E");
     let mut f = generate_task!(F(32), "Compute F");
     let mut g = generate_task!(G(64), "Compute G");
@@ -152,3 +167,2223 @@ fn task_failed_execute() {
 fn task_keep_going() {
     test_dag(true, Some(8));
 }
+
+fn flaky_task(name: &str, failures: usize, counter: Arc<AtomicUsize>) -> DefaultTask {
+    DefaultTask::with_closure(name, move |_, _| {
+        if counter.fetch_add(1, Ordering::SeqCst) < failures {
+            Output::error_with_exit_code(Some(1), None)
+        } else {
+            Output::new(1usize)
+        }
+    })
+}
+
+#[test]
+fn task_retry_until_success() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let mut task = flaky_task("flaky", 2, counter.clone());
+    task.set_retry_policy(
+        RetryPolicy::new(3).with_backoff(Backoff::Fixed(Duration::from_millis(10))),
+    );
+    let mut job = Dag::with_tasks(vec![task]);
+    assert!(job.start().unwrap());
+    assert_eq!(counter.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn task_retry_exhausted() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let mut task = flaky_task("flaky", 5, counter.clone());
+    task.set_retry_policy(RetryPolicy::new(2));
+    let mut job = Dag::with_tasks(vec![task]);
+    assert!(!job.start().unwrap());
+    assert_eq!(counter.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn task_retry_only_on_matching_exit_code() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let mut task = flaky_task("flaky", 2, counter.clone());
+    task.set_retry_policy(RetryPolicy::new(3).retry_on_exit_codes([255]));
+    let mut job = Dag::with_tasks(vec![task]);
+    assert!(!job.start().unwrap());
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn task_retry_after_panic() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let attempts = counter.clone();
+    let mut task = DefaultTask::with_closure("panic", move |_, _| {
+        if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+            panic!("first attempt panics");
+        }
+        Output::empty()
+    });
+    task.set_retry_policy(RetryPolicy::new(2));
+    let mut job = Dag::with_tasks(vec![task]);
+    assert!(job.start().unwrap());
+    assert_eq!(counter.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn task_retry_predicate_skips_panic() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let attempts = counter.clone();
+    let mut task = DefaultTask::with_closure("panic", move |_, _| {
+        attempts.fetch_add(1, Ordering::SeqCst);
+        panic!("always panics");
+    });
+    task.set_retry_policy(RetryPolicy::new(3).retry_on_exit_codes([1]));
+    let mut job = Dag::with_tasks(vec![task]);
+    assert!(!job.start().unwrap());
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn yaml_task_retry() {
+    let res = Dag::with_yaml("tests/config/retry.yaml", HashMap::new())
+        .unwrap()
+        .start();
+    assert!(!res.unwrap());
+}
+
+#[test]
+fn task_timeout_kills_command() {
+    let mut task = DefaultTask::with_action("sleep", CommandAction::new("sleep 5"));
+    task.set_timeout(Duration::from_millis(200));
+    let start = Instant::now();
+    let mut job = Dag::with_tasks(vec![task]);
+    assert!(!job.start().unwrap());
+    assert!(start.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+fn task_timeout_skips_successors() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let mut a = DefaultTask::with_action("sleep", CommandAction::new("sleep 5"));
+    a.set_timeout(Duration::from_millis(200));
+    let mut b = flaky_task("after sleep", 0, counter.clone());
+    b.set_predecessors(&[&a]);
+    let c = flaky_task("independent", 0, counter.clone());
+    let mut job = Dag::with_tasks(vec![a, b, c]).keep_going();
+    assert!(!job.start().unwrap());
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn dag_timeout() {
+    let a = DefaultTask::with_action("sleep", CommandAction::new("sleep 5"));
+    let start = Instant::now();
+    let mut job = Dag::with_tasks(vec![a]);
+    job.set_timeout(Duration::from_millis(200));
+    assert!(!job.start().unwrap());
+    assert!(start.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+fn yaml_task_timeout() {
+    let start = Instant::now();
+    let res = Dag::with_yaml("tests/config/timeout.yaml", HashMap::new())
+        .unwrap()
+        .start();
+    assert!(!res.unwrap());
+    assert!(start.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+fn cancel_running_dag() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let a = DefaultTask::with_action("sleep", CommandAction::new("sleep 5"));
+    let mut b = flaky_task("after sleep", 0, counter.clone());
+    b.set_predecessors(&[&a]);
+    let mut job = Dag::with_tasks(vec![a, b]);
+    let handle = job.cancellation_handle();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(200));
+        handle.cancel();
+    });
+    let start = Instant::now();
+    assert!(!job.start().unwrap());
+    assert!(start.elapsed() < Duration::from_secs(3));
+    assert_eq!(counter.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn cancel_before_start() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let a = flaky_task("a", 0, counter.clone());
+    let mut b = flaky_task("b", 0, counter.clone());
+    b.set_predecessors(&[&a]);
+    let mut job = Dag::with_tasks(vec![a, b]);
+    job.set_env(EnvVar::new());
+    let handle = job.cancellation_handle();
+    handle.cancel();
+    assert!(!job.start().unwrap());
+    assert_eq!(counter.load(Ordering::SeqCst), 0);
+}
+
+/// An asynchronous action recording the peak number of concurrent executions.
+struct ConcurrencyProbe {
+    running: Arc<AtomicUsize>,
+    peak: Arc<AtomicUsize>,
+}
+
+#[async_trait::async_trait]
+impl Complex for ConcurrencyProbe {
+    fn run(&self, _input: Input, _env: Arc<EnvVar>) -> Output {
+        Output::empty()
+    }
+
+    async fn async_run(&self, _input: Input, _env: Arc<EnvVar>) -> Output {
+        let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(running, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        self.running.fetch_sub(1, Ordering::SeqCst);
+        Output::empty()
+    }
+
+    fn is_async(&self) -> bool {
+        true
+    }
+}
+
+fn probe_tasks(n: usize, peak: &Arc<AtomicUsize>) -> Vec<DefaultTask> {
+    let running = Arc::new(AtomicUsize::new(0));
+    (0..n)
+        .map(|i| {
+            let probe = ConcurrencyProbe {
+                running: running.clone(),
+                peak: peak.clone(),
+            };
+            DefaultTask::with_action(&format!("probe {}", i), probe)
+        })
+        .collect()
+}
+
+#[test]
+fn max_parallelism() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let mut job = Dag::with_tasks(probe_tasks(6, &peak));
+    job.set_max_parallelism(2);
+    assert!(job.start().unwrap());
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn resource_pool() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let mut tasks = probe_tasks(4, &peak);
+    tasks
+        .iter_mut()
+        .for_each(|task| task.add_resource("gpu-slot", 1));
+    let mut job = Dag::with_tasks(tasks);
+    job.add_resource_pool("gpu-slot", 1);
+    assert!(job.start().unwrap());
+    assert_eq!(peak.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn resource_pool_unavailable() {
+    let mut task = DefaultTask::with_closure("a", |_, _| Output::empty());
+    task.add_resource("db", 2);
+    let mut job = Dag::with_tasks(vec![task]);
+    job.add_resource_pool("db", 1);
+    assert!(matches!(
+        job.start(),
+        Err(DagError::ResourceUnavailable(_, _, 2))
+    ));
+}
+
+#[test]
+fn engine_max_parallelism() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let mut engine = Engine::default();
+    engine.set_max_parallelism(3);
+    engine
+        .append_dag("probe", Dag::with_tasks(probe_tasks(6, &peak)))
+        .unwrap();
+    assert!(engine.run_dag("probe").unwrap().is_success());
+    assert_eq!(peak.load(Ordering::SeqCst), 3);
+}
+
+/// An Engine of `n` Dags of one probe task each, sharing the probe counters.
+fn probe_engine(n: usize, peak: &Arc<AtomicUsize>) -> Engine {
+    let running = Arc::new(AtomicUsize::new(0));
+    let mut engine = Engine::default();
+    for i in 0..n {
+        let probe = ConcurrencyProbe {
+            running: running.clone(),
+            peak: peak.clone(),
+        };
+        let task = DefaultTask::with_action("probe", probe);
+        engine
+            .append_dag(&format!("dag {}", i), Dag::with_tasks(vec![task]))
+            .unwrap();
+    }
+    engine
+}
+
+#[test]
+fn engine_run_parallel() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let mut engine = probe_engine(4, &peak);
+    let reports = engine.run_parallel(Some(2));
+    assert_eq!(reports.len(), 4);
+    assert!(reports.values().all(|report| report.is_success()));
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+
+    peak.store(0, Ordering::SeqCst);
+    let reports = engine.run_all();
+    assert!(reports["dag 3"].is_success());
+    assert_eq!(peak.load(Ordering::SeqCst), 4);
+    // The Dags are still in the Engine.
+    assert!(engine.run_dag("dag 0").unwrap().is_success());
+}
+
+/// Delays the start of a Dag.
+struct SlowStart;
+
+impl DagObserver for SlowStart {
+    fn on_dag_start(&self, _tasks: &[(usize, &str)]) {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn engine_resource_pool_shared_by_concurrent_dags() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let running = Arc::new(AtomicUsize::new(0));
+    let mut engine = Engine::default();
+    engine.add_resource_pool("gpu", 1);
+    for name in ["a", "b"] {
+        let probe = ConcurrencyProbe {
+            running: running.clone(),
+            peak: peak.clone(),
+        };
+        let mut task = DefaultTask::with_action(name, probe);
+        task.add_resource("gpu", 1);
+        let mut dag = Dag::with_tasks(vec![task]);
+        if name == "b" {
+            dag.add_observer(Arc::new(SlowStart));
+        }
+        engine.append_dag(name, dag).unwrap();
+    }
+    // The Dag `b` starts while the task of `a` holds the only permit of the pool.
+    for _ in 0..10 {
+        assert!(engine.run_all().values().all(|report| report.is_success()));
+    }
+    assert_eq!(peak.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn engine_run_all_reports_each_dag() {
+    let mut engine = Engine::default();
+    let ok = DefaultTask::with_closure("ok", |_, _| Output::new(1usize));
+    engine.append_dag("ok", Dag::with_tasks(vec![ok])).unwrap();
+    let failed = DefaultTask::with_closure("failed", |_, _| Output::error("boom".to_string()));
+    engine
+        .append_dag("failed", Dag::with_tasks(vec![failed]))
+        .unwrap();
+    let reports = engine.run_all();
+    assert!(reports["ok"].is_success());
+    assert_eq!(reports["failed"].outcome, DagOutcome::Failed);
+    assert_eq!(*engine.get_dag_result::<usize>("ok").unwrap(), 1);
+}
+
+/// An Engine computing `load` from `extract` through two Dags in between.
+fn dependent_engine() -> Engine {
+    let mut engine = Engine::default();
+    let load = DefaultTask::with_closure("load", |_, env| {
+        Output::new(env.get::<usize>("double").unwrap() + env.get::<usize>("square").unwrap())
+    });
+    engine
+        .append_dag("load", Dag::with_tasks(vec![load]))
+        .unwrap();
+    let double = DefaultTask::with_closure("double", |_, env| {
+        Output::new(env.get::<usize>("extract").unwrap() * 2)
+    });
+    engine
+        .append_dag("double", Dag::with_tasks(vec![double]))
+        .unwrap();
+    let square = DefaultTask::with_closure("square", |_, env| {
+        std::thread::sleep(Duration::from_millis(20));
+        Output::new(env.get::<usize>("extract").unwrap().pow(2))
+    });
+    engine
+        .append_dag("square", Dag::with_tasks(vec![square]))
+        .unwrap();
+    let extract = DefaultTask::with_closure("extract", |_, _| Output::new(3usize));
+    engine
+        .append_dag("extract", Dag::with_tasks(vec![extract]))
+        .unwrap();
+    engine.add_dependency("double", &["extract"]).unwrap();
+    engine.add_dependency("square", &["extract"]).unwrap();
+    engine
+        .add_dependency("load", &["double", "square"])
+        .unwrap();
+    engine
+}
+
+#[test]
+fn engine_dependencies_pass_results() {
+    let mut engine = dependent_engine();
+    assert_eq!(engine.run_sequential(), vec![true; 4]);
+    assert_eq!(*engine.get_dag_result::<usize>("load").unwrap(), 15);
+
+    let mut engine = dependent_engine();
+    let reports = engine.run_parallel(Some(1));
+    assert!(reports.values().all(|report| report.is_success()));
+    assert_eq!(*engine.get_dag_result::<usize>("load").unwrap(), 15);
+}
+
+#[test]
+fn engine_dependency_results_do_not_leak() {
+    let mut engine = Engine::default();
+    let extract = DefaultTask::with_closure("extract", |_, _| Output::new(3usize));
+    engine
+        .append_dag("extract", Dag::with_tasks(vec![extract]))
+        .unwrap();
+    let load = DefaultTask::with_closure("load", |_, env| {
+        Output::new(env.get::<usize>("extract").is_some())
+    });
+    engine
+        .append_dag("load", Dag::with_tasks(vec![load]))
+        .unwrap();
+    engine.add_dependency("load", &["extract"]).unwrap();
+    assert_eq!(engine.run_sequential(), vec![true; 2]);
+    assert!(*engine.get_dag_result::<bool>("load").unwrap());
+
+    // Executed alone, the Dag does not see the result of the previous execution of its dependency.
+    assert!(engine.run_dag("load").unwrap().is_success());
+    assert!(!*engine.get_dag_result::<bool>("load").unwrap());
+}
+
+#[test]
+fn engine_dependency_failure_skips_dependents() {
+    let mut engine = Engine::default();
+    let failed = DefaultTask::with_closure("failed", |_, _| Output::error("boom".to_string()));
+    engine
+        .append_dag("failed", Dag::with_tasks(vec![failed]))
+        .unwrap();
+    for name in ["after", "after after", "independent"] {
+        let task = DefaultTask::with_closure(name, |_, _| Output::empty());
+        engine
+            .append_dag(name, Dag::with_tasks(vec![task]))
+            .unwrap();
+    }
+    engine.add_dependency("after", &["failed"]).unwrap();
+    engine.add_dependency("after after", &["after"]).unwrap();
+    let reports = engine.run_all();
+    assert_eq!(reports["failed"].outcome, DagOutcome::Failed);
+    assert_eq!(reports["after"].outcome, DagOutcome::Skipped);
+    assert_eq!(reports["after after"].outcome, DagOutcome::Skipped);
+    assert_eq!(reports["after"].tasks[0].status, TaskStatus::Pending);
+    assert!(reports["independent"].is_success());
+    assert_eq!(engine.run_sequential(), vec![false, false, false, true]);
+}
+
+#[test]
+fn engine_dependency_errors() {
+    let mut engine = dependent_engine();
+    assert!(matches!(
+        engine.add_dependency("extract", &["load"]),
+        Err(DagError::LoopGraph)
+    ));
+    assert!(matches!(
+        engine.add_dependency("load", &["missing"]),
+        Err(DagError::DagNotFound(name)) if name == "missing"
+    ));
+    // The rejected dependency is not kept.
+    assert_eq!(engine.run_sequential(), vec![true; 4]);
+}
+
+#[test]
+fn engine_registry() {
+    let mut engine = dependent_engine();
+    let task = DefaultTask::with_closure("extract", |_, _| Output::new(3usize));
+    assert!(matches!(
+        engine.append_dag("extract", Dag::with_tasks(vec![task])),
+        Err(DagError::DuplicateDag(name)) if name == "extract"
+    ));
+    let mut a = DefaultTask::new("a");
+    let mut b = DefaultTask::new("b");
+    a.set_predecessors(&[&b]);
+    b.set_predecessors(&[&a]);
+    assert!(matches!(
+        engine.append_dag("loop", Dag::with_tasks(vec![a, b])),
+        Err(DagError::LoopGraph)
+    ));
+    assert_eq!(engine.list_dags(), ["load", "double", "square", "extract"]);
+
+    // The replacement keeps the dependencies of the Dag.
+    let triple = DefaultTask::with_closure("triple", |_, env| {
+        Output::new(env.get::<usize>("extract").unwrap() * 3)
+    });
+    let previous = engine
+        .replace_dag("double", Dag::with_tasks(vec![triple]))
+        .unwrap();
+    assert_eq!(previous.get_result::<usize>(), None);
+    assert_eq!(engine.run_sequential(), vec![true; 4]);
+    assert_eq!(*engine.get_dag_result::<usize>("load").unwrap(), 18);
+    assert!(matches!(
+        engine.replace_dag("missing", Dag::with_tasks(vec![DefaultTask::new("x")])),
+        Err(DagError::DagNotFound(_))
+    ));
+
+    engine.remove_dag("square").unwrap();
+    assert!(engine.remove_dag("square").is_err());
+    assert_eq!(engine.list_dags(), ["load", "double", "extract"]);
+
+    // The Dags depending on a removed Dag no longer wait for it.
+    let failed = DefaultTask::with_closure("failed", |_, _| Output::error("boom".to_string()));
+    engine
+        .append_dag("failed", Dag::with_tasks(vec![failed]))
+        .unwrap();
+    let after = DefaultTask::with_closure("after", |_, _| Output::empty());
+    engine
+        .append_dag("after", Dag::with_tasks(vec![after]))
+        .unwrap();
+    engine.add_dependency("after", &["failed"]).unwrap();
+    assert_eq!(engine.run_all()["after"].outcome, DagOutcome::Skipped);
+    engine.remove_dag("failed").unwrap();
+    assert!(engine.run_all()["after"].is_success());
+
+    let metadata = DagMetadata {
+        description: Some("Loads the results".to_string()),
+        tags: vec!["nightly".to_string()],
+        owner: Some("data".to_string()),
+    };
+    engine.set_dag_metadata("load", metadata.clone()).unwrap();
+    assert_eq!(engine.get_dag_metadata("load"), Some(&metadata));
+    assert_eq!(
+        engine.get_dag_metadata("double"),
+        Some(&DagMetadata::default())
+    );
+    assert!(engine.get_dag_metadata("square").is_none());
+    let mut dag = Dag::with_tasks(vec![DefaultTask::new("x")]);
+    dag.set_metadata(metadata.clone());
+    engine.append_dag("x", dag).unwrap();
+    assert_eq!(engine.get_dag_metadata("x"), Some(&metadata));
+}
+
+fn at(seconds: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+#[test]
+fn cron_schedule_next_runs() {
+    // 2024-02-28 03:00 UTC.
+    let now = at(1709089200);
+    let daily = Schedule::cron("0 2 * * *").unwrap();
+    assert_eq!(daily.next_after(now), Some(at(1709172000)));
+    let leap_day = Schedule::cron("0 0 29 feb *").unwrap();
+    assert_eq!(leap_day.next_after(at(1709172000)), Some(at(1835395200)));
+    // Either the 1st of the month, a Friday, or a Sunday.
+    let either = Schedule::cron("0 0 1 * sun").unwrap();
+    assert_eq!(either.next_after(now), Some(at(1709251200)));
+    assert_eq!(either.next_after(at(1709251200)), Some(at(1709424000)));
+    let quarters = Schedule::cron("*/15 9-17 * * 1-5").unwrap();
+    assert_eq!(quarters.next_after(now), Some(at(1709110800)));
+    assert_eq!(quarters.next_after(at(1709110800)), Some(at(1709111700)));
+    assert_eq!(
+        Schedule::cron("@hourly").unwrap().next_after(now),
+        Some(at(1709092800))
+    );
+    for invalid in [
+        "* * * *",
+        "0 24 * * *",
+        "0 0 0 * *",
+        "5/2 * * * *",
+        "0 0 * * mon-sun",
+    ] {
+        assert!(matches!(
+            Schedule::cron(invalid),
+            Err(DagError::InvalidSchedule(expression, _)) if expression == invalid
+        ));
+    }
+    let every = Schedule::every(Duration::from_secs(60));
+    assert_eq!(every.next_after(now), Some(now + Duration::from_secs(60)));
+}
+
+/// An Engine with a Dag `job` scheduled every minute from time 0, with a clock at `now`.
+fn scheduled_engine(schedule: impl FnOnce(&mut Schedule), now: u64) -> Engine {
+    let mut engine = Engine::default();
+    let task = DefaultTask::with_closure("job", |_, _| Output::empty());
+    engine
+        .append_dag("job", Dag::with_tasks(vec![task]))
+        .unwrap();
+    let mut every_minute = Schedule::every(Duration::from_secs(60));
+    every_minute.set_start(at(0));
+    schedule(&mut every_minute);
+    engine.schedule("job", every_minute).unwrap();
+    engine.set_clock(Arc::new(ManualClock::new(at(now))));
+    engine
+}
+
+#[test]
+fn scheduler_missed_runs() {
+    let mut engine = scheduled_engine(
+        |schedule| {
+            schedule.set_missed_runs(MissedRuns::CatchUp);
+            schedule.set_max_queued_runs(3);
+        },
+        210,
+    );
+    let runs = engine.run_scheduler(async {});
+    let due: Vec<_> = runs.iter().map(|run| run.scheduled_at).collect();
+    assert_eq!(due, vec![at(60), at(120), at(180)]);
+    assert!(runs
+        .iter()
+        .all(|run| run.dag == "job" && run.report.as_ref().unwrap().is_success()));
+
+    let mut engine = scheduled_engine(|schedule| schedule.set_max_queued_runs(3), 210);
+    let runs = engine.run_scheduler(async {});
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0].scheduled_at, at(180));
+    // The Dag is back in the Engine.
+    assert!(engine.run_dag("job").unwrap().is_success());
+    assert!(matches!(
+        engine.schedule("missing", Schedule::every(Duration::from_secs(1))),
+        Err(DagError::DagNotFound(_))
+    ));
+}
+
+#[test]
+fn scheduler_long_missed_gap() {
+    // A century of runs every minute was missed.
+    let now = 100 * 365 * 86400 + 30;
+    let mut engine = scheduled_engine(|_| {}, now);
+    let runs = engine.run_scheduler(async {});
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0].scheduled_at, at(now - 30));
+
+    let mut engine = scheduled_engine(
+        |schedule| schedule.set_missed_runs(MissedRuns::CatchUp),
+        now,
+    );
+    let runs = engine.run_scheduler(async {});
+    // Only the latest runs are caught up, and only the first one fits in the queue.
+    assert_eq!(runs.len(), 1000);
+    assert_eq!(runs[0].scheduled_at, at(now - 30 - 999 * 60));
+    assert_eq!(runs[999].scheduled_at, at(now - 30));
+    assert_eq!(runs.iter().filter(|run| run.report.is_some()).count(), 1);
+}
+
+#[test]
+fn scheduler_max_queued_runs() {
+    for (max, executed) in [(1, 1), (2, 2)] {
+        let mut engine = scheduled_engine(
+            |schedule| {
+                schedule.set_missed_runs(MissedRuns::CatchUp);
+                schedule.set_max_queued_runs(max);
+            },
+            210,
+        );
+        let runs = engine.run_scheduler(async {});
+        assert_eq!(runs.len(), 3);
+        let dropped: Vec<_> = runs
+            .iter()
+            .filter(|run| run.report.is_none())
+            .map(|run| run.scheduled_at)
+            .collect();
+        assert_eq!(runs.len() - dropped.len(), executed);
+        assert_eq!(dropped.last(), Some(&at(180)));
+    }
+}
+
+#[test]
+fn scheduler_drops_the_runs_of_a_cancelled_dag() {
+    let mut engine = Engine::default();
+    let task = DefaultTask::with_closure("job", |_, env| {
+        env.cancellation().cancel();
+        Output::empty()
+    });
+    engine
+        .append_dag("job", Dag::with_tasks(vec![task]))
+        .unwrap();
+    let mut schedule = Schedule::every(Duration::from_secs(60));
+    schedule.set_start(at(0));
+    schedule.set_missed_runs(MissedRuns::CatchUp);
+    schedule.set_max_queued_runs(3);
+    engine.schedule("job", schedule).unwrap();
+    engine.set_clock(Arc::new(ManualClock::new(at(210))));
+    let runs = engine.run_scheduler(async {});
+    assert_eq!(runs.len(), 3);
+    assert_eq!(
+        runs[0].report.as_ref().unwrap().outcome,
+        DagOutcome::Cancelled
+    );
+    assert!(runs[1..].iter().all(|run| run.report.is_none()));
+}
+
+#[test]
+fn scheduler_follows_the_clock() {
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let mut engine = Engine::default();
+    let task = DefaultTask::with_closure("tick", move |_, _| {
+        sender.send(()).unwrap();
+        Output::empty()
+    });
+    engine
+        .append_dag("tick", Dag::with_tasks(vec![task]))
+        .unwrap();
+    let mut schedule = Schedule::cron("*/10 * * * *").unwrap();
+    // A run is reported before the previous one finished.
+    schedule.set_max_queued_runs(2);
+    engine.schedule("tick", schedule).unwrap();
+    let clock = Arc::new(ManualClock::new(at(0)));
+    engine.set_clock(clock.clone());
+    let runs = engine.run_scheduler(async move {
+        for _ in 0..3 {
+            clock.advance(Duration::from_secs(600));
+            receiver.recv().await.unwrap();
+        }
+        // Not due yet.
+        clock.advance(Duration::from_secs(599));
+        tokio::task::yield_now().await;
+    });
+    let due: Vec<_> = runs.iter().map(|run| run.scheduled_at).collect();
+    assert_eq!(due, vec![at(600), at(1200), at(1800)]);
+}
+
+#[test]
+fn engine_resource_pool_replaced() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let gpu_tasks = || {
+        let mut tasks = probe_tasks(2, &peak);
+        tasks
+            .iter_mut()
+            .for_each(|task| task.add_resource("gpu", 1));
+        Dag::with_tasks(tasks)
+    };
+    let mut engine = Engine::default();
+    engine.add_resource_pool("gpu", 1);
+    engine.append_dag("before", gpu_tasks()).unwrap();
+    // The new capacity applies to the Dags appended before and after.
+    engine.add_resource_pool("gpu", 2);
+    engine.append_dag("after", gpu_tasks()).unwrap();
+    assert!(engine.run_dag("before").unwrap().is_success());
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+    peak.store(0, Ordering::SeqCst);
+    assert!(engine.run_dag("after").unwrap().is_success());
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn engine_runs_within_a_runtime() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        // Created, executed and dropped within the runtime.
+        let mut engine = Engine::with_handle(tokio::runtime::Handle::current());
+        let task = DefaultTask::with_closure("task", |_, _| Output::new(1usize));
+        engine
+            .append_dag("job", Dag::with_tasks(vec![task]))
+            .unwrap();
+        let mut engine = tokio::spawn(async move {
+            assert!(engine.async_run_dag("job").await.unwrap().is_success());
+            assert_eq!(engine.async_run_sequential().await, vec![true]);
+            assert!(engine.async_run_all().await["job"].is_success());
+            engine
+        })
+        .await
+        .unwrap();
+        assert!(matches!(
+            engine.async_run_dag("missing").await,
+            Err(DagError::DagNotFound(_))
+        ));
+        // The synchronous methods block on the runtime from outside of it.
+        let report = tokio::task::spawn_blocking(move || engine.run_dag("job"))
+            .await
+            .unwrap();
+        assert!(report.unwrap().is_success());
+
+        let mut engine = dependent_engine();
+        assert!(engine.async_run_parallel(Some(2)).await["load"].is_success());
+        assert_eq!(*engine.get_dag_result::<usize>("load").unwrap(), 15);
+    });
+}
+
+#[test]
+fn engine_keeps_dags_of_dropped_run() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let mut engine = Engine::with_handle(tokio::runtime::Handle::current());
+        let task = DefaultTask::with_closure("slow", |_, _| {
+            std::thread::sleep(Duration::from_millis(200));
+            Output::new(1usize)
+        });
+        engine
+            .append_dag("slow", Dag::with_tasks(vec![task]))
+            .unwrap();
+        let run = tokio::time::timeout(Duration::from_millis(20), engine.async_run_all());
+        assert!(run.await.is_err());
+        assert_eq!(engine.list_dags(), ["slow"]);
+        assert!(engine.async_run_all().await["slow"].is_success());
+    });
+}
+
+#[test]
+fn dag_runs_on_a_spawned_task() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let a = DefaultTask::with_closure("a", |_, _| Output::new(1usize));
+    let mut b = DefaultTask::with_closure("b", |input, _| {
+        Output::new(input.get_from::<usize>("a").unwrap() + 1)
+    });
+    b.set_predecessors(&[&a]);
+    let mut dag = Dag::with_tasks(vec![a, b]);
+    let dag = runtime
+        .block_on(runtime.spawn(async move {
+            assert!(dag.async_start().await.unwrap());
+            dag
+        }))
+        .unwrap();
+    assert_eq!(*dag.get_result::<usize>().unwrap(), 2);
+}
+
+#[test]
+fn blocking_tasks_run_concurrently() {
+    let tasks = (0..8)
+        .map(|i| {
+            DefaultTask::with_closure(&format!("blocking {}", i), |_, _| {
+                std::thread::sleep(Duration::from_millis(100));
+                Output::empty()
+            })
+        })
+        .collect();
+    let start = Instant::now();
+    let mut job = Dag::with_tasks(tasks);
+    assert!(job.start().unwrap());
+    assert!(start.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn report_task_statuses() {
+    let ok = DefaultTask::with_closure("ok", |_, _| Output::empty());
+    let failed = DefaultTask::with_closure("failed", |_, _| Output::error("boom".to_string()));
+    let panicked = DefaultTask::with_closure("panicked", |_, _| panic!("oops"));
+    let mut skipped = DefaultTask::with_closure("skipped", |_, _| Output::empty());
+    skipped.set_predecessors(&[&failed]);
+    let mut job = Dag::with_tasks(vec![ok, failed, panicked, skipped]).keep_going();
+    let report = job.start_with_report().unwrap();
+    assert_eq!(report.outcome, DagOutcome::Failed);
+    assert_eq!(report.tasks.len(), 4);
+
+    let ok = report.task_by_name("ok").unwrap();
+    assert_eq!(ok.status, TaskStatus::Succeeded);
+    assert_eq!(ok.attempts, 1);
+    assert!(ok.started_at.is_some() && ok.finished_at.is_some() && ok.duration.is_some());
+    let failed = report.task_by_name("failed").unwrap();
+    assert_eq!(failed.status, TaskStatus::Failed);
+    assert_eq!(failed.error.as_deref(), Some("boom"));
+    let panicked = report.task_by_name("panicked").unwrap();
+    assert_eq!(panicked.status, TaskStatus::Panicked);
+    assert_eq!(panicked.error.as_deref(), Some("oops"));
+    let skipped = report.task_by_name("skipped").unwrap();
+    assert_eq!(skipped.status, TaskStatus::Skipped);
+    assert_eq!(skipped.attempts, 0);
+    assert!(skipped.started_at.is_none());
+    assert_eq!(report.failed_tasks().count(), 2);
+}
+
+#[test]
+fn report_terminated_after_error() {
+    let a = DefaultTask::with_closure("a", |_, _| Output::error("boom".to_string()));
+    let mut b = DefaultTask::with_closure("b", |_, _| Output::empty());
+    b.set_predecessors(&[&a]);
+    let mut job = Dag::with_tasks(vec![a, b]);
+    let report = job.start_with_report().unwrap();
+    assert!(!report.is_success());
+    assert_eq!(
+        report.task_by_name("b").unwrap().status,
+        TaskStatus::Terminated
+    );
+}
+
+#[test]
+fn report_command_exit_code() {
+    let task = DefaultTask::with_action("cmd", CommandAction::new("echo oops >&2; exit 3"));
+    let id = task.id();
+    let mut job = Dag::with_tasks(vec![task]);
+    let report = job.start_with_report().unwrap();
+    let task = report.task(id).unwrap();
+    assert_eq!(task.status, TaskStatus::Failed);
+    assert_eq!(task.exit_code, Some(3));
+    assert_eq!(task.error.as_deref(), Some("oops"));
+}
+
+#[test]
+fn report_retry_attempts() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let mut task = flaky_task("flaky", 2, counter);
+    task.set_retry_policy(RetryPolicy::new(3));
+    let mut job = Dag::with_tasks(vec![task]);
+    let report = job.start_with_report().unwrap();
+    assert!(report.is_success());
+    assert_eq!(report.task_by_name("flaky").unwrap().attempts, 3);
+}
+
+#[test]
+fn report_dag_timeout() {
+    let task = DefaultTask::with_action("sleep", CommandAction::new("sleep 5"));
+    let mut job = Dag::with_tasks(vec![task]);
+    job.set_timeout(Duration::from_millis(200));
+    let report = job.start_with_report().unwrap();
+    assert_eq!(report.outcome, DagOutcome::TimedOut);
+    assert_eq!(
+        report.task_by_name("sleep").unwrap().status,
+        TaskStatus::TimedOut
+    );
+}
+
+#[test]
+fn report_cancelled() {
+    let task = DefaultTask::with_closure("a", |_, _| Output::empty());
+    let mut job = Dag::with_tasks(vec![task]);
+    job.cancellation_handle().cancel();
+    let report = job.start_with_report().unwrap();
+    assert_eq!(report.outcome, DagOutcome::Cancelled);
+    assert_eq!(
+        report.task_by_name("a").unwrap().status,
+        TaskStatus::Terminated
+    );
+}
+
+#[test]
+fn engine_run_missing_dag() {
+    let mut engine = Engine::default();
+    assert!(matches!(
+        engine.run_dag("missing"),
+        Err(DagError::DagNotFound(_))
+    ));
+}
+
+/// Records the lifecycle events it receives.
+#[derive(Default)]
+struct EventRecorder(Mutex<Vec<String>>);
+
+impl EventRecorder {
+    fn push(&self, event: String) {
+        self.0.lock().unwrap().push(event);
+    }
+
+    fn events(&self) -> Vec<String> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl DagObserver for EventRecorder {
+    fn on_dag_start(&self, tasks: &[(usize, &str)]) {
+        self.push(format!("dag start {}", tasks.len()));
+    }
+
+    fn on_task_ready(&self, _id: usize, name: &str) {
+        self.push(format!("ready {}", name));
+    }
+
+    fn on_task_start(&self, _id: usize, name: &str, attempt: u32) {
+        self.push(format!("start {} {}", name, attempt));
+    }
+
+    fn on_task_success(&self, report: &TaskReport, _output: &Output) {
+        self.push(format!("success {}", report.name));
+    }
+
+    fn on_task_failure(&self, report: &TaskReport) {
+        self.push(format!("failure {}", report.name));
+    }
+
+    fn on_task_skipped(&self, report: &TaskReport) {
+        self.push(format!("skipped {}", report.name));
+    }
+
+    fn on_dag_finish(&self, report: &DagReport) {
+        self.push(format!("dag finish {:?}", report.outcome));
+    }
+}
+
+#[test]
+fn observer_events() {
+    let a = DefaultTask::with_closure("a", |_, _| Output::empty());
+    let mut b = DefaultTask::with_closure("b", |_, _| Output::error("boom".to_string()));
+    b.set_predecessors(&[&a]);
+    let mut c = DefaultTask::with_closure("c", |_, _| Output::empty());
+    c.set_predecessors(&[&b]);
+    let recorder = Arc::new(EventRecorder::default());
+    let mut job = Dag::with_tasks(vec![a, b, c]).keep_going();
+    job.add_observer(recorder.clone());
+    assert!(!job.start().unwrap());
+    assert_eq!(
+        recorder.events(),
+        [
+            "dag start 3",
+            "ready a",
+            "start a 1",
+            "success a",
+            "ready b",
+            "start b 1",
+            "failure b",
+            "skipped c",
+            "dag finish Failed",
+        ]
+    );
+}
+
+#[test]
+fn engine_observer() {
+    let recorder = Arc::new(EventRecorder::default());
+    let mut engine = Engine::default();
+    engine
+        .append_dag(
+            "first",
+            Dag::with_tasks(vec![DefaultTask::with_closure("a", |_, _| Output::empty())]),
+        )
+        .unwrap();
+    engine.add_observer(recorder.clone());
+    engine
+        .append_dag(
+            "second",
+            Dag::with_tasks(vec![DefaultTask::with_closure("b", |_, _| Output::empty())]),
+        )
+        .unwrap();
+    assert_eq!(engine.run_sequential(), [true, true]);
+    let events = recorder.events();
+    assert!(events.contains(&"success a".to_string()));
+    assert!(events.contains(&"success b".to_string()));
+}
+
+#[test]
+fn sender_receives_outputs() {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let task = DefaultTask::with_closure("a", |_, _| Output::new(1usize));
+    let mut job = Dag::with_tasks_and_sender(vec![task], tx);
+    assert!(job.start().unwrap());
+    match rx.try_recv().unwrap() {
+        OutputMessage::Message(content) => assert_eq!(content.task_name, "a"),
+        OutputMessage::Finish => panic!("the output of the task is expected first"),
+    }
+    assert!(matches!(rx.try_recv().unwrap(), OutputMessage::Finish));
+}
+
+/// A chain of tasks adding their inputs to the "base" environment variable.
+fn sum_chain(n: usize) -> Vec<DefaultTask> {
+    let mut tasks: Vec<DefaultTask> = (0..n)
+        .map(|i| {
+            DefaultTask::with_closure(&format!("sum {}", i), |input, env| {
+                let sum: usize = input.get_iter().filter_map(|c| c.get::<usize>()).sum();
+                Output::new(sum + env.get::<usize>("base").unwrap())
+            })
+        })
+        .collect();
+    for i in 1..n {
+        let id = tasks[i - 1].id();
+        tasks[i].set_predecessors_by_id([id]);
+    }
+    tasks
+}
+
+fn env_with_base(base: usize) -> EnvVar {
+    let mut env = EnvVar::new();
+    env.set("base", base);
+    env
+}
+
+#[test]
+fn single_shot_dag() {
+    let mut job = Dag::with_tasks(sum_chain(3));
+    job.set_env(env_with_base(1));
+    assert!(job.start().unwrap());
+    assert!(!job.start().unwrap());
+}
+
+#[test]
+fn reusable_dag() {
+    let mut job = Dag::with_tasks(sum_chain(3)).reusable();
+    for base in 1..5 {
+        job.set_env(env_with_base(base));
+        let report = job.start_with_report().unwrap();
+        assert!(report.is_success());
+        assert!(report.tasks.iter().all(|task| task.attempts == 1));
+        assert_eq!(*job.get_result::<usize>().unwrap(), base * 3);
+    }
+}
+
+#[test]
+fn reset_after_failure() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let mut job = Dag::with_tasks(vec![flaky_task("flaky", 1, counter)]);
+    assert!(!job.start().unwrap());
+    job.reset();
+    assert!(job.start().unwrap());
+}
+
+#[test]
+fn reset_after_cancel() {
+    let mut job = Dag::with_tasks(sum_chain(2));
+    job.set_env(env_with_base(1));
+    let handle = job.cancellation_handle();
+    handle.cancel();
+    assert!(!job.start().unwrap());
+    job.reset();
+    assert!(!job.cancellation_handle().is_cancelled());
+    assert!(job.start().unwrap());
+    assert_eq!(*job.get_result::<usize>().unwrap(), 2);
+}
+
+#[test]
+fn engine_rerun_dag() {
+    let mut engine = Engine::default();
+    let mut job = Dag::with_tasks(sum_chain(3));
+    job.set_env(env_with_base(2));
+    engine.append_dag("sum", job).unwrap();
+    assert!(engine.run_dag("sum").unwrap().is_success());
+    assert!(engine.run_dag("sum").unwrap().is_success());
+    assert_eq!(*engine.get_dag_result::<usize>("sum").unwrap(), 6);
+}
+
+fn checkpoint_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("dagrs_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// a -> b -> c, `a` counts its executions and `b` fails `b_failures` times.
+fn checkpoint_tasks(a_runs: &Arc<AtomicUsize>, b_failures: usize) -> Vec<DefaultTask> {
+    let a_runs = a_runs.clone();
+    let a = DefaultTask::with_closure("a", move |_, _| {
+        a_runs.fetch_add(1, Ordering::SeqCst);
+        Output::new("from a".to_string())
+    });
+    let mut b = DefaultTask::with_closure("b", move |input, _| {
+        if b_failures > 0 {
+            return Output::error("boom".to_string());
+        }
+        let a = input.get_iter().next().unwrap().get::<String>().unwrap();
+        Output::new(format!("{} and b", a))
+    });
+    b.set_predecessors(&[&a]);
+    let mut c = DefaultTask::with_closure("c", |input, _| {
+        let b = input.get_iter().next().unwrap().get::<String>().unwrap();
+        Output::new(format!("{} and c", b))
+    });
+    c.set_predecessors(&[&b]);
+    vec![a, b, c]
+}
+
+#[test]
+fn checkpoint_resume() {
+    let dir = checkpoint_dir("resume");
+    let store = Arc::new(FileCheckpointStore::new(&dir));
+    let a_runs = Arc::new(AtomicUsize::new(0));
+
+    let mut job = Dag::with_tasks(checkpoint_tasks(&a_runs, 1));
+    job.set_checkpoint_store(store.clone());
+    assert!(!job.start().unwrap());
+    assert_eq!(store.load("b").unwrap().unwrap().status, TaskStatus::Failed);
+
+    let mut job = Dag::with_tasks(checkpoint_tasks(&a_runs, 0));
+    job.resume_from(store.clone());
+    let report = job.start_with_report().unwrap();
+    assert!(report.is_success());
+    assert_eq!(a_runs.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        report.task_by_name("a").unwrap().status,
+        TaskStatus::Restored
+    );
+    assert_eq!(
+        report.task_by_name("b").unwrap().status,
+        TaskStatus::Succeeded
+    );
+    assert_eq!(
+        *job.get_result::<String>().unwrap(),
+        "from a and b and c".to_string()
+    );
+
+    // Starting without resuming clears the checkpoints.
+    let mut job = Dag::with_tasks(checkpoint_tasks(&a_runs, 0));
+    job.set_checkpoint_store(store);
+    assert!(job.start().unwrap());
+    assert_eq!(a_runs.load(Ordering::SeqCst), 2);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[derive(Debug, PartialEq)]
+struct Point(i32, i32);
+
+impl Checkpointable for Point {
+    const TYPE_TAG: &'static str = "Point";
+
+    fn to_bytes(&self) -> Vec<u8> {
+        format!("{},{}", self.0, self.1).into_bytes()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (x, y) = std::str::from_utf8(bytes).ok()?.split_once(',')?;
+        Some(Point(x.parse().ok()?, y.parse().ok()?))
+    }
+}
+
+#[test]
+fn checkpoint_custom_type() {
+    let dir = checkpoint_dir("custom");
+    let store = Arc::new(FileCheckpointStore::new(&dir));
+    let runs = Arc::new(AtomicUsize::new(0));
+    let point_task = |runs: &Arc<AtomicUsize>| {
+        let runs = runs.clone();
+        DefaultTask::with_closure("point", move |_, _| {
+            runs.fetch_add(1, Ordering::SeqCst);
+            Output::new(Point(1, -2))
+        })
+    };
+
+    // Not registered, the output cannot be stored so the task is executed again.
+    let mut job = Dag::with_tasks(vec![point_task(&runs)]);
+    job.set_checkpoint_store(store.clone());
+    assert!(job.start().unwrap());
+    let mut job = Dag::with_tasks(vec![point_task(&runs)]);
+    job.resume_from(store.clone());
+    assert!(job.start().unwrap());
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+    let mut job = Dag::with_tasks(vec![point_task(&runs)]);
+    job.register_checkpoint_type::<Point>();
+    job.resume_from(store.clone());
+    assert!(job.start().unwrap());
+    let mut job = Dag::with_tasks(vec![point_task(&runs)]);
+    job.register_checkpoint_type::<Point>();
+    job.resume_from(store);
+    assert!(job.start().unwrap());
+    assert_eq!(runs.load(Ordering::SeqCst), 3);
+    assert_eq!(*job.get_result::<Point>().unwrap(), Point(1, -2));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn file_checkpoint_store() {
+    let dir = checkpoint_dir("store");
+    let store = FileCheckpointStore::new(&dir);
+    assert!(store.load("a/b c").unwrap().is_none());
+    let checkpoint = TaskCheckpoint {
+        status: TaskStatus::Succeeded,
+        output: Some(StoredOutput::Value {
+            type_tag: "String".to_string(),
+            data: b"two\nlines".to_vec(),
+        }),
+    };
+    store.save("a/b c", &checkpoint).unwrap();
+    store
+        .save(
+            "a_b c",
+            &TaskCheckpoint {
+                status: TaskStatus::Failed,
+                output: None,
+            },
+        )
+        .unwrap();
+    assert_eq!(store.load("a/b c").unwrap(), Some(checkpoint));
+    assert_eq!(store.load("a_b c").unwrap().unwrap().output, None);
+    store.clear().unwrap();
+    assert!(store.load("a/b c").unwrap().is_none());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn checkpoint_command_output() {
+    let dir = checkpoint_dir("command");
+    let store = Arc::new(FileCheckpointStore::new(&dir));
+    let mut job = Dag::with_tasks(vec![DefaultTask::with_action(
+        "cmd",
+        CommandAction::new("echo 'a:b'; echo; echo err >&2"),
+    )]);
+    job.set_checkpoint_store(store.clone());
+    assert!(job.start().unwrap());
+    let lines = |job: &Dag| {
+        let content = job.get_result::<Content>().unwrap();
+        content.get::<(Vec<String>, Vec<String>)>().unwrap().clone()
+    };
+    let expected = lines(&job);
+    assert_eq!(expected.0, ["a:b", ""]);
+
+    let mut job = Dag::with_tasks(vec![DefaultTask::with_action(
+        "cmd",
+        CommandAction::new("exit 1"),
+    )]);
+    job.resume_from(store);
+    assert!(job.start().unwrap());
+    assert_eq!(lines(&job), expected);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn branch_selects_successors() {
+    let check = DefaultTask::with_closure("check", |_input, _env| {
+        Output::branch_with(5usize, ["double"])
+    });
+    let mut double = DefaultTask::with_closure("double", |input, _env| {
+        Output::new(input.get_iter().next().unwrap().get::<usize>().unwrap() * 2)
+    });
+    double.set_predecessors(&[&check]);
+    let mut negate = DefaultTask::with_closure("negate", |_input, _env| Output::new(0usize));
+    negate.set_predecessors(&[&check]);
+    let mut after_negate =
+        DefaultTask::with_closure("after negate", |_input, _env| Output::empty());
+    after_negate.set_predecessors(&[&negate]);
+    let mut join = DefaultTask::with_closure("join", |input, _env| {
+        Output::new(
+            input
+                .get_iter()
+                .filter_map(|c| c.get::<usize>())
+                .sum::<usize>(),
+        )
+    });
+    join.set_predecessors(&[&double, &after_negate]);
+    join.set_trigger_rule(TriggerRule::NoneFailed);
+
+    let mut job = Dag::with_tasks(vec![check, double, negate, after_negate, join]);
+    let report = job.start_with_report().unwrap();
+    assert!(report.is_success());
+    let status = |name: &str| report.task_by_name(name).unwrap().status;
+    assert_eq!(status("double"), TaskStatus::Succeeded);
+    assert_eq!(status("negate"), TaskStatus::Bypassed);
+    assert_eq!(status("after negate"), TaskStatus::Bypassed);
+    assert_eq!(status("join"), TaskStatus::Succeeded);
+    assert_eq!(report.task_by_name("negate").unwrap().attempts, 0);
+    assert_eq!(*job.get_result::<usize>().unwrap(), 10);
+}
+
+#[test]
+fn condition_bypasses_task() {
+    let run = |production: bool| {
+        let a = DefaultTask::with_closure("a", |_input, _env| Output::new(1usize));
+        let mut deploy = DefaultTask::with_closure("deploy", |_input, _env| Output::new(2usize));
+        deploy.set_predecessors(&[&a]);
+        deploy.set_condition(|input, env| {
+            input.get_iter().count() == 1 && env.get::<bool>("production") == Some(true)
+        });
+        let mut job = Dag::with_tasks(vec![a, deploy]);
+        let mut env = EnvVar::new();
+        env.set("production", production);
+        job.set_env(env);
+        let report = job.start_with_report().unwrap();
+        assert!(report.is_success());
+        report.task_by_name("deploy").unwrap().status
+    };
+    assert_eq!(run(true), TaskStatus::Succeeded);
+    assert_eq!(run(false), TaskStatus::Bypassed);
+}
+
+#[test]
+fn trigger_rules() {
+    let failed =
+        DefaultTask::with_closure("failed", |_input, _env| Output::error("boom".to_string()));
+    let succeeded = DefaultTask::with_closure("succeeded", |_input, _env| Output::new(1usize));
+    let mut tasks = vec![];
+    for rule in [
+        TriggerRule::AllSuccess,
+        TriggerRule::AnySuccess,
+        TriggerRule::AllDone,
+        TriggerRule::NoneFailed,
+    ] {
+        let mut join = DefaultTask::with_closure(&rule.to_string(), |input, _env| {
+            Output::new(input.get_iter().count())
+        });
+        join.set_predecessors(&[&failed, &succeeded]);
+        join.set_trigger_rule(rule);
+        tasks.push(join);
+    }
+    tasks.extend([failed, succeeded]);
+    let mut job = Dag::with_tasks(tasks).keep_going();
+    let report = job.start_with_report().unwrap();
+    assert_eq!(report.outcome, DagOutcome::Failed);
+    let status = |name: &str| report.task_by_name(name).unwrap().status;
+    assert_eq!(status("all_success"), TaskStatus::Skipped);
+    assert_eq!(status("any_success"), TaskStatus::Succeeded);
+    assert_eq!(status("all_done"), TaskStatus::Succeeded);
+    assert_eq!(status("none_failed"), TaskStatus::Skipped);
+    let inputs = job.get_results::<usize>();
+    let id = report.task_by_name("all_done").unwrap().id;
+    assert_eq!(inputs[&id].as_deref(), Some(&1));
+}
+
+#[test]
+fn trigger_rule_stops_without_keep_going() {
+    let failed =
+        DefaultTask::with_closure("failed", |_input, _env| Output::error("boom".to_string()));
+    let mut cleanup = DefaultTask::with_closure("cleanup", |_input, _env| Output::empty());
+    cleanup.set_predecessors(&[&failed]);
+    cleanup.set_trigger_rule(TriggerRule::AllDone);
+    let mut job = Dag::with_tasks(vec![failed, cleanup]);
+    let report = job.start_with_report().unwrap();
+    assert_eq!(
+        report.task_by_name("cleanup").unwrap().status,
+        TaskStatus::Terminated
+    );
+}
+
+#[test]
+fn any_success_all_bypassed() {
+    let check =
+        DefaultTask::with_closure("check", |_input, _env| Output::branch(Vec::<String>::new()));
+    let mut a = DefaultTask::with_closure("a", |_input, _env| Output::empty());
+    a.set_predecessors(&[&check]);
+    let mut b = DefaultTask::with_closure("b", |_input, _env| Output::empty());
+    b.set_predecessors(&[&check]);
+    let mut join = DefaultTask::with_closure("join", |_input, _env| Output::empty());
+    join.set_predecessors(&[&a, &b]);
+    join.set_trigger_rule(TriggerRule::AnySuccess);
+    let mut job = Dag::with_tasks(vec![check, a, b, join]);
+    let report = job.start_with_report().unwrap();
+    assert!(report.is_success());
+    assert_eq!(
+        report.task_by_name("join").unwrap().status,
+        TaskStatus::Bypassed
+    );
+}
+
+fn square_tasks(n: usize) -> Vec<DefaultTask> {
+    (1..=n)
+        .map(|i| {
+            DefaultTask::with_closure(&format!("square {}", i), move |_input, _env| {
+                Output::new(i * i)
+            })
+        })
+        .collect()
+}
+
+fn sum_task(name: &str) -> DefaultTask {
+    DefaultTask::with_closure(name, |input, _env| {
+        Output::new(
+            input
+                .get_iter()
+                .filter_map(|c| c.get::<usize>())
+                .sum::<usize>(),
+        )
+    })
+}
+
+#[test]
+fn expand_and_gather() {
+    let count = DefaultTask::with_closure("count", |_input, _env| Output::new(4usize));
+    let mut list = DefaultTask::with_closure("list", |input, _env| {
+        let n = *input.get_iter().next().unwrap().get::<usize>().unwrap();
+        Output::expand(square_tasks(n))
+    });
+    list.set_predecessors(&[&count]);
+    let mut sum = sum_task("sum");
+    sum.set_predecessors(&[&list]);
+
+    let mut job = Dag::with_tasks(vec![count, list, sum]);
+    let report = job.start_with_report().unwrap();
+    assert!(report.is_success());
+    let names: Vec<&str> = report.tasks.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(
+        names,
+        ["count", "list", "square 1", "square 2", "square 3", "square 4", "sum"]
+    );
+    assert!(report
+        .tasks
+        .iter()
+        .all(|task| task.status == TaskStatus::Succeeded));
+    assert_eq!(*job.get_result::<usize>().unwrap(), 30);
+    let square = report.task_by_name("square 3").unwrap().id;
+    assert_eq!(job.get_results::<usize>()[&square].as_deref(), Some(&9));
+}
+
+#[test]
+fn expand_children_receive_parent_input() {
+    let base = DefaultTask::with_closure("base", |_input, _env| Output::new(10usize));
+    let mut list = DefaultTask::with_closure("list", |_input, _env| {
+        Output::expand((1..=2usize).map(|i| {
+            DefaultTask::with_closure(&format!("add {}", i), move |input, _env| {
+                Output::new(input.get_iter().next().unwrap().get::<usize>().unwrap() + i)
+            })
+        }))
+    });
+    list.set_predecessors(&[&base]);
+    let mut sum = sum_task("sum");
+    sum.set_predecessors(&[&list]);
+    let mut job = Dag::with_tasks(vec![base, list, sum]);
+    assert!(job.start().unwrap());
+    assert_eq!(*job.get_result::<usize>().unwrap(), 23);
+}
+
+#[test]
+fn nested_expansion() {
+    let outer = DefaultTask::with_closure("outer", |_input, _env| {
+        Output::expand((1..=2usize).map(|i| {
+            DefaultTask::with_closure(&format!("inner {}", i), move |_input, _env| {
+                Output::expand(square_tasks(i + 1).into_iter().map(move |mut task| {
+                    task.set_name(&format!("{} of inner {}", task.name(), i));
+                    task
+                }))
+            })
+        }))
+    });
+    let mut sum = sum_task("sum");
+    sum.set_predecessors(&[&outer]);
+    let mut job = Dag::with_tasks(vec![outer, sum]);
+    let report = job.start_with_report().unwrap();
+    assert!(report.is_success());
+    // 1 + 4 from the first inner task, 1 + 4 + 9 from the second.
+    assert_eq!(*job.get_result::<usize>().unwrap(), 19);
+    let names: Vec<&str> = report.tasks.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "outer",
+            "inner 1",
+            "square 1 of inner 1",
+            "square 2 of inner 1",
+            "inner 2",
+            "square 1 of inner 2",
+            "square 2 of inner 2",
+            "square 3 of inner 2",
+            "sum"
+        ]
+    );
+}
+
+#[test]
+fn expansion_child_failure() {
+    let expand = |_input: Input, _env: Arc<EnvVar>| {
+        Output::expand((1..=3usize).map(|i| {
+            DefaultTask::with_closure(&format!("child {}", i), move |_input, _env| {
+                if i == 2 {
+                    Output::error("boom".to_string())
+                } else {
+                    Output::new(i)
+                }
+            })
+        }))
+    };
+    let run = |rule: TriggerRule| {
+        let list = DefaultTask::with_closure("list", expand);
+        let mut gather = sum_task("gather");
+        gather.set_predecessors(&[&list]);
+        gather.set_trigger_rule(rule);
+        let mut job = Dag::with_tasks(vec![list, gather]).keep_going();
+        let report = job.start_with_report().unwrap();
+        assert_eq!(report.outcome, DagOutcome::Failed);
+        assert_eq!(
+            report.task_by_name("child 2").unwrap().status,
+            TaskStatus::Failed
+        );
+        (
+            report.task_by_name("gather").unwrap().status,
+            job.get_result::<usize>(),
+        )
+    };
+    assert_eq!(run(TriggerRule::AllSuccess).0, TaskStatus::Skipped);
+    let (status, result) = run(TriggerRule::AllDone);
+    assert_eq!(status, TaskStatus::Succeeded);
+    assert_eq!(*result.unwrap(), 4);
+}
+
+#[test]
+fn expansion_checks_resources() {
+    let list = DefaultTask::with_closure("list", |_input, _env| {
+        let mut child = DefaultTask::with_closure("child", |_input, _env| Output::empty());
+        child.add_resource("gpu", 2);
+        Output::expand([child])
+    });
+    let mut job = Dag::with_tasks(vec![list]);
+    job.add_resource_pool("gpu", 1);
+    let report = job.start_with_report().unwrap();
+    assert!(!report.is_success());
+    let child = report.task_by_name("child").unwrap();
+    assert_eq!(child.status, TaskStatus::Failed);
+    assert!(child.error.as_deref().unwrap().contains("gpu"));
+}
+
+#[test]
+fn expansion_reset() {
+    let list = DefaultTask::with_closure("list", |_input, _env| Output::expand(square_tasks(2)));
+    let mut sum = sum_task("sum");
+    sum.set_predecessors(&[&list]);
+    let mut job = Dag::with_tasks(vec![list, sum]).reusable();
+    for _ in 0..2 {
+        let report = job.start_with_report().unwrap();
+        assert_eq!(report.tasks.len(), 4);
+        assert_eq!(*job.get_result::<usize>().unwrap(), 5);
+    }
+}
+
+fn add_task(name: &str, n: usize) -> DefaultTask {
+    DefaultTask::with_closure(name, move |input, _env| {
+        Output::new(
+            input
+                .get_iter()
+                .filter_map(|c| c.get::<usize>())
+                .sum::<usize>()
+                + n,
+        )
+    })
+}
+
+#[test]
+fn sub_dag_task() {
+    let source = DefaultTask::with_closure("source", |_input, _env| Output::new(1usize));
+    let inner_a = add_task("inner a", 10);
+    let inner_b = add_task("inner b", 20);
+    let mut inner_join = add_task("inner join", 0);
+    inner_join.set_predecessors(&[&inner_a, &inner_b]);
+    let mut sub = SubDagTask::new("sub", Dag::with_tasks(vec![inner_a, inner_b, inner_join]));
+    sub.set_predecessors(&[&source]);
+    let mut sink = add_task("sink", 100);
+    sink.set_predecessors_by_id([sub.id()]);
+
+    let mut job = Dag::with_tasks_dyn(vec![Box::new(source), Box::new(sub), Box::new(sink)]);
+    let report = job.start_with_report().unwrap();
+    assert!(report.is_success());
+    // Both inner roots receive 1: (1 + 10) + (1 + 20), then 100 is added.
+    assert_eq!(*job.get_result::<usize>().unwrap(), 132);
+    let sub = report.task_by_name("sub").unwrap();
+    assert_eq!(sub.status, TaskStatus::Succeeded);
+    let inner = sub.sub_dag.as_ref().unwrap();
+    assert!(inner.is_success());
+    assert_eq!(inner.tasks.len(), 3);
+    assert!(report.task_by_name("source").unwrap().sub_dag.is_none());
+}
+
+#[test]
+fn sub_dag_failure() {
+    let inner_ok = DefaultTask::with_closure("inner ok", |_input, _env| Output::empty());
+    let mut inner_failed = DefaultTask::with_closure("inner failed", |_input, _env| {
+        Output::error("boom".to_string())
+    });
+    inner_failed.set_predecessors(&[&inner_ok]);
+    let sub = SubDagTask::new("sub", Dag::with_tasks(vec![inner_ok, inner_failed]));
+    let mut job = Dag::with_tasks(vec![sub]);
+    let report = job.start_with_report().unwrap();
+    assert_eq!(report.outcome, DagOutcome::Failed);
+    let sub = report.task_by_name("sub").unwrap();
+    assert_eq!(sub.status, TaskStatus::Failed);
+    assert!(sub.error.as_deref().unwrap().contains("inner failed"));
+    let inner = sub.sub_dag.as_ref().unwrap();
+    assert_eq!(
+        inner.task_by_name("inner failed").unwrap().status,
+        TaskStatus::Failed
+    );
+}
+
+#[test]
+fn sub_dag_rerun_and_env() {
+    let inner = DefaultTask::with_closure("inner", |_input, env| {
+        Output::new(env.get::<usize>("base").unwrap())
+    });
+    let mut job =
+        Dag::with_tasks(vec![SubDagTask::new("sub", Dag::with_tasks(vec![inner]))]).reusable();
+    for base in [3usize, 4] {
+        let mut env = EnvVar::new();
+        env.set("base", base);
+        job.set_env(env);
+        assert!(job.start().unwrap());
+        assert_eq!(*job.get_result::<usize>().unwrap(), base);
+    }
+}
+
+#[test]
+fn sub_dag_cancelled_with_outer_dag() {
+    let inner = DefaultTask::with_closure("inner", |_input, env| {
+        while !env.cancellation().is_cancelled() {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        Output::empty()
+    });
+    let mut job = Dag::with_tasks(vec![SubDagTask::new("sub", Dag::with_tasks(vec![inner]))]);
+    let handle = job.cancellation_handle();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        handle.cancel();
+    });
+    let started = Instant::now();
+    let report = job.start_with_report().unwrap();
+    assert_eq!(report.outcome, DagOutcome::Cancelled);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn yaml_sub_dag() {
+    let mut job = Dag::with_yaml("tests/config/sub_dag.yaml", HashMap::new()).unwrap();
+    let report = job.start_with_report().unwrap();
+    assert!(report.is_success());
+    for (name, inner) in [("Task 2", "Inner 2"), ("Task 3", "Inline 2")] {
+        let sub_dag = report.task_by_name(name).unwrap().sub_dag.as_ref().unwrap();
+        assert_eq!(
+            sub_dag.task_by_name(inner).unwrap().status,
+            TaskStatus::Succeeded
+        );
+    }
+}
+
+#[test]
+fn keyed_inputs() {
+    let fetch = DefaultTask::with_closure("fetch", |_input, _env| Output::new(2usize));
+    let label = DefaultTask::with_closure("label", |_input, _env| Output::new("x".to_string()));
+    let check = DefaultTask::with_closure("check", |_input, _env| Output::empty());
+    let fetch_id = fetch.id();
+    let mut merge = DefaultTask::with_closure("merge", move |input, _env| {
+        assert_eq!(input.get_iter().count(), 2);
+        assert_eq!(input.sources().count(), 3);
+        assert_eq!(input.get_from::<String>("label").unwrap(), "x");
+        // The type must match.
+        assert!(input.get_from::<String>("fetch").is_none());
+        assert!(input.output_of("check").unwrap().is_none());
+        assert!(input.output_of("missing").is_none());
+        Output::new(*input.get_from_id::<usize>(fetch_id).unwrap())
+    });
+    merge.set_predecessors(&[&fetch, &label, &check]);
+    let mut job = Dag::with_tasks(vec![fetch, label, check, merge]);
+    assert!(job.start().unwrap());
+    assert_eq!(*job.get_result::<usize>().unwrap(), 2);
+}
+
+#[test]
+fn keyed_inputs_of_expansion_and_sub_dag() {
+    let list = DefaultTask::with_closure("list", |_input, _env| Output::expand(square_tasks(3)));
+    let mut gather = DefaultTask::with_closure("gather", |input, _env| {
+        let names: Vec<&str> = input.sources().map(|(_, name, _)| name).collect();
+        assert_eq!(names, ["square 1", "square 2", "square 3"]);
+        Output::new(*input.get_from::<usize>("square 3").unwrap())
+    });
+    gather.set_predecessors(&[&list]);
+    let inner = DefaultTask::with_closure("inner", |input, _env| {
+        Output::new(*input.get_from::<usize>("gather").unwrap() + 1)
+    });
+    let mut sub = SubDagTask::new("sub", Dag::with_tasks(vec![inner]));
+    sub.set_predecessors(&[&gather]);
+    let mut job = Dag::with_tasks_dyn(vec![Box::new(list), Box::new(gather), Box::new(sub)]);
+    assert!(job.start().unwrap());
+    assert_eq!(*job.get_result::<usize>().unwrap(), 10);
+}
+
+#[test]
+fn typed_tasks() {
+    let a = TypedTask::source("a", |_env| 1usize);
+    let b = TypedTask::source("b", |_env| 2usize);
+    let c = TypedTask::source("c", |_env| 3usize);
+    let mut gather = TypedTask::new("gather", |values: Vec<usize>, _env| values);
+    gather.set_predecessors(&[&c as &dyn TypedOutput<usize>, &a, &b]);
+    let mut label = TypedTask::new("label", |values: Vec<usize>, _env| format!("{:?}", values));
+    label.set_predecessor(&gather);
+    let mut pair = TypedTask::new("pair", |(label, a): (String, usize), _env| {
+        format!("{} {}", label, a)
+    });
+    pair.set_predecessors(&label, &a);
+    // An untyped successor reads the output of a typed task like any other output.
+    let mut untyped = DefaultTask::with_closure("untyped", |input, _env| {
+        Output::new(input.get_from::<String>("pair").unwrap().len())
+    });
+    untyped.set_predecessors_by_id([pair.id()]);
+    let mut job = Dag::with_tasks_dyn(vec![
+        Box::new(a),
+        Box::new(b),
+        Box::new(c),
+        Box::new(gather),
+        Box::new(label),
+        Box::new(pair),
+        Box::new(untyped),
+    ]);
+    assert!(job.start().unwrap());
+    assert_eq!(*job.get_result::<usize>().unwrap(), "[3, 1, 2] 1".len());
+}
+
+#[test]
+fn typed_task_failure() {
+    let parse = TypedTask::source("parse", |_env| "x".to_string());
+    let mut number = TypedTask::fallible("number", |text: String, _env| {
+        text.parse::<usize>().map_err(|err| err.to_string())
+    });
+    number.set_predecessor(&parse);
+    let mut double = TypedTask::new("double", |n: usize, _env| n * 2);
+    double.set_predecessor(&number);
+    let mut job = Dag::with_tasks_dyn(vec![Box::new(parse), Box::new(number), Box::new(double)]);
+    let report = job.start_with_report().unwrap();
+    assert!(!report.is_success());
+    assert_eq!(report.tasks[1].status, TaskStatus::Failed);
+    assert!(report.tasks[2].status != TaskStatus::Succeeded);
+}
+
+#[test]
+fn typed_task_not_connected() {
+    let mut job = Dag::with_tasks_dyn(vec![Box::new(TypedTask::new("orphan", |n: usize, _env| n))]);
+    let report = job.start_with_report().unwrap();
+    assert_eq!(report.tasks[0].status, TaskStatus::Failed);
+}
+
+fn counted_task(name: &str, counter: &Arc<AtomicUsize>, value: usize) -> DefaultTask {
+    let counter = counter.clone();
+    let mut task = DefaultTask::with_closure(name, move |input, _env| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Output::new(
+            value
+                + input
+                    .get_iter()
+                    .filter_map(|c| c.get::<usize>())
+                    .sum::<usize>(),
+        )
+    });
+    task.set_cache_policy(CachePolicy::new());
+    task
+}
+
+#[test]
+fn cache_skips_unchanged_tasks() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let store = Arc::new(MemoryCacheStore::new());
+    let a = counted_task("a", &counter, 1);
+    let mut b = counted_task("b", &counter, 10);
+    b.set_predecessors(&[&a]);
+    let mut job = Dag::with_tasks(vec![a, b]).reusable();
+    job.set_cache_store(store.clone());
+
+    let report = job.start_with_report().unwrap();
+    assert!(report.is_success());
+    assert_eq!(counter.load(Ordering::SeqCst), 2);
+    assert_eq!(store.len(), 2);
+
+    let report = job.start_with_report().unwrap();
+    assert!(report.is_success());
+    assert_eq!(counter.load(Ordering::SeqCst), 2);
+    assert!(report.tasks.iter().all(|t| t.status == TaskStatus::Cached));
+    assert_eq!(*job.get_result::<usize>().unwrap(), 11);
+}
+
+#[test]
+fn cache_fingerprint_depends_on_inputs_and_env() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let store = Arc::new(MemoryCacheStore::new());
+    let run = |source: usize, mode: &str| {
+        let a = DefaultTask::with_closure("a", move |_input, _env| Output::new(source));
+        let mut b = counted_task("b", &counter, 0);
+        b.set_cache_policy(CachePolicy::new().with_env("mode"));
+        b.set_predecessors(&[&a]);
+        let mut job = Dag::with_tasks(vec![a, b]);
+        let mut env = EnvVar::new();
+        env.set("mode", mode.to_string());
+        job.set_env(env);
+        job.set_cache_store(store.clone());
+        let report = job.start_with_report().unwrap();
+        assert!(report.is_success());
+        report.tasks[1].status
+    };
+    assert_eq!(run(1, "debug"), TaskStatus::Succeeded);
+    assert_eq!(run(1, "debug"), TaskStatus::Cached);
+    assert_eq!(run(2, "debug"), TaskStatus::Succeeded);
+    assert_eq!(run(2, "release"), TaskStatus::Succeeded);
+    assert_eq!(run(1, "debug"), TaskStatus::Cached);
+    assert_eq!(counter.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn cache_requires_storable_values() {
+    #[derive(Debug)]
+    struct Opaque;
+
+    let counter = Arc::new(AtomicUsize::new(0));
+    let store = Arc::new(MemoryCacheStore::new());
+    let a = DefaultTask::with_closure("a", |_input, _env| Output::new(Opaque));
+    let mut b = counted_task("b", &counter, 0);
+    b.set_predecessors(&[&a]);
+    let mut c = DefaultTask::with_closure("c", |_input, _env| Output::new(Opaque));
+    c.set_cache_policy(CachePolicy::new());
+    let mut job = Dag::with_tasks(vec![a, b, c]).reusable();
+    job.set_cache_store(store.clone());
+    for _ in 0..2 {
+        let report = job.start_with_report().unwrap();
+        assert!(report
+            .tasks
+            .iter()
+            .all(|t| t.status == TaskStatus::Succeeded));
+    }
+    // The input of `b` and the output of `c` cannot be stored.
+    assert_eq!(counter.load(Ordering::SeqCst), 2);
+    assert!(store.is_empty());
+}
+
+#[test]
+fn cache_command_input_files() {
+    let dir = std::env::temp_dir().join("dagrs_cache_command_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("input.txt");
+    std::fs::write(&file, "one").unwrap();
+    let store = Arc::new(FileCacheStore::new(dir.join("cache")));
+
+    let run = || {
+        let mut action = CommandAction::new(&format!("cat {}", file.display()));
+        action.add_input_file(&file);
+        let mut task = DefaultTask::with_action("cat", action);
+        task.set_cache_policy(CachePolicy::new());
+        let mut job = Dag::with_tasks(vec![task]);
+        job.set_cache_store(store.clone());
+        let report = job.start_with_report().unwrap();
+        assert!(report.is_success());
+        let out = job.get_result::<Content>().unwrap();
+        let (stdout, _) = out.get::<(Vec<String>, Vec<String>)>().unwrap();
+        (report.tasks[0].status, stdout.clone())
+    };
+    assert_eq!(run(), (TaskStatus::Succeeded, vec!["one".to_string()]));
+    assert_eq!(run(), (TaskStatus::Cached, vec!["one".to_string()]));
+    std::fs::write(&file, "two").unwrap();
+    assert_eq!(run(), (TaskStatus::Succeeded, vec!["two".to_string()]));
+    assert_eq!(run(), (TaskStatus::Cached, vec!["two".to_string()]));
+
+    store.clear().unwrap();
+    assert_eq!(run().0, TaskStatus::Succeeded);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// A command writing `out` from `in`, in a fresh directory, with its input older than now.
+fn build_dir(name: &str) -> (std::path::PathBuf, CommandAction) {
+    let dir = std::env::temp_dir().join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("in.txt");
+    std::fs::write(&input, "source").unwrap();
+    let past = std::time::SystemTime::now() - Duration::from_secs(60);
+    std::fs::File::options()
+        .write(true)
+        .open(&input)
+        .unwrap()
+        .set_modified(past)
+        .unwrap();
+    let output = dir.join("out.txt");
+    let mut action = CommandAction::new(&format!("cp {} {}", input.display(), output.display()));
+    action.add_input_file(dir.join("in*.txt"));
+    action.add_output_file(&output);
+    (dir, action)
+}
+
+#[test]
+fn up_to_date_outputs_skip_command() {
+    let (dir, action) = build_dir("dagrs_up_to_date_test");
+    let action: Arc<dyn Complex + Send + Sync> = Arc::new(action);
+    let run = |force: bool| {
+        let task = DefaultTask::with_action_dyn("copy", action.clone());
+        let mut job = Dag::with_tasks(vec![task]);
+        if force {
+            job.force_task("copy");
+        }
+        let report = job.start_with_report().unwrap();
+        assert!(report.is_success());
+        report.tasks[0].status
+    };
+    assert_eq!(run(false), TaskStatus::Succeeded);
+    assert_eq!(run(false), TaskStatus::UpToDate);
+    assert_eq!(run(true), TaskStatus::Succeeded);
+
+    // An output older than the input is stale.
+    let older = std::time::SystemTime::now() - Duration::from_secs(120);
+    std::fs::File::options()
+        .write(true)
+        .open(dir.join("out.txt"))
+        .unwrap()
+        .set_modified(older)
+        .unwrap();
+    assert_eq!(run(false), TaskStatus::Succeeded);
+    assert_eq!(run(false), TaskStatus::UpToDate);
+
+    std::fs::remove_file(dir.join("out.txt")).unwrap();
+    assert_eq!(run(false), TaskStatus::Succeeded);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn up_to_date_task_runs_successors() {
+    let (dir, action) = build_dir("dagrs_up_to_date_successors_test");
+    let action: Arc<dyn Complex + Send + Sync> = Arc::new(action);
+    let run = || {
+        let copy = DefaultTask::with_action_dyn("copy", action.clone());
+        let mut next = DefaultTask::with_closure("next", |input, _env| {
+            Output::new(input.get_from::<Content>("copy").is_none())
+        });
+        next.set_predecessors(&[&copy]);
+        let mut job = Dag::with_tasks(vec![copy, next]);
+        let report = job.start_with_report().unwrap();
+        assert!(report.is_success());
+        let statuses: Vec<_> = report.tasks.iter().map(|task| task.status).collect();
+        (statuses, *job.get_result::<bool>().unwrap())
+    };
+    assert_eq!(
+        run(),
+        (vec![TaskStatus::Succeeded, TaskStatus::Succeeded], false)
+    );
+    assert_eq!(
+        run(),
+        (vec![TaskStatus::UpToDate, TaskStatus::Succeeded], true)
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// A task recording its name in `log` when it starts, then sleeping for `millis`.
+fn logged_task(name: &str, log: &Arc<Mutex<Vec<String>>>, millis: u64) -> DefaultTask {
+    let (log, name_owned) = (log.clone(), name.to_string());
+    DefaultTask::with_closure(name, move |_input, _env| {
+        log.lock().unwrap().push(name_owned.clone());
+        std::thread::sleep(Duration::from_millis(millis));
+        Output::empty()
+    })
+}
+
+/// The number of tasks named with `prefix` started before the task `name`.
+fn started_before(log: &[String], name: &str, prefix: &str) -> usize {
+    log.iter()
+        .take_while(|started| started.as_str() != name)
+        .filter(|started| started.starts_with(prefix))
+        .count()
+}
+
+#[test]
+fn priority_scheduling() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let tasks: Vec<_> = (0..4)
+        .map(|priority| {
+            let mut task = logged_task(&format!("p{}", priority), &log, 30);
+            task.set_priority(priority);
+            task
+        })
+        .collect();
+    let mut job = Dag::with_tasks(tasks);
+    job.set_max_parallelism(1);
+    job.set_scheduling_policy(SchedulingPolicy::Priority);
+    assert!(job.start().unwrap());
+
+    // The first task to become ready starts at once, the others wait for it by priority.
+    let log = log.lock().unwrap();
+    let expected: Vec<_> = ["p3", "p2", "p1", "p0"]
+        .into_iter()
+        .filter(|name| *name != log[0])
+        .collect();
+    assert_eq!(log[1..], expected);
+}
+
+#[test]
+fn critical_path_scheduling() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut chain: Vec<DefaultTask> = Vec::new();
+    for i in 0..3 {
+        let mut task = logged_task(&format!("a{}", i), &log, 10);
+        task.set_estimated_duration(Duration::from_secs(10));
+        if let Some(previous) = chain.last() {
+            task.set_predecessors(&[previous]);
+        }
+        chain.push(task);
+    }
+    let mut tasks = chain;
+    for i in 0..3 {
+        let mut task = logged_task(&format!("b{}", i), &log, 10);
+        task.set_estimated_duration(Duration::from_secs(1));
+        tasks.push(task);
+    }
+    let mut job = Dag::with_tasks(tasks);
+    job.set_max_parallelism(1);
+    job.set_scheduling_policy(SchedulingPolicy::CriticalPath);
+    assert!(job.start().unwrap());
+
+    // At most the first task to become ready is not on the longest chain.
+    let log = log.lock().unwrap();
+    assert!(started_before(&log, "a2", "b") <= 1, "{:?}", log);
+}
+
+#[test]
+fn critical_path_uses_previous_durations() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    // Without durations, the chain of b tasks is the longest.
+    let mut tasks: Vec<DefaultTask> = Vec::new();
+    for (prefix, len, millis) in [("a", 2, 60), ("b", 4, 1)] {
+        let start = tasks.len();
+        for i in 0..len {
+            let mut task = logged_task(&format!("{}{}", prefix, i), &log, millis);
+            if i > 0 {
+                task.set_predecessors(&[&tasks[start + i - 1]]);
+            }
+            tasks.push(task);
+        }
+    }
+    let mut job = Dag::with_tasks(tasks).reusable();
+    job.set_max_parallelism(1);
+    job.set_scheduling_policy(SchedulingPolicy::CriticalPath);
+    assert!(job.start().unwrap());
+    assert!(started_before(&log.lock().unwrap(), "b1", "a") <= 1);
+
+    // Once the durations are known, the chain of a tasks is the longest.
+    log.lock().unwrap().clear();
+    assert!(job.start().unwrap());
+    assert!(started_before(&log.lock().unwrap(), "a1", "b") <= 1);
+}
+
+#[test]
+fn serial_executor_runs_one_action_at_a_time() {
+    let (running, threads) = (
+        Arc::new(AtomicUsize::new(0)),
+        Arc::new(Mutex::new(Vec::new())),
+    );
+    let tasks: Vec<_> = (0..4)
+        .map(|i| {
+            let (running, threads) = (running.clone(), threads.clone());
+            DefaultTask::with_closure(&format!("t{}", i), move |_input, _env| {
+                assert_eq!(running.fetch_add(1, Ordering::SeqCst), 0);
+                threads.lock().unwrap().push(std::thread::current().id());
+                std::thread::sleep(Duration::from_millis(10));
+                running.fetch_sub(1, Ordering::SeqCst);
+                Output::empty()
+            })
+        })
+        .collect();
+    let mut job = Dag::with_tasks(tasks);
+    job.set_executor(Arc::new(SerialExecutor::new()));
+    assert!(job.start().unwrap());
+    let threads = threads.lock().unwrap();
+    assert_eq!(threads.len(), 4);
+    assert!(threads.iter().all(|thread| *thread == threads[0]));
+    assert_ne!(threads[0], std::thread::current().id());
+}
+
+#[test]
+fn serial_executor_reports_panics() {
+    let task = DefaultTask::with_closure("panic", |_input, _env| panic!("boom"));
+    let mut job = Dag::with_tasks(vec![task]);
+    job.set_executor(Arc::new(SerialExecutor::new()));
+    let report = job.start_with_report().unwrap();
+    assert_eq!(report.tasks[0].status, TaskStatus::Panicked);
+    assert_eq!(report.tasks[0].error.as_deref(), Some("boom"));
+}
+
+#[test]
+fn serial_executor_timeout_kills_command() {
+    let executor = Arc::new(SerialExecutor::new());
+    let mut task = DefaultTask::with_action("sleep", CommandAction::new("sleep 5"));
+    task.set_timeout(Duration::from_millis(200));
+    let start = Instant::now();
+    let mut job = Dag::with_tasks(vec![task]);
+    job.set_executor(executor.clone());
+    assert!(!job.start().unwrap());
+    // The thread of the executor is free again once the command is killed.
+    let next = DefaultTask::with_closure("next", |_input, _env| Output::empty());
+    let mut job = Dag::with_tasks(vec![next]);
+    job.set_executor(executor);
+    assert!(job.start().unwrap());
+    assert!(start.elapsed() < Duration::from_secs(3));
+}
+
+/// Counts the actions it runs on the tokio runtime.
+#[derive(Default)]
+struct CountingExecutor(AtomicUsize);
+
+impl Executor for CountingExecutor {
+    fn spawn(
+        &self,
+        task: &str,
+        action: dagrs::Action,
+        input: Input,
+        env: Arc<EnvVar>,
+    ) -> tokio::task::JoinHandle<Output> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        TokioExecutor.spawn(task, action, input, env)
+    }
+}
+
+#[test]
+fn task_executor_overrides_dag_executor() {
+    let (dag_executor, task_executor) = (
+        Arc::new(CountingExecutor::default()),
+        Arc::new(CountingExecutor::default()),
+    );
+    let mut c = TypedTask::source("c", |_env| 5usize);
+    c.set_executor(task_executor.clone());
+    let mut a = DefaultTask::with_closure("a", |input, _env| {
+        Output::new(input.get_from::<usize>("c").unwrap() + 1)
+    });
+    a.set_predecessors_by_id([c.id()]);
+    let mut b = DefaultTask::with_closure("b", |input, _env| {
+        Output::new(input.get_from::<usize>("a").unwrap() * 10)
+    });
+    b.set_predecessors(&[&a]);
+    b.set_executor(task_executor.clone());
+    let mut job = Dag::with_tasks_dyn(vec![Box::new(c), Box::new(a), Box::new(b)]);
+    job.set_executor(dag_executor.clone());
+    assert!(job.start().unwrap());
+    assert_eq!(*job.get_result::<usize>().unwrap(), 60);
+    assert_eq!(dag_executor.0.load(Ordering::SeqCst), 1);
+    assert_eq!(task_executor.0.load(Ordering::SeqCst), 2);
+}
+
+/// A dag of independent tasks and a chain, logging the order the tasks start in.
+fn deterministic_dag(log: &Arc<Mutex<Vec<String>>>, seed: u64) -> Dag<'static> {
+    let mut tasks: Vec<_> = (0..6)
+        .map(|i| logged_task(&format!("t{}", i), log, 0))
+        .collect();
+    let mut chain = logged_task("chain", log, 0);
+    chain.set_predecessors(&[&tasks[0], &tasks[3]]);
+    tasks.push(chain);
+    let mut dag = Dag::with_tasks(tasks).reusable();
+    dag.set_deterministic(seed);
+    dag
+}
+
+#[test]
+fn deterministic_execution_is_reproducible() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut orders = Vec::new();
+    for _ in 0..3 {
+        let mut dag = deterministic_dag(&log, 3);
+        for _ in 0..3 {
+            assert!(dag.start().unwrap());
+            orders.push(log.lock().unwrap().drain(..).collect::<Vec<_>>());
+        }
+    }
+    assert!(orders.iter().all(|order| order == &orders[0]));
+    assert_eq!(orders[0].last().unwrap(), "chain");
+}
+
+#[test]
+fn deterministic_seed_breaks_ties() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let orders: Vec<_> = (0..8)
+        .map(|seed| {
+            assert!(deterministic_dag(&log, seed).start().unwrap());
+            log.lock().unwrap().drain(..).collect::<Vec<_>>()
+        })
+        .collect();
+    assert!(orders.iter().any(|order| order != &orders[0]));
+}
+
+#[test]
+fn deterministic_invalid_dag() {
+    let mut job = Dag::with_yaml("tests/config/loop_error.yaml", HashMap::new()).unwrap();
+    job.set_deterministic(1);
+    assert!(matches!(job.start(), Err(DagError::LoopGraph)));
+    job.set_deterministic(2);
+    assert!(matches!(job.start(), Err(DagError::LoopGraph)));
+
+    // An initialized dag is sorted again with the seed when it starts.
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut dag = deterministic_dag(&log, 0);
+    assert!(dag.start().unwrap());
+    dag.set_deterministic(5);
+    assert!(dag.start().unwrap());
+    let order = log.lock().unwrap().split_off(7);
+    assert!(deterministic_dag(&log, 5).start().unwrap());
+    assert_eq!(log.lock().unwrap()[7..], order);
+}
+
+#[test]
+fn deterministic_execution_runs_on_one_thread() {
+    let threads = Arc::new(Mutex::new(Vec::new()));
+    let tasks: Vec<_> = (0..4)
+        .map(|i| {
+            let threads = threads.clone();
+            DefaultTask::with_closure(&format!("t{}", i), move |_input, _env| {
+                threads.lock().unwrap().push(std::thread::current().id());
+                Output::empty()
+            })
+        })
+        .collect();
+    let mut dag = Dag::with_tasks(tasks);
+    dag.set_deterministic(0);
+    assert!(dag.start().unwrap());
+    let threads = threads.lock().unwrap();
+    assert_eq!(threads.len(), 4);
+    assert!(threads.iter().all(|thread| *thread == threads[0]));
+}
@@ -0,0 +1,234 @@
+//! Tests of the serialization of contents.
+
+use std::sync::Arc;
+
+use dagrs::{
+    task::Content, Checkpointable, CommandAction, ContentRegistry, Dag, DefaultTask, EnvVar,
+    FileCheckpointStore, Output, Serializable, Task, TaskStatus, WorkerPool,
+};
+use serde::{de::DeserializeSeed, Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+impl Checkpointable for Point {
+    const TYPE_TAG: &'static str = "Point";
+
+    fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        serde_json::from_slice(bytes).ok()
+    }
+}
+
+impl Serializable for Point {}
+
+fn from_json(registry: &ContentRegistry, json: &str) -> Result<Content, serde_json::Error> {
+    registry.deserialize(&mut serde_json::Deserializer::from_str(json))
+}
+
+fn from_bincode(registry: &ContentRegistry, bytes: &[u8]) -> Result<Content, bincode::Error> {
+    registry.deserialize(&mut bincode::Deserializer::from_slice(
+        bytes,
+        bincode::DefaultOptions::new(),
+    ))
+}
+
+#[test]
+fn json_round_trip() {
+    let registry = ContentRegistry::default();
+    let json = serde_json::to_string(&Content::serializable("hello".to_string())).unwrap();
+    assert_eq!(json, r#"["String","hello"]"#);
+    let content = from_json(&registry, &json).unwrap();
+    assert_eq!(content.get::<String>().unwrap(), "hello");
+    // The decoded content can be serialized again.
+    assert_eq!(serde_json::to_string(&content).unwrap(), json);
+
+    let json = serde_json::to_string(&Content::serializable(vec!["a".to_string()])).unwrap();
+    let content = from_json(&registry, &json).unwrap();
+    assert_eq!(content.get::<Vec<String>>().unwrap(), &["a"]);
+}
+
+#[test]
+fn bincode_round_trip() {
+    use bincode::Options;
+
+    let registry = ContentRegistry::default();
+    let options = bincode::DefaultOptions::new();
+    let bytes = options.serialize(&Content::serializable(-7i64)).unwrap();
+    let content = from_bincode(&registry, &bytes).unwrap();
+    assert_eq!(content.get::<i64>(), Some(&-7));
+}
+
+#[test]
+fn registered_types() {
+    let mut registry = ContentRegistry::default();
+    let json = serde_json::to_string(&Content::serializable(Point { x: 1, y: 2 })).unwrap();
+    assert!(!registry.contains("Point"));
+    assert!(from_json(&registry, &json).is_err());
+
+    registry.register::<Point>();
+    assert!(registry.contains("Point"));
+    assert!(registry.contains("Content<Point>"));
+    let content = from_json(&registry, &json).unwrap();
+    assert_eq!(content.get::<Point>(), Some(&Point { x: 1, y: 2 }));
+
+    assert!(from_json(&ContentRegistry::empty(), r#"["usize",1]"#).is_err());
+}
+
+#[test]
+fn restored_checkpoint_is_serializable() {
+    let dir = std::env::temp_dir().join(format!("dagrs_serde_checkpoint_{}", std::process::id()));
+    let store = Arc::new(FileCheckpointStore::new(&dir));
+    let job = |a: DefaultTask| {
+        let mut b = DefaultTask::with_closure("b", |input, _env| {
+            let content = input.get_iter().next().unwrap();
+            match serde_json::to_string(content) {
+                Ok(json) => Output::new(json),
+                Err(err) => Output::error(err.to_string()),
+            }
+        });
+        b.set_predecessors(&[&a]);
+        Dag::with_tasks(vec![a, b])
+    };
+    let mut first = job(DefaultTask::with_closure("a", |_, _| Output::new(21usize)));
+    first.set_checkpoint_store(store.clone());
+    // The output of `a` was not created by `Content::serializable`.
+    assert!(!first.start().unwrap());
+
+    // The contents restored from a checkpoint can be serialized, like the ones of a registry.
+    let mut second = job(DefaultTask::with_closure("a", |_, _| -> Output {
+        unreachable!()
+    }));
+    second.resume_from(store);
+    let report = second.start_with_report().unwrap();
+    assert_eq!(
+        report.task_by_name("a").unwrap().status,
+        TaskStatus::Restored
+    );
+    assert_eq!(*second.get_result::<String>().unwrap(), r#"["usize",21]"#);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unserializable_content() {
+    let content = Content::new(3usize);
+    assert!(!content.is_serializable());
+    assert!(serde_json::to_string(&content).is_err());
+    assert!(Content::new(Content::serializable(3usize)).is_serializable());
+    assert!(!Content::new(content).is_serializable());
+}
+
+#[test]
+fn command_output_round_trip() {
+    let cmd = DefaultTask::with_action("cmd", CommandAction::new("echo hello"));
+    let mut encode = DefaultTask::with_closure("encode", |input, _env| {
+        let content = input.get_iter().next().unwrap();
+        Output::new(serde_json::to_string(content).unwrap())
+    });
+    encode.set_predecessors(&[&cmd]);
+    let mut job = Dag::with_tasks(vec![cmd, encode]);
+    assert!(job.start().unwrap());
+    let json = job.get_result::<String>().unwrap();
+    assert_eq!(
+        json.as_str(),
+        r#"["Content<(Vec<String>, Vec<String>)>",[["hello"],[]]]"#
+    );
+
+    let content = from_json(&ContentRegistry::default(), &json).unwrap();
+    let (stdout, stderr) = content
+        .get::<Content>()
+        .unwrap()
+        .get::<(Vec<String>, Vec<String>)>()
+        .unwrap();
+    assert_eq!(stdout, &["hello"]);
+    assert!(stderr.is_empty());
+}
+
+/// The tasks whose actions are executed by the worker processes.
+fn worker_tasks() -> Vec<DefaultTask> {
+    let source = DefaultTask::with_closure("source", |_input, env| {
+        Output::serializable(env.get::<usize>("base").unwrap())
+    });
+    let mut double = DefaultTask::with_closure("double", |input, _env| {
+        Output::serializable(input.get_from::<usize>("source").unwrap() * 2)
+    });
+    double.set_predecessors(&[&source]);
+    let pid = DefaultTask::with_closure("pid", |_input, _env| {
+        Output::serializable(std::process::id())
+    });
+    let panic = DefaultTask::with_closure("panic", |_input, _env| panic!("untrusted"));
+    vec![source, double, pid, panic]
+}
+
+/// The worker processes of the tests run this test binary, filtered to this test.
+#[test]
+fn worker_main() {
+    let actions = worker_tasks()
+        .iter()
+        .map(|task| (task.name().to_string(), task.action()))
+        .collect();
+    WorkerPool::serve(actions, &ContentRegistry::default());
+}
+
+fn worker_pool() -> WorkerPool {
+    WorkerPool::with_command(
+        2,
+        std::env::current_exe().unwrap(),
+        ["worker_main", "--exact", "--nocapture"],
+    )
+}
+
+#[test]
+fn worker_pool_runs_actions() {
+    let mut tasks = worker_tasks();
+    tasks.truncate(2);
+    let mut job = Dag::with_tasks(tasks);
+    let mut env = EnvVar::new();
+    env.set_serializable("base", 21usize);
+    job.set_env(env);
+    job.set_executor(Arc::new(worker_pool()));
+    assert!(job.start().unwrap());
+    assert_eq!(*job.get_result::<usize>().unwrap(), 42);
+}
+
+#[test]
+fn worker_pool_isolates_panics() {
+    let pool = Arc::new(worker_pool());
+    let mut tasks = worker_tasks();
+    let mut panic = tasks.pop().unwrap();
+    panic.set_executor(pool.clone());
+    let mut job = Dag::with_tasks(vec![panic]);
+    let report = job.start_with_report().unwrap();
+    assert_eq!(report.tasks[0].status, TaskStatus::Panicked);
+    assert!(report.tasks[0]
+        .error
+        .as_ref()
+        .unwrap()
+        .contains("untrusted"));
+
+    // The pool starts a new worker after the panic.
+    let mut pid = tasks.pop().unwrap();
+    pid.set_executor(pool);
+    let mut job = Dag::with_tasks(vec![pid]);
+    assert!(job.start().unwrap());
+    assert_ne!(*job.get_result::<u32>().unwrap(), std::process::id());
+}
+
+#[test]
+fn worker_pool_rejects_unserializable_input() {
+    let source = DefaultTask::with_closure("source", |_input, _env| Output::new(21usize));
+    let mut double = DefaultTask::new("double");
+    double.set_predecessors(&[&source]);
+    double.set_executor(Arc::new(worker_pool()));
+    let mut job = Dag::with_tasks(vec![source, double]);
+    let report = job.start_with_report().unwrap();
+    let double = report.task_by_name("double").unwrap();
+    assert_eq!(double.status, TaskStatus::Failed);
+    assert!(double.error.as_ref().unwrap().contains("cannot be sent"));
+}
@@ -1,6 +1,6 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
-use dagrs::{ParseError, Parser, Task, YamlParser};
+use dagrs::{ParseError, Parser, Task, TriggerRule, YamlParser};
 
 #[test]
 fn file_not_found_test() {
@@ -71,3 +71,149 @@ fn correct_parse() {
         YamlParser.parse_tasks("tests/config/correct.yaml", HashMap::new());
     assert!(tasks.is_ok());
 }
+
+#[test]
+fn retry_parse() {
+    let tasks = YamlParser
+        .parse_tasks("tests/config/retry.yaml", HashMap::new())
+        .unwrap();
+    assert!(tasks
+        .iter()
+        .all(|task| task.retry_policy().unwrap().max_attempts() == 2));
+}
+
+#[test]
+fn yaml_task_illegal_retry() {
+    let illegal_retry: Result<Vec<Box<dyn Task>>, ParseError> =
+        YamlParser.parse_tasks("tests/config/illegal_retry.yaml", HashMap::new());
+    assert!(illegal_retry.is_err())
+}
+
+#[test]
+fn yaml_task_zero_retry() {
+    let zero_retry: Result<Vec<Box<dyn Task>>, ParseError> =
+        YamlParser.parse_tasks("tests/config/zero_retry.yaml", HashMap::new());
+    assert!(zero_retry.is_err())
+}
+
+#[test]
+fn timeout_parse() {
+    let tasks = YamlParser
+        .parse_tasks("tests/config/timeout.yaml", HashMap::new())
+        .unwrap();
+    let timeouts: Vec<_> = tasks.iter().map(|task| task.timeout()).collect();
+    assert!(timeouts.contains(&Some(Duration::from_millis(200))));
+    assert!(timeouts.contains(&Some(Duration::from_secs(86400))));
+    assert!(timeouts.contains(&None));
+}
+
+#[test]
+fn resources_parse() {
+    let tasks = YamlParser
+        .parse_tasks("tests/config/resources.yaml", HashMap::new())
+        .unwrap();
+    let mut resources: Vec<_> = tasks.iter().flat_map(|task| task.resources()).collect();
+    resources.sort();
+    assert_eq!(
+        resources,
+        vec![
+            &("db".to_string(), 1),
+            &("db".to_string(), 1),
+            &("gpu-slot".to_string(), 2)
+        ]
+    );
+}
+
+#[test]
+fn trigger_rule_parse() {
+    let tasks = YamlParser
+        .parse_tasks("tests/config/trigger_rule.yaml", HashMap::new())
+        .unwrap();
+    let mut rules: Vec<_> = tasks
+        .iter()
+        .map(|task| (task.name().to_string(), task.trigger_rule()))
+        .collect();
+    rules.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        rules,
+        vec![
+            ("Task 1".to_string(), TriggerRule::AllSuccess),
+            ("Task 2".to_string(), TriggerRule::AllDone)
+        ]
+    );
+}
+
+#[test]
+fn yaml_task_illegal_trigger_rule() {
+    let illegal_rule: Result<Vec<Box<dyn Task>>, ParseError> =
+        YamlParser.parse_tasks("tests/config/illegal_trigger_rule.yaml", HashMap::new());
+    assert!(illegal_rule.is_err())
+}
+
+#[test]
+fn files_parse() {
+    let tasks = YamlParser
+        .parse_tasks("tests/config/files.yaml", HashMap::new())
+        .unwrap();
+    let cached: Vec<_> = tasks
+        .iter()
+        .filter(|task| task.cache_policy().is_some())
+        .map(|task| task.name())
+        .collect();
+    assert_eq!(cached, vec!["Compile"]);
+}
+
+#[test]
+fn yaml_task_illegal_files() {
+    let illegal_files: Result<Vec<Box<dyn Task>>, ParseError> =
+        YamlParser.parse_tasks("tests/config/illegal_files.yaml", HashMap::new());
+    assert!(illegal_files.is_err())
+}
+
+#[test]
+fn priority_parse() {
+    let tasks = YamlParser
+        .parse_tasks("tests/config/priority.yaml", HashMap::new())
+        .unwrap();
+    let mut priorities: Vec<_> = tasks
+        .iter()
+        .map(|task| (task.priority(), task.estimated_duration()))
+        .collect();
+    priorities.sort();
+    assert_eq!(
+        priorities,
+        vec![(-1, None), (0, None), (10, Some(Duration::from_secs(120)))]
+    );
+}
+
+#[test]
+fn yaml_task_illegal_priority() {
+    let illegal_priority: Result<Vec<Box<dyn Task>>, ParseError> =
+        YamlParser.parse_tasks("tests/config/illegal_priority.yaml", HashMap::new());
+    assert!(illegal_priority.is_err())
+}
+
+#[test]
+fn yaml_task_illegal_sub_dag() {
+    let illegal_sub_dag: Result<Vec<Box<dyn Task>>, ParseError> =
+        YamlParser.parse_tasks("tests/config/illegal_sub_dag.yaml", HashMap::new());
+    assert!(illegal_sub_dag.is_err())
+}
+
+#[test]
+fn yaml_sub_dag_include_cycle() {
+    for file in [
+        "tests/config/self_include.yaml",
+        "tests/config/include_cycle.yaml",
+    ] {
+        let err = YamlParser.parse_tasks(file, HashMap::new()).unwrap_err();
+        assert!(err.0.contains("includes itself"), "{}", err.0);
+    }
+}
+
+#[test]
+fn yaml_sub_dag_loop() {
+    let sub_dag_loop: Result<Vec<Box<dyn Task>>, ParseError> =
+        YamlParser.parse_tasks("tests/config/sub_dag_loop.yaml", HashMap::new());
+    assert!(sub_dag_loop.is_err())
+}